@@ -1,7 +1,8 @@
 use bitmask_enum::bitmask;
-use fixed::{types::extra::U12, FixedI64};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-pub type FixedPoint = FixedI64<U12>;
+pub use crate::math::FixedPoint;
+use crate::{math::fixed_serde, resources::Prefab};
 
 #[bitmask(u8)]
 #[derive(Default)]
@@ -12,8 +13,20 @@ pub enum Direction {
     Right,
 }
 
+impl Serialize for Direction {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.bits().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Direction {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u8::deserialize(deserializer).map(Direction::from)
+    }
+}
+
 #[allow(unused)]
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum Action {
     /// Indicate that we are moving in some cardinal direction
     StartMoving { dir: Direction },
@@ -21,12 +34,42 @@ pub enum Action {
     /// Indicate that we are no longer moving in some cardinal direction
     StopMoving { dir: Direction },
 
+    /// Indicate analog movement, such as from a stick, with a length of at most 1
+    MoveAnalog {
+        #[serde(with = "fixed_serde")]
+        x: FixedPoint,
+        #[serde(with = "fixed_serde")]
+        y: FixedPoint,
+    },
+
     /// Indicate the initiation of a jump
     Jump,
 
     /// Indicate the movement of the cursor
-    Cursor { x: FixedPoint, y: FixedPoint },
+    Cursor {
+        #[serde(with = "fixed_serde")]
+        x: FixedPoint,
+        #[serde(with = "fixed_serde")]
+        y: FixedPoint,
+    },
 
     /// Indicate firing a weapon/ability
     Fire,
+
+    /// Spawn an entity from a named prefab, with some of its components replaced
+    Spawn { prefab: String, overrides: Prefab },
+
+    /// Indicate that the player left, taking every entity they own with them.
+    /// Only ever made by the server.
+    Leave,
+}
+
+/// Identifies a player within a session
+pub type PlayerId = u32;
+
+/// An action along with the player who made it
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct PlayerAction {
+    pub player: PlayerId,
+    pub action: Action,
 }
@@ -0,0 +1,2 @@
+mod owner;
+pub use owner::Owner;
@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+
+use crate::{action::PlayerId, components::Reflect};
+
+/// Marks an entity as controlled by a player, whose actions only affect their own entities
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Owner {
+    pub player: PlayerId,
+}
+
+impl Component for Owner {
+    type Storage = VecStorage<Self>;
+}
+
+impl Reflect for Owner {}
@@ -1,5 +1,9 @@
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 
+use crate::components::Reflect;
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Color {
     pub red: u8,
     pub green: u8,
@@ -21,3 +25,5 @@ impl Color {
 impl Component for Color {
     type Storage = VecStorage<Self>;
 }
+
+impl Reflect for Color {}
@@ -1,5 +1,9 @@
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 
+use crate::components::Reflect;
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct DrawCircle {
     pub radius: f32,
 }
@@ -13,3 +17,13 @@ impl DrawCircle {
 impl Component for DrawCircle {
     type Storage = VecStorage<Self>;
 }
+
+impl Reflect for DrawCircle {
+    fn validate(&self) -> Result<(), String> {
+        if !(self.radius.is_finite() && self.radius > 0.) {
+            return Err(format!("radius must be positive, got {}", self.radius));
+        }
+
+        Ok(())
+    }
+}
@@ -1,2 +1,23 @@
+pub mod control;
 pub mod graphics;
 pub mod physics;
+
+mod registry;
+pub use registry::{registry, ComponentInfo, Reflect, Registry};
+
+/// The one place components are declared, which makes them usable
+/// from scenes, prefabs, snapshots and the inspector
+fn declare(registry: &mut Registry) {
+    use control::*;
+    use graphics::*;
+    use physics::*;
+
+    registry.add::<Position>("Position");
+    registry.add::<Velocity>("Velocity");
+    registry.add::<Gravity>("Gravity");
+    registry.add::<GravityEmitter>("GravityEmitter");
+    registry.add::<MovementReceiver>("MovementReceiver");
+    registry.add::<Color>("Color");
+    registry.add::<DrawCircle>("DrawCircle");
+    registry.add::<Owner>("Owner");
+}
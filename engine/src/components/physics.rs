@@ -2,7 +2,7 @@ mod gravity;
 pub use gravity::Gravity;
 
 mod gravity_emitter;
-pub use gravity_emitter::GravityEmitter;
+pub use gravity_emitter::{Falloff, GravityEmitter};
 
 mod position;
 pub use position::Position;
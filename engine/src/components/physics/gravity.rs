@@ -1,7 +1,13 @@
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 
-pub struct Gravity;
+use crate::components::Reflect;
+
+#[derive(Clone, Default, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Gravity {}
 
 impl Component for Gravity {
     type Storage = VecStorage<Self>;
 }
+
+impl Reflect for Gravity {}
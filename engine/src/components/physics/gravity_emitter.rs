@@ -1,14 +1,68 @@
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 
-#[derive(Default)]
-pub struct GravityEmitter;
+use crate::{action::FixedPoint, components::Reflect, math::fixed_serde};
+
+/// How the pull of a gravity emitter diminishes with distance
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+pub enum Falloff {
+    /// The same pull at any distance
+    Constant,
+    /// Pull proportional to 1/r
+    Inverse,
+    /// Pull proportional to 1/r², as in Newtonian gravity
+    InverseSquare,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GravityEmitter {
+    /// Scale of the pull, negative values repel
+    #[serde(with = "fixed_serde")]
+    pub strength: FixedPoint,
+
+    pub falloff: Falloff,
+
+    /// Receivers further away than this are not affected at all
+    #[serde(with = "fixed_serde::option")]
+    pub max_range: Option<FixedPoint>,
+
+    /// Added in quadrature to the distance so the pull stays finite near the emitter
+    #[serde(with = "fixed_serde")]
+    pub softening: FixedPoint,
+}
 
 impl GravityEmitter {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            strength: FixedPoint::from_num(2),
+            falloff: Falloff::Inverse,
+            max_range: None,
+            softening: FixedPoint::from_num(1),
+        }
+    }
+}
+
+impl Default for GravityEmitter {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl Component for GravityEmitter {
-    type Storage = NullStorage<Self>;
+    type Storage = VecStorage<Self>;
+}
+
+impl Reflect for GravityEmitter {
+    fn validate(&self) -> Result<(), String> {
+        if self.softening < 0 {
+            return Err("softening can't be negative".to_owned());
+        }
+
+        if self.max_range.is_some_and(|range| range <= 0) {
+            return Err("max_range must be positive".to_owned());
+        }
+
+        Ok(())
+    }
 }
@@ -1,47 +1,359 @@
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
-use web_sys::console;
 
-use crate::action::{Direction, FixedPoint};
+use crate::{
+    action::{Action, Direction, FixedPoint},
+    components::Reflect,
+    math::{fixed_serde, FixedVec2},
+};
 
-use super::Velocity;
+use super::{Position, Velocity};
 
-/// TODO Testing receiving actions to a VelocityComponent
+/// Whether a receiver moves freely in the plane or walks and jumps under gravity
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum MovementMode {
+    /// Input drives both axes
+    TopDown,
+
+    /// Input drives the horizontal axis only, with a constant downward pull and jumping
+    Platformer {
+        /// Downward speed added per tick while airborne
+        #[serde(with = "fixed_serde")]
+        gravity: FixedPoint,
+
+        /// Upward speed given by a jump
+        #[serde(with = "fixed_serde")]
+        jump_speed: FixedPoint,
+
+        /// Height of the ground, the receiver can't fall below this
+        #[serde(with = "fixed_serde")]
+        floor: FixedPoint,
+    },
+}
+
+/// How input translates into changes in velocity
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MovementModel {
+    /// Speed added per tick along the input direction
+    #[serde(with = "fixed_serde")]
+    pub acceleration: FixedPoint,
+
+    /// Input won't accelerate past this speed, or the same fraction of it as analog
+    /// input is pushed, though other forces may
+    #[serde(with = "fixed_serde")]
+    pub max_speed: FixedPoint,
+
+    /// Speed removed per tick on an axis without input
+    #[serde(with = "fixed_serde")]
+    pub friction: FixedPoint,
+
+    /// Fraction of velocity removed every tick
+    #[serde(with = "fixed_serde")]
+    pub drag: FixedPoint,
+
+    /// Scale diagonal input so it is no faster than moving along one axis
+    pub normalize_diagonal: bool,
+
+    pub mode: MovementMode,
+}
+
+impl MovementModel {
+    pub fn new() -> Self {
+        Self {
+            acceleration: FixedPoint::from_num(1),
+            max_speed: FixedPoint::from_num(5),
+            friction: FixedPoint::from_num(1),
+            drag: FixedPoint::ZERO,
+            normalize_diagonal: true,
+            mode: MovementMode::TopDown,
+        }
+    }
+}
+
+impl Default for MovementModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Moves an entity according to the movement actions it receives
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MovementReceiver {
+    /// The directions currently held
     pub direction: Direction,
+
+    /// Analog input, used while no direction is held
+    pub analog: FixedVec2,
+
+    /// Set by a jump, consumed by the next update
+    pub jump_requested: bool,
+
+    pub model: MovementModel,
 }
 
 impl MovementReceiver {
     pub fn new() -> Self {
+        Self::with_model(MovementModel::new())
+    }
+
+    pub fn with_model(model: MovementModel) -> Self {
         Self {
             direction: Direction::none(),
+            analog: FixedVec2::ZERO,
+            jump_requested: false,
+            model,
         }
     }
 }
 
+impl Default for MovementReceiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Component for MovementReceiver {
     type Storage = VecStorage<Self>;
 }
 
 impl MovementReceiver {
-    pub fn apply(&self, vel: &mut Velocity) {
-        let negative: FixedPoint = FixedPoint::from_num(-5.);
-        let positive: FixedPoint = FixedPoint::from_num(5.);
-        let zero: FixedPoint = FixedPoint::from_num(0.);
-
-        if self.direction.contains(Direction::Left) {
-            vel.vx = negative;
-        } else if self.direction.contains(Direction::Right) {
-            vel.vx = positive;
+    /// Takes a movement action, anything else is none of our business
+    pub fn receive(&mut self, action: &Action) {
+        match action {
+            Action::StartMoving { dir } => self.start_moving(*dir),
+            Action::StopMoving { dir } => self.stop_moving(*dir),
+            Action::MoveAnalog { x, y } => self.move_analog(FixedVec2::new(*x, *y)),
+            Action::Jump => self.jump(),
+            _ => {}
+        }
+    }
+
+    pub fn start_moving(&mut self, dir: Direction) {
+        // In a platformer, pressing up is how you jump
+        let pressed = dir.and(self.direction.not());
+        if pressed.contains(Direction::Up) && self.is_platformer() {
+            self.jump_requested = true;
+        }
+
+        self.direction = self.direction.or(dir);
+    }
+
+    pub fn stop_moving(&mut self, dir: Direction) {
+        self.direction = self.direction.and(dir.not());
+    }
+
+    pub fn jump(&mut self) {
+        self.jump_requested = true;
+    }
+
+    pub fn move_analog(&mut self, input: FixedVec2) {
+        // Pushing a stick up jumps too, once it's past halfway
+        let half = FixedPoint::from_num(0.5);
+        if self.is_platformer() && input.y > half && self.analog.y <= half {
+            self.jump_requested = true;
+        }
+
+        self.analog = input;
+    }
+
+    fn is_platformer(&self) -> bool {
+        matches!(self.model.mode, MovementMode::Platformer { .. })
+    }
+
+    /// The held input as a vector, each component one of -1, 0 or 1
+    fn input(&self) -> FixedVec2 {
+        let axis = |negative, positive| match (
+            self.direction.contains(negative),
+            self.direction.contains(positive),
+        ) {
+            (true, false) => -FixedPoint::ONE,
+            (false, true) => FixedPoint::ONE,
+            _ => FixedPoint::ZERO,
+        };
+
+        FixedVec2::new(
+            axis(Direction::Left, Direction::Right),
+            axis(Direction::Down, Direction::Up),
+        )
+    }
+
+    /// Applies one tick of movement, adding to whatever velocity the entity already has
+    pub fn apply(&mut self, pos: &mut Position, vel: &mut Velocity) {
+        let model = self.model;
+        let digital = !self.direction.is_none();
+
+        let mut input = if digital { self.input() } else { self.analog };
+        if self.is_platformer() {
+            input.y = FixedPoint::ZERO;
+        }
+
+        // Analog input already has a magnitude, it only needs keeping within the unit circle
+        let normalize = if digital {
+            model.normalize_diagonal
+        } else {
+            input.length() > FixedPoint::ONE
+        };
+        if normalize {
+            input = input.normalize();
+        }
+
+        // Platformers leave the vertical axis to gravity and jumping
+        if self.is_platformer() {
+            vel.vx = Self::drive(FixedVec2::new(vel.vx, FixedPoint::ZERO), input, &model).x;
         } else {
-            vel.vx = zero;
+            let driven = Self::drive(FixedVec2::new(vel.vx, vel.vy), input, &model);
+            (vel.vx, vel.vy) = (driven.x, driven.y);
+        }
+
+        vel.vx -= vel.vx.saturating_mul(model.drag);
+        vel.vy -= vel.vy.saturating_mul(model.drag);
+
+        if let MovementMode::Platformer {
+            gravity,
+            jump_speed,
+            floor,
+        } = model.mode
+        {
+            let grounded = pos.y <= floor;
+
+            if grounded {
+                pos.y = floor;
+                vel.vy = vel.vy.max(FixedPoint::ZERO);
+            }
+
+            if grounded && self.jump_requested {
+                vel.vy = vel.vy.saturating_add(jump_speed);
+            } else if !grounded {
+                vel.vy = vel.vy.saturating_sub(gravity);
+            }
+        }
+
+        self.jump_requested = false;
+    }
+
+    /// Accelerates towards the input, or slows to a stop on an axis without any.
+    /// The speed input drives up to is the length of the velocity, so moving
+    /// diagonally is no faster, and analog input pushed part way goes as far.
+    fn drive(v: FixedVec2, input: FixedVec2, model: &MovementModel) -> FixedVec2 {
+        let friction = |v: FixedPoint, input: FixedPoint| {
+            if input != 0 {
+                v
+            } else if v > 0 {
+                (v - model.friction).max(FixedPoint::ZERO)
+            } else {
+                (v + model.friction).min(FixedPoint::ZERO)
+            }
+        };
+        let v = FixedVec2::new(friction(v.x, input.x), friction(v.y, input.y));
+        if input.is_zero() {
+            return v;
         }
 
-        if self.direction.contains(Direction::Down) {
-            vel.vy = negative;
-        } else if self.direction.contains(Direction::Up) {
-            vel.vy = positive;
+        // Already going faster, from other forces, input can steer but not speed up
+        let max = model
+            .max_speed
+            .saturating_mul(input.length().min(FixedPoint::ONE));
+        let limit = max.max(v.length());
+
+        let driven = v.saturating_add(input.saturating_mul(model.acceleration));
+        if driven.length() > limit {
+            driven.normalize().saturating_mul(limit)
         } else {
-            vel.vy = zero;
+            driven
+        }
+    }
+}
+
+impl Reflect for MovementReceiver {
+    fn validate(&self) -> Result<(), String> {
+        let model = &self.model;
+
+        if model.acceleration < 0 || model.max_speed < 0 || model.friction < 0 {
+            return Err("acceleration, max_speed and friction can't be negative".to_owned());
+        }
+
+        if model.drag < 0 || model.drag > 1 {
+            return Err("drag must be between 0 and 1".to_owned());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs enough ticks for any input to reach full speed
+    fn settle(receiver: &mut MovementReceiver, vel: &mut Velocity) {
+        let mut pos = Position::new(FixedPoint::ZERO, FixedPoint::ZERO);
+        for _ in 0..20 {
+            receiver.apply(&mut pos, vel);
         }
     }
+
+    fn speed(vel: &Velocity) -> FixedPoint {
+        FixedVec2::new(vel.vx, vel.vy).length()
+    }
+
+    fn still() -> Velocity {
+        Velocity::new(FixedPoint::ZERO, FixedPoint::ZERO)
+    }
+
+    #[test]
+    fn diagonals_are_no_faster() {
+        let model = MovementModel {
+            normalize_diagonal: false,
+            ..MovementModel::new()
+        };
+        let mut receiver = MovementReceiver::with_model(model);
+        receiver.start_moving(Direction::Up | Direction::Right);
+
+        let mut vel = still();
+        settle(&mut receiver, &mut vel);
+
+        assert!(speed(&vel) <= model.max_speed);
+        assert!(speed(&vel) >= model.max_speed - FixedPoint::DELTA * 4);
+        assert_eq!(vel.vx, vel.vy);
+    }
+
+    #[test]
+    fn analog_input_caps_speed_as_far_as_it_is_pushed() {
+        let model = MovementModel::new();
+        let mut receiver = MovementReceiver::with_model(model);
+        let half = FixedPoint::from_num(0.5);
+        receiver.move_analog(FixedVec2::new(half, FixedPoint::ZERO));
+
+        let mut vel = still();
+        settle(&mut receiver, &mut vel);
+
+        assert_eq!(vel.vx, model.max_speed * half);
+        assert_eq!(vel.vy, 0);
+    }
+
+    #[test]
+    fn input_steers_but_doesnt_speed_up_past_the_max() {
+        let model = MovementModel::new();
+        let mut receiver = MovementReceiver::with_model(model);
+        receiver.start_moving(Direction::Up);
+
+        let fast = model.max_speed * 2;
+        let mut vel = Velocity::new(fast, FixedPoint::ZERO);
+        let mut pos = Position::new(FixedPoint::ZERO, FixedPoint::ZERO);
+        receiver.apply(&mut pos, &mut vel);
+
+        assert!(vel.vy > 0);
+        assert!(speed(&vel) <= fast);
+    }
+
+    #[test]
+    fn friction_stops_without_input() {
+        let mut receiver = MovementReceiver::new();
+        let mut vel = Velocity::new(FixedPoint::from_num(3), FixedPoint::from_num(-2));
+        settle(&mut receiver, &mut vel);
+
+        assert_eq!(vel, still());
+    }
 }
@@ -1,10 +1,13 @@
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 
-use crate::action::FixedPoint;
+use crate::{action::FixedPoint, components::Reflect, math::fixed_serde};
 
-#[derive(Clone)]
+#[derive(Clone, Hash, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Position {
+    #[serde(with = "fixed_serde")]
     pub x: FixedPoint,
+    #[serde(with = "fixed_serde")]
     pub y: FixedPoint,
 }
 
@@ -21,3 +24,5 @@ impl Position {
 impl Component for Position {
     type Storage = VecStorage<Self>;
 }
+
+impl Reflect for Position {}
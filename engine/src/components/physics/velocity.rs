@@ -1,9 +1,13 @@
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 
-use crate::action::FixedPoint;
+use crate::{action::FixedPoint, components::Reflect, math::fixed_serde};
 
+#[derive(Clone, Hash, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Velocity {
+    #[serde(with = "fixed_serde")]
     pub vx: FixedPoint,
+    #[serde(with = "fixed_serde")]
     pub vy: FixedPoint,
 }
 
@@ -20,3 +24,5 @@ impl Velocity {
 impl Component for Velocity {
     type Storage = VecStorage<Self>;
 }
+
+impl Reflect for Velocity {}
@@ -0,0 +1,203 @@
+use std::{any::TypeId, collections::HashMap, fmt, sync::OnceLock};
+
+use serde::{
+    de::{DeserializeOwned, Visitor},
+    forward_to_deserialize_any, Deserializer, Serialize,
+};
+use serde_json::Value;
+use specs::prelude::*;
+
+/// What a component needs so it can be handled generically through the registry
+pub trait Reflect: Component + Clone + Serialize + DeserializeOwned + Send + Sync {
+    /// Rejects values that have the right type but make no sense
+    fn validate(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Type erased access to one kind of component, by way of JSON values
+pub struct ComponentInfo {
+    pub name: &'static str,
+    pub fields: &'static [&'static str],
+
+    register: fn(&mut World),
+    read: fn(&World, Entity) -> Option<Value>,
+    check: fn(&Value) -> Result<(), String>,
+    insert: fn(&World, Entity, &Value) -> Result<(), String>,
+    insert_lazy: fn(&LazyUpdate, Entity, &Value) -> Result<(), String>,
+    remove: fn(&World, Entity),
+}
+
+impl ComponentInfo {
+    fn of<T: Reflect>(name: &'static str) -> Self
+    where
+        T::Storage: Default,
+    {
+        Self {
+            name,
+            fields: field_names::<T>(),
+            register: |world| world.register::<T>(),
+            read: |world, entity| {
+                let storage = world.read_storage::<T>();
+                serde_json::to_value(storage.get(entity)?).ok()
+            },
+            check: |value| parse::<T>(value).map(|_| ()),
+            insert: |world, entity, value| {
+                world
+                    .write_storage::<T>()
+                    .insert(entity, parse::<T>(value)?)
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            },
+            insert_lazy: |updater, entity, value| {
+                updater.insert(entity, parse::<T>(value)?);
+                Ok(())
+            },
+            remove: |world, entity| {
+                world.write_storage::<T>().remove(entity);
+            },
+        }
+    }
+
+    pub fn register(&self, world: &mut World) {
+        (self.register)(world)
+    }
+
+    /// The entity's component as JSON, if it has one
+    pub fn read(&self, world: &World, entity: Entity) -> Option<Value> {
+        (self.read)(world, entity)
+    }
+
+    /// Whether a JSON value would make a valid component
+    pub fn check(&self, value: &Value) -> Result<(), String> {
+        (self.check)(value)
+    }
+
+    /// Adds the component to an entity, replacing any it already has
+    pub fn insert(&self, world: &World, entity: Entity, value: &Value) -> Result<(), String> {
+        (self.insert)(world, entity, value)
+    }
+
+    /// As `insert`, but from within a system
+    pub fn insert_lazy(
+        &self,
+        updater: &LazyUpdate,
+        entity: Entity,
+        value: &Value,
+    ) -> Result<(), String> {
+        (self.insert_lazy)(updater, entity, value)
+    }
+
+    pub fn remove(&self, world: &World, entity: Entity) {
+        (self.remove)(world, entity)
+    }
+}
+
+fn parse<T: Reflect>(value: &Value) -> Result<T, String> {
+    let component = T::deserialize(value).map_err(|e| e.to_string())?;
+    component.validate()?;
+    Ok(component)
+}
+
+/// Every component type the engine knows about
+#[derive(Default)]
+pub struct Registry {
+    components: Vec<ComponentInfo>,
+    by_name: HashMap<&'static str, usize>,
+    by_type: HashMap<TypeId, usize>,
+}
+
+impl Registry {
+    pub fn add<T: Reflect>(&mut self, name: &'static str)
+    where
+        T::Storage: Default,
+    {
+        let index = self.components.len();
+        self.components.push(ComponentInfo::of::<T>(name));
+        self.by_name.insert(name, index);
+        self.by_type.insert(TypeId::of::<T>(), index);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ComponentInfo> {
+        Some(&self.components[*self.by_name.get(name)?])
+    }
+
+    pub fn name_of<T: Reflect>(&self) -> Option<&'static str> {
+        Some(self.components[*self.by_type.get(&TypeId::of::<T>())?].name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.components.iter().map(|info| info.name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ComponentInfo> {
+        self.components.iter()
+    }
+
+    pub fn register_all(&self, world: &mut World) {
+        for info in &self.components {
+            info.register(world);
+        }
+    }
+}
+
+/// The registry of all components, as declared in `components::declare`
+pub fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+    REGISTRY.get_or_init(|| {
+        let mut registry = Registry::default();
+        super::declare(&mut registry);
+        registry
+    })
+}
+
+/// Finds the field names of a struct by asking its `Deserialize` impl for them
+fn field_names<T: DeserializeOwned>() -> &'static [&'static str] {
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldNames(&mut fields));
+    fields
+}
+
+struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+#[derive(Debug)]
+struct Done;
+
+impl fmt::Display for Done {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("done")
+    }
+}
+
+impl std::error::Error for Done {}
+
+impl serde::de::Error for Done {
+    fn custom<T: fmt::Display>(_: T) -> Self {
+        Done
+    }
+}
+
+impl<'de, 'a> Deserializer<'de> for FieldNames<'a> {
+    type Error = Done;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Done> {
+        Err(Done)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        fields: &'static [&'static str],
+        _: V,
+    ) -> Result<V::Value, Done> {
+        *self.0 = fields;
+        Err(Done)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
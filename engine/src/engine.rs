@@ -1,141 +1,715 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
 use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, MutexGuard};
 
-use crate::components::graphics::{Color, DrawCircle};
-use crate::components::physics::{Gravity, GravityEmitter, MovementReceiver, Position, Velocity};
-use crate::input::EventQueue;
+use crate::action::Action;
+use crate::error::EngineError;
+use crate::input::{Binding, Bindings, EventQueue, TouchControls};
+use crate::inspector;
+use crate::net::{Connection, ConnectionState, NetClient, WebSocketTransport};
 use crate::renderer::init_renderer;
-use crate::resources::TickCoordinator;
+use crate::resources::{
+    ActionDelay, ActionValidator, EngineEvent, Errors, EventBus, LocalPlayer, NetStats, Prediction,
+    Prefab, Prefabs, TickCoordinator,
+};
+use crate::scene::load_scene;
+use crate::simulation;
+use crate::snapshot::Snapshot;
+use crate::state_hash::state_hash;
 use crate::systems;
 use specs::prelude::*;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
+use web_sys::console;
+
+type FrameCallback = Rc<RefCell<Option<Closure<dyn FnMut()>>>>;
+type Host = Rc<RefCell<HostCallbacks>>;
+type Net = Rc<RefCell<Option<Connection<WebSocketTransport>>>>;
 
 #[wasm_bindgen]
 pub struct Engine {
     is_running: Arc<AtomicBool>,
     world: Arc<Mutex<World>>,
+    frame: FrameLoop,
+
+    host: Host,
+    net: Net,
 }
 
-pub fn init_engine(canvas: web_sys::HtmlCanvasElement) -> Engine {
-    Engine::new(&canvas)
+/// Functions the host page registered to hear from us
+#[derive(Clone, Default)]
+struct HostCallbacks {
+    on_error: Option<js_sys::Function>,
+
+    /// Listeners for each type of `EngineEvent`
+    listeners: BTreeMap<&'static str, js_sys::Function>,
+}
+
+impl HostCallbacks {
+    /// Passes an error to the error callback, or logs it if there is none
+    fn report(&self, error: EngineError) {
+        match &self.on_error {
+            Some(callback) => {
+                let _ = callback.call1(&JsValue::NULL, &error.into());
+            }
+            None => console::error_1(&error.to_string().into()),
+        }
+    }
+
+    /// Hands each listener the events of its type, as one array per batch
+    fn deliver(&self, events: &[EngineEvent]) {
+        for (&event_type, callback) in &self.listeners {
+            let batch: Vec<_> = events
+                .iter()
+                .filter(|event| event.type_name() == event_type)
+                .collect();
+
+            if batch.is_empty() {
+                continue;
+            }
+
+            match js_sys::JSON::parse(&to_json(&batch)) {
+                Ok(batch) => {
+                    let _ = callback.call1(&JsValue::NULL, &batch);
+                }
+                Err(e) => self.report(e.into()),
+            }
+        }
+    }
 }
 
-fn window() -> web_sys::Window {
-    web_sys::window().expect("no global `window` exists")
+/// The animation frame callback, kept across stops so we can start again
+struct FrameLoop {
+    callback: FrameCallback,
+
+    /// Whether the browser will still call us, in which case the callback can't be dropped yet
+    pending: Rc<Cell<bool>>,
+
+    /// Set when the engine is gone, so a pending callback drops itself
+    released: Rc<Cell<bool>>,
 }
 
-fn request_animation_frame(f: &Closure<dyn FnMut()>) {
-    window()
-        .request_animation_frame(f.as_ref().unchecked_ref())
-        .expect("should register `requestAnimationFrame` OK");
+impl FrameLoop {
+    fn request(&self) -> Result<(), EngineError> {
+        if !self.pending.get() {
+            request_animation_frame(self.callback.borrow().as_ref().unwrap())?;
+            self.pending.set(true);
+        }
+
+        Ok(())
+    }
 }
 
-impl Engine {
-    pub fn new(canvas: &web_sys::HtmlCanvasElement) -> Self {
-        Self {
-            is_running: Arc::new(AtomicBool::new(false)),
-            world: Arc::new(Mutex::new(init_world(canvas))),
-            //event_queue: Arc::new(Mutex::new(EventQueue::new()))
-            //event_queue: EventQueue::new(),
-            //dispatcher: Arc::new(init_dispatcher())
+impl Drop for FrameLoop {
+    fn drop(&mut self) {
+        if self.pending.get() {
+            self.released.set(true);
+        } else {
+            self.callback.borrow_mut().take();
         }
     }
+}
 
-    fn tick(&self) {
-        let f = Rc::new(RefCell::new(None));
-        let g = f.clone();
+pub fn init_engine(canvas: web_sys::HtmlCanvasElement, seed: u64) -> Result<Engine, EngineError> {
+    Engine::new(&canvas, seed)
+}
 
-        let is_running = self.is_running.clone();
-        let world = self.world.clone();
-        let mut dispatcher = init_dispatcher();
-        //let systems = init_systems();
+fn window() -> Result<web_sys::Window, EngineError> {
+    web_sys::window().ok_or(EngineError::NoWindow)
+}
 
-        *g.borrow_mut() = Some(Closure::new(move || {
-            if !is_running.load(Ordering::Relaxed) {
+fn request_animation_frame(f: &Closure<dyn FnMut()>) -> Result<(), EngineError> {
+    window()?.request_animation_frame(f.as_ref().unchecked_ref())?;
+    Ok(())
+}
+
+impl Engine {
+    pub fn new(canvas: &web_sys::HtmlCanvasElement, seed: u64) -> Result<Self, EngineError> {
+        let is_running = Arc::new(AtomicBool::new(false));
+        let world = Arc::new(Mutex::new(init_world(canvas, seed)?));
+        let host = Host::default();
+        let net = Net::default();
+
+        Ok(Self {
+            frame: Self::frame(is_running.clone(), world.clone(), host.clone(), net.clone()),
+            is_running,
+            world,
+            host,
+            net,
+        })
+    }
+
+    fn frame(
+        is_running: Arc<AtomicBool>,
+        world: Arc<Mutex<World>>,
+        host: Host,
+        net: Net,
+    ) -> FrameLoop {
+        let callback: FrameCallback = Rc::new(RefCell::new(None));
+        let pending = Rc::new(Cell::new(false));
+        let released = Rc::new(Cell::new(false));
+
+        let f = callback.clone();
+        let (is_pending, is_released) = (pending.clone(), released.clone());
+        let mut input = init_input_dispatcher();
+        let mut simulation = simulation::init_dispatcher();
+        let mut rendering = init_render_dispatcher();
+
+        *callback.borrow_mut() = Some(Closure::new(move || {
+            is_pending.set(false);
+
+            if is_released.get() {
                 f.borrow_mut().take();
                 return;
             }
 
-            dispatcher.dispatch_seq(&world.lock().unwrap());
-            world.lock().unwrap().maintain();
+            if !is_running.load(Ordering::Relaxed) {
+                return;
+            }
+
+            let mut world = world.lock().unwrap();
+            let mut connection = net.borrow_mut();
+
+            if let Some(connection) = connection.as_mut() {
+                if let Err(e) = connection.receive(&mut world) {
+                    world.write_resource::<Errors>().push(e);
+                }
+            }
+
+            if let Err(e) = world.read_resource::<EventQueue>().poll_gamepads() {
+                world.write_resource::<Errors>().push(e.into());
+            }
+
+            input.dispatch_seq(&world);
+
+            // Rendering carries on while paused, only the simulation stops
+            let ticks = world.write_resource::<TickCoordinator>().ticks_this_frame();
+            for _ in 0..ticks {
+                simulation::tick(&mut simulation, &mut world);
+                if let Some(mut client) = world.try_fetch_mut::<NetClient>() {
+                    client.tick_completed(&world);
+                }
+            }
+
+            if let Some(connection) = connection.as_mut() {
+                connection.flush(&world);
+            }
+            drop(connection);
+
+            // Frames spent fast-forwarding aren't worth drawing, as nobody could follow them
+            if !world.read_resource::<TickCoordinator>().catching_up {
+                rendering.dispatch_seq(&world);
+            }
+
+            let (errors, mut events) = {
+                let (mut errors, mut bus) =
+                    world.system_data::<(WriteExpect<Errors>, WriteExpect<EventBus>)>();
+                (errors.drain().collect::<Vec<_>>(), bus.take())
+            };
+
+            // Callbacks may call back into the engine, so neither the world
+            // nor the callbacks themselves can stay borrowed
+            drop(world);
+            let host = host.borrow().clone();
 
-            request_animation_frame(f.borrow().as_ref().unwrap());
+            for error in errors {
+                events.push(EngineEvent::Error {
+                    message: error.to_string(),
+                });
+                host.report(error);
+            }
+            host.deliver(&events);
+
+            match request_animation_frame(f.borrow().as_ref().unwrap()) {
+                Ok(()) => is_pending.set(true),
+                Err(e) => {
+                    is_running.store(false, Ordering::Relaxed);
+                    host.report(e);
+                }
+            }
         }));
 
-        request_animation_frame(g.borrow().as_ref().unwrap());
+        FrameLoop {
+            callback,
+            pending,
+            released,
+        }
     }
 }
 
 // The JavaScript interface
 #[wasm_bindgen]
 impl Engine {
-    pub fn start(&mut self) {
+    pub fn start(&mut self) -> Result<(), JsValue> {
         let was_running = self.is_running.fetch_or(true, Ordering::Relaxed);
         if !was_running {
-            self.tick();
+            if let Err(e) = self.frame.request() {
+                self.is_running.store(false, Ordering::Relaxed);
+                return Err(e.into());
+            }
         }
+
+        Ok(())
     }
 
+    /// Sets the function errors raised while running are passed to,
+    /// without one they are logged to the console
+    pub fn on_error(&self, callback: Option<js_sys::Function>) {
+        self.host.borrow_mut().on_error = callback;
+    }
+
+    /// Sets the function called after each frame with an array of that frame's events
+    /// of the given type, one of `tick_completed`, `entity_spawned`, `entity_despawned`,
+    /// `collision`, `desync`, `error`, `action_rejected`, `connection_changed`,
+    /// `lobby_changed`, `refused`, `catching_up` or `caught_up`. Passing nothing removes it.
+    pub fn on(&self, event_type: &str, callback: Option<js_sys::Function>) -> Result<(), JsValue> {
+        let event_type = EngineEvent::TYPES
+            .iter()
+            .find(|&&name| name == event_type)
+            .ok_or_else(|| format!("Unknown event type {event_type}"))?;
+
+        let mut host = self.host.borrow_mut();
+        match callback {
+            Some(callback) => host.listeners.insert(event_type, callback),
+            None => host.listeners.remove(event_type),
+        };
+
+        Ok(())
+    }
+
+    /// Stops the frame loop entirely, see `pause` to keep rendering
     pub fn stop(&mut self) {
         self.is_running.fetch_and(false, Ordering::Relaxed);
     }
-}
 
-fn init_world(canvas: &web_sys::HtmlCanvasElement) -> World {
-    let mut world = World::new();
-    world.register::<Position>();
-    world.register::<Velocity>();
-    world.register::<Gravity>();
-    world.register::<Color>();
-    world.register::<DrawCircle>();
-    world.register::<GravityEmitter>();
-    world.register::<MovementReceiver>();
+    /// Binds a key, as named by `KeyboardEvent.key`, to one of `up`, `down`,
+    /// `left`, `right`, `jump` or `fire`
+    pub fn bind_key(&self, key: &str, binding: &str) -> Result<(), JsValue> {
+        let binding = parse_binding(binding)?;
+        let world = self.world.lock().unwrap();
+        world.write_resource::<Bindings>().bind_key(key, binding);
 
-    // Some test entities that are affected by gravity
-    for x in 0..8 {
+        Ok(())
+    }
+
+    /// As `bind_key`, for a button by index in the standard gamepad layout
+    pub fn bind_button(&self, button: u32, binding: &str) -> Result<(), JsValue> {
+        let binding = parse_binding(binding)?;
+        let world = self.world.lock().unwrap();
         world
-            .create_entity()
-            .with(Position::new_f32(-32. * x as f32, -8.))
-            .with(Velocity::new_f32(1., -1.))
-            .with(Color::new(20 * x as u8, 255 - 16 * x as u8, 0, 255))
-            .with(DrawCircle::new(16.))
-            .with(Gravity)
-            .build();
-    }
-
-    // A movable gravity emitter
-    world
-        .create_entity()
-        .with(Position::new_f32(0., 0.))
-        .with(Velocity::new_f32(0., 0.))
-        .with(GravityEmitter::new())
-        .with(MovementReceiver::new())
-        .with(Color::new(0, 0, 0, 255))
-        .with(DrawCircle::new(8.))
-        .build();
-
-    // Add resources
-    world.insert(init_renderer(canvas).unwrap());
+            .write_resource::<Bindings>()
+            .bind_button(button, binding);
+
+        Ok(())
+    }
+
+    pub fn set_gamepad_deadzone(&self, deadzone: f32) {
+        let world = self.world.lock().unwrap();
+        world
+            .read_resource::<EventQueue>()
+            .set_gamepad_deadzone(deadzone);
+    }
+
+    /// Freezes the simulation while still rendering
+    pub fn pause(&self) {
+        self.with_tick_coordinator(|tc| tc.pause());
+    }
+
+    pub fn resume(&self) {
+        self.with_tick_coordinator(|tc| tc.resume());
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.with_tick_coordinator(|tc| tc.paused)
+    }
+
+    /// Pauses if needed, and runs exactly one tick on the next frame
+    pub fn step(&self) {
+        self.with_tick_coordinator(|tc| tc.step());
+    }
+
+    /// Ticks to run per frame, e.g. 4 to fast-forward or 0.5 for slow motion
+    pub fn set_time_scale(&self, scale: f32) {
+        self.with_tick_coordinator(|tc| tc.set_time_scale(scale));
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        self.with_tick_coordinator(|tc| tc.time_scale)
+    }
+
+    pub fn current_tick(&self) -> usize {
+        self.with_tick_coordinator(|tc| tc.current_tick)
+    }
+
+    /// Feeds a measured round trip time to a peer, in milliseconds,
+    /// into the delay our actions are scheduled with
+    pub fn record_round_trip(&self, rtt: f64) {
+        let world = self.world.lock().unwrap();
+        world.write_resource::<ActionDelay>().record_round_trip(rtt);
+    }
+
+    /// Ticks between an input and the tick its actions apply on
+    pub fn action_delay(&self) -> usize {
+        self.with_action_delay(|delay| delay.ticks)
+    }
+
+    /// Smoothed round trip time in milliseconds, as used for `action_delay`
+    pub fn round_trip_time(&self) -> f64 {
+        self.with_action_delay(|delay| delay.round_trip)
+    }
+
+    /// Smoothed round trip time deviation in milliseconds, as used for `action_delay`
+    pub fn jitter(&self) -> f64 {
+        self.with_action_delay(|delay| delay.jitter)
+    }
+
+    /// Smoothed milliseconds inputs waited for the frame that handled them
+    pub fn input_age(&self) -> f64 {
+        self.with_action_delay(|delay| delay.input_age)
+    }
+
+    /// Draws the entity we move where our latest input will take it, instead of
+    /// waiting for the action delay. Purely visual, the simulation is unaffected.
+    pub fn set_prediction(&self, enabled: bool) {
+        let world = self.world.lock().unwrap();
+        world.write_resource::<Prediction>().set_enabled(enabled);
+    }
+
+    /// How many ticks the simulation ended up somewhere other than predicted
+    pub fn mispredictions(&self) -> usize {
+        let world = self.world.lock().unwrap();
+        let mispredictions = world.read_resource::<Prediction>().mispredictions;
+        mispredictions
+    }
+
+    /// Joins the session hosted at `url` as `name`, replacing the world with the
+    /// server's once it lets us in. Reconnects by itself if the connection drops.
+    pub fn connect(&self, url: &str, name: &str) -> Result<(), JsValue> {
+        let transport = WebSocketTransport::open(url)?;
+
+        self.disconnect();
+        let mut world = self.world.lock().unwrap();
+        world.insert(NetClient::new(name));
+        world.write_resource::<TickCoordinator>().hold();
+        world.write_resource::<NetStats>().reset();
+
+        *self.net.borrow_mut() = Some(Connection::new(transport));
+
+        Ok(())
+    }
+
+    /// Leaves the session, carrying on offline from wherever it was
+    pub fn disconnect(&self) {
+        if self.net.borrow_mut().take().is_none() {
+            return;
+        }
+
+        let mut world = self.world.lock().unwrap();
+        world.remove::<NetClient>();
+        world.write_resource::<TickCoordinator>().release();
+    }
+
+    /// Tells the lobby whether we're ready for the game to start
+    pub fn set_ready(&self, ready: bool) {
+        self.with_net_client(|client| client.set_ready(ready));
+    }
+
+    /// Asks the server to throw a player out, which only works for the host
+    pub fn kick(&self, player: u32) {
+        self.with_net_client(|client| client.kick(player));
+    }
+
+    /// JSON of who is in the lobby and whether the game started, if we're in one
+    pub fn lobby(&self) -> Option<String> {
+        let world = self.world.lock().unwrap();
+        let lobby = world
+            .try_fetch::<NetClient>()
+            .and_then(|client| client.lobby.as_ref().map(to_json));
+        lobby
+    }
+
+    /// Estimated server time minus ours in milliseconds, 0 while offline
+    pub fn clock_offset(&self) -> f64 {
+        let world = self.world.lock().unwrap();
+        let offset = world
+            .try_fetch::<NetClient>()
+            .map_or(0., |client| client.clock.offset);
+        offset
+    }
+
+    /// How fast the simulation runs to keep pace with the server, 1 being normal speed
+    pub fn tick_rate(&self) -> f32 {
+        self.with_tick_coordinator(|tc| tc.rate)
+    }
+
+    /// Whether we joined a session already going, and are still fast-forwarding to where it is at
+    pub fn is_catching_up(&self) -> bool {
+        self.with_tick_coordinator(|tc| tc.catching_up)
+    }
+
+    /// How the server keeps us up to date, `lockstep` or `state`, as it told us when letting us in
+    pub fn sync_mode(&self) -> Option<String> {
+        let world = self.world.lock().unwrap();
+        let mode = world
+            .try_fetch::<NetClient>()
+            .filter(|client| client.player.is_some())
+            .map(|client| client.sync.name().to_owned());
+        mode
+    }
+
+    /// JSON of how the latest connection is doing: round trip time, jitter and
+    /// stalled time in milliseconds, ping loss from 0 to 1, bytes in and out,
+    /// ticks ahead of the simulation and how many stalls there were
+    pub fn net_stats(&self) -> String {
+        let world = self.world.lock().unwrap();
+        let stats = to_json(&*world.read_resource::<NetStats>());
+        stats
+    }
+
+    /// Draws a graph of the connection over the game: round trip time in blue,
+    /// jitter in yellow, ticks ahead in green, bytes received in white and stalls in red
+    pub fn set_net_overlay(&self, enabled: bool) {
+        let world = self.world.lock().unwrap();
+        world.write_resource::<NetStats>().overlay = enabled;
+    }
+
+    /// One of `connecting`, `open`, `reconnecting` or `closed`
+    pub fn connection_state(&self) -> String {
+        let state = match &*self.net.borrow() {
+            Some(connection) => connection.state(),
+            None => ConnectionState::Closed,
+        };
+
+        state.name().to_owned()
+    }
+
+    /// JSON counts of accepted actions and of rejected ones by reason
+    pub fn validation_stats(&self) -> String {
+        let world = self.world.lock().unwrap();
+        let stats = to_json(&world.read_resource::<ActionValidator>().stats);
+        stats
+    }
+
+    /// JSON list of the most recently rejected actions, oldest first
+    pub fn recent_rejections(&self) -> String {
+        let world = self.world.lock().unwrap();
+        let recent = to_json(&world.read_resource::<ActionValidator>().recent);
+        recent
+    }
+
+    /// Schedules spawning the named prefab on the next tick, or while online asks
+    /// the server to. `overrides` is an optional JSON object of component values
+    /// to use instead of the prefab's.
+    pub fn spawn_prefab(&self, name: &str, overrides: Option<String>) -> Result<(), JsValue> {
+        let overrides = match overrides {
+            Some(json) => serde_json::from_str(&json).map_err(|e| e.to_string())?,
+            None => Prefab::default(),
+        };
+
+        let world = self.world.lock().unwrap();
+        // Overrides were checked as they were parsed
+        if world.read_resource::<Prefabs>().get(name).is_none() {
+            return Err(format!("No prefab named {name}").into());
+        }
+
+        let action = Action::Spawn {
+            prefab: name.to_owned(),
+            overrides,
+        };
+
+        // Everyone has to spawn it, so it goes the way our input does
+        if let Some(mut client) = world.try_fetch_mut::<NetClient>() {
+            if !client.can_act() {
+                return Err("Can't spawn until in the session".into());
+            }
+
+            let current_tick = world.read_resource::<TickCoordinator>().current_tick;
+            let tick = world
+                .read_resource::<ActionDelay>()
+                .target_tick(current_tick);
+            client.send_actions(tick, vec![action]);
+            return Ok(());
+        }
+
+        // The host page is trusted, so this skips validation
+        let player = world.read_resource::<LocalPlayer>().0;
+        let mut tc = world.write_resource::<TickCoordinator>();
+        let tick = tc.current_tick + 1;
+        tc.enqueue_action(player, action, tick)?;
+
+        Ok(())
+    }
+
+    /// Replaces the contents of the world with a scene, given as JSON. Only while offline.
+    pub fn load_scene(&self, json: &str) -> Result<(), JsValue> {
+        let mut world = self.offline_world("Loading a scene")?;
+        load_scene(&mut world, json).map_err(|e| e.to_string().into())
+    }
+
+    /// The whole simulation state as JSON
+    pub fn snapshot(&self) -> String {
+        to_json(&Snapshot::take(&self.world.lock().unwrap()))
+    }
+
+    /// Replaces the simulation state with one from `snapshot`. Only while offline.
+    pub fn restore_snapshot(&self, json: &str) -> Result<(), JsValue> {
+        let snapshot: Snapshot = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let mut world = self.offline_world("Restoring a snapshot")?;
+        snapshot.restore(&mut world);
+
+        Ok(())
+    }
+
+    /// Hash of the simulation state, for comparing against other peers
+    pub fn state_hash(&self) -> u64 {
+        state_hash(&self.world.lock().unwrap())
+    }
+
+    /// Compares a peer's hash for the current tick with ours, raising a `desync` event if they differ
+    pub fn verify_state_hash(&self, remote: u64) -> bool {
+        let world = self.world.lock().unwrap();
+        let local = state_hash(&world);
+
+        if local != remote {
+            let tick = world.read_resource::<TickCoordinator>().current_tick;
+            world
+                .write_resource::<EventBus>()
+                .push(EngineEvent::Desync {
+                    tick,
+                    local,
+                    remote,
+                });
+        }
+
+        local == remote
+    }
+}
+
+impl Engine {
+    fn with_tick_coordinator<R>(&self, f: impl FnOnce(&mut TickCoordinator) -> R) -> R {
+        f(&mut self.world.lock().unwrap().write_resource())
+    }
+
+    fn with_action_delay<R>(&self, f: impl FnOnce(&ActionDelay) -> R) -> R {
+        f(&self.world.lock().unwrap().read_resource())
+    }
+
+    /// The world, unless connected to a server, which alone decides what is in it
+    fn offline_world(&self, what: &'static str) -> Result<MutexGuard<'_, World>, EngineError> {
+        let world = self.world.lock().unwrap();
+        if world.has_value::<NetClient>() {
+            return Err(EngineError::Online(what));
+        }
+
+        Ok(world)
+    }
+
+    /// Does nothing while offline
+    fn with_net_client(&self, f: impl FnOnce(&mut NetClient)) {
+        let world = self.world.lock().unwrap();
+        if let Some(mut client) = world.try_fetch_mut::<NetClient>() {
+            f(&mut client);
+        };
+    }
+}
+
+// The debug inspector. Changes made here bypass the action queue and would only
+// affect this peer, so they can only be made offline.
+#[wasm_bindgen]
+impl Engine {
+    /// JSON list of every component type, with its fields
+    pub fn component_types(&self) -> String {
+        to_json(&inspector::component_types())
+    }
+
+    /// JSON list of every entity, with the names of its components
+    pub fn entities(&self) -> String {
+        to_json(&inspector::list_entities(&self.world.lock().unwrap()))
+    }
+
+    /// JSON object of all of an entity's components
+    pub fn components(&self, id: u32) -> Result<String, JsValue> {
+        let components = inspector::components(&self.world.lock().unwrap(), id)?;
+        Ok(to_json(&components))
+    }
+
+    /// One component of an entity as JSON, or nothing if it doesn't have it
+    pub fn component(&self, id: u32, name: &str) -> Result<Option<String>, JsValue> {
+        let component = inspector::component(&self.world.lock().unwrap(), id, name)?;
+        Ok(component.map(|value| to_json(&value)))
+    }
+
+    pub fn set_component(&self, id: u32, name: &str, json: &str) -> Result<(), JsValue> {
+        let value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let world = self.offline_world("Editing")?;
+        inspector::set_component(&world, id, name, &value)?;
+
+        Ok(())
+    }
+
+    pub fn remove_component(&self, id: u32, name: &str) -> Result<(), JsValue> {
+        let world = self.offline_world("Editing")?;
+        inspector::remove_component(&world, id, name)?;
+
+        Ok(())
+    }
+
+    /// Creates an entity right away from a JSON object of components, returning its id
+    pub fn spawn(&self, json: &str) -> Result<u32, JsValue> {
+        let components: Prefab = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let mut world = self.offline_world("Spawning from the inspector")?;
+        Ok(inspector::spawn(&mut world, &components))
+    }
+
+    pub fn despawn(&self, id: u32) -> Result<(), JsValue> {
+        let mut world = self.offline_world("Despawning from the inspector")?;
+        inspector::despawn(&mut world, id)?;
+
+        Ok(())
+    }
+}
+
+fn parse_binding(name: &str) -> Result<Binding, JsValue> {
+    Binding::from_name(name).ok_or_else(|| format!("Unknown binding {name}").into())
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+fn init_world(canvas: &web_sys::HtmlCanvasElement, seed: u64) -> Result<World, EngineError> {
+    let mut world = simulation::init_world(seed)?;
+
+    // Add the resources that need a browser
+    world.insert(init_renderer(canvas)?);
     let event_queue = EventQueue::new();
-    event_queue.attach(canvas);
+    event_queue.attach(canvas)?;
     world.insert(event_queue);
-    world.insert(TickCoordinator::new());
+    world.insert(Bindings::new());
+    world.insert(TouchControls::new(canvas.width() as f32));
+
+    // Only ever drawn, so kept out of the simulation
+    world.insert(Prediction::new());
+    world.insert(NetStats::new());
 
-    world
+    Ok(world)
 }
 
-fn init_dispatcher() -> Dispatcher<'static, 'static> {
+/// Systems that turn input into actions, once per frame before any ticks
+fn init_input_dispatcher() -> Dispatcher<'static, 'static> {
     DispatcherBuilder::new()
-        // Register systems
         .with(systems::SysInput, "Input", &[])
-        .with(systems::SysMovementReceiver, "MovementReceiver", &[])
-        .with(systems::SysFireReceiver, "FireReceiver", &[])
-        .with(systems::SysMovement, "Movement", &["MovementReceiver"])
-        .with(systems::SysGravity, "Gravity", &[])
-        .with(systems::SysRenderer, "Renderer", &[])
-        .with(systems::SysTickCoordinator, "TickCoordinator", &[])
+        .build()
+}
+
+/// Systems that run once per frame, however many ticks it had
+fn init_render_dispatcher() -> Dispatcher<'static, 'static> {
+    DispatcherBuilder::new()
+        .with(systems::SysPrediction, "Prediction", &[])
+        .with(systems::SysRenderer, "Renderer", &["Prediction"])
+        .with(systems::SysTouchOverlay, "TouchOverlay", &["Renderer"])
+        .with(systems::SysNetOverlay, "NetOverlay", &["Renderer"])
         .build()
 }
@@ -0,0 +1,99 @@
+//! The error type shared by the whole crate.
+
+use std::fmt;
+
+use wasm_bindgen::JsValue;
+
+use crate::scene::SceneError;
+
+#[derive(Debug)]
+pub enum EngineError {
+    /// There is no global `window`, e.g. when running in a worker
+    NoWindow,
+
+    /// The browser or device doesn't support WebGL2
+    WebGl2Unavailable,
+
+    /// Setting up the renderer failed, e.g. a shader didn't compile
+    Renderer(String),
+
+    /// A call into JavaScript threw
+    Js(String),
+
+    Scene(SceneError),
+
+    /// An action was scheduled for a tick outside the action queue's window
+    TickOutOfWindow {
+        tick: usize,
+        current_tick: usize,
+    },
+
+    /// The simulation tried to advance beyond the last tick we have actions for
+    PastHorizon {
+        tick: usize,
+    },
+
+    /// A tick bundle arrived other than right after the last one
+    BundleOutOfOrder {
+        tick: usize,
+        expected: usize,
+    },
+
+    /// A network message couldn't be understood, or written
+    Protocol(String),
+
+    /// A component couldn't be turned into its JSON value
+    Component(String),
+
+    /// Something that would change the world on this peer alone was tried while online
+    Online(&'static str),
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoWindow => write!(f, "No global `window` exists"),
+            Self::WebGl2Unavailable => write!(f, "WebGL2 is not available"),
+            Self::Renderer(message) => write!(f, "Renderer setup failed: {message}"),
+            Self::Js(message) => write!(f, "JavaScript error: {message}"),
+            Self::Scene(e) => write!(f, "Invalid scene at {e}"),
+            Self::TickOutOfWindow { tick, current_tick } => write!(
+                f,
+                "Can't schedule an action at tick {tick}, currently at {current_tick}"
+            ),
+            Self::PastHorizon { tick } => write!(
+                f,
+                "Attempted to advance past the action horizon at tick {tick}"
+            ),
+            Self::BundleOutOfOrder { tick, expected } => {
+                write!(
+                    f,
+                    "Received the bundle for tick {tick}, expected {expected}"
+                )
+            }
+            Self::Protocol(message) => write!(f, "Bad network message: {message}"),
+            Self::Component(message) => write!(f, "Bad component: {message}"),
+            Self::Online(what) => write!(f, "{what} is only possible while offline"),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+impl From<SceneError> for EngineError {
+    fn from(e: SceneError) -> Self {
+        Self::Scene(e)
+    }
+}
+
+impl From<JsValue> for EngineError {
+    fn from(value: JsValue) -> Self {
+        Self::Js(value.as_string().unwrap_or_else(|| format!("{value:?}")))
+    }
+}
+
+impl From<EngineError> for JsValue {
+    fn from(e: EngineError) -> Self {
+        js_sys::Error::new(&e.to_string()).into()
+    }
+}
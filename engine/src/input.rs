@@ -4,25 +4,86 @@ use std::{
 };
 
 use wasm_bindgen::{convert::FromWasmAbi, prelude::*};
-use web_sys::{EventTarget, HtmlCanvasElement};
+use web_sys::{Event, EventTarget, HtmlCanvasElement, TouchEvent};
+
+mod bindings;
+pub use bindings::{Binding, Bindings};
+
+mod gamepad;
+use gamepad::GamepadPoller;
+
+use crate::utils::now;
+
+mod touch;
+pub use touch::TouchControls;
 
 #[allow(unused)]
 pub enum InputEvent {
     // Keyboard
-    KeyDown { key: String },
-    KeyUp { key: String },
+    KeyDown {
+        key: String,
+    },
+    KeyUp {
+        key: String,
+    },
 
     // Mouse
-    MouseMove { x: i32, y: i32 },
-    MouseDown { button: u8 },
-    MouseUp { button: u8 },
+    MouseMove {
+        x: i32,
+        y: i32,
+    },
+    MouseDown {
+        button: u8,
+    },
+    MouseUp {
+        button: u8,
+    },
+
+    // Gamepad, with buttons by index in the standard gamepad layout
+    GamepadButtonDown {
+        pad: u32,
+        button: u32,
+    },
+    GamepadButtonUp {
+        pad: u32,
+        button: u32,
+    },
+
+    /// Left stick position after the deadzone, each axis in -1..=1 with y pointing down
+    GamepadStick {
+        pad: u32,
+        x: f32,
+        y: f32,
+    },
+
+    // Touch, tracked by identifier, in canvas pixels with y pointing up
+    TouchStart {
+        id: i32,
+        x: f32,
+        y: f32,
+    },
+    TouchMove {
+        id: i32,
+        x: f32,
+        y: f32,
+    },
+    TouchEnd {
+        id: i32,
+    },
+}
+
+/// An input event along with when it happened, in milliseconds as given by `utils::now`
+pub struct TimedInput {
+    pub time: f64,
+    pub event: InputEvent,
 }
 
 /// Structure to forward events from JS-land to Rust-land
 #[wasm_bindgen]
 #[derive(Default)]
 pub struct EventQueue {
-    queue: Arc<Mutex<VecDeque<InputEvent>>>,
+    queue: Arc<Mutex<VecDeque<TimedInput>>>,
+    gamepads: Arc<Mutex<GamepadPoller>>,
 }
 
 #[wasm_bindgen]
@@ -30,10 +91,15 @@ impl EventQueue {
     pub fn new() -> Self {
         Self {
             queue: Arc::new(Mutex::new(VecDeque::new())),
+            gamepads: Arc::new(Mutex::new(GamepadPoller::new())),
         }
     }
 
-    pub fn attach(&self, el: &HtmlCanvasElement) {
+    pub fn attach(&self, el: &HtmlCanvasElement) -> Result<(), JsValue> {
+        let document = el
+            .owner_document()
+            .ok_or_else(|| JsValue::from_str("Canvas is not in a document"))?;
+
         // TODO probably some great leaking going on here
         // self.listen(el, "mousemove", |queue, event: web_sys::MouseEvent| {
         //     queue.lock().unwrap().push_back(InputEvent::MouseMove {
@@ -42,51 +108,100 @@ impl EventQueue {
         //     });
         // });
 
-        self.listen(
-            &el.owner_document().unwrap(),
-            "keydown",
-            |queue, event: web_sys::KeyboardEvent| {
-                queue
-                    .lock()
-                    .unwrap()
-                    .push_back(InputEvent::KeyDown { key: event.key() });
-            },
-        );
-
-        self.listen(
-            &el.owner_document().unwrap(),
-            "keyup",
-            |queue, event: web_sys::KeyboardEvent| {
-                queue
-                    .lock()
-                    .unwrap()
-                    .push_back(InputEvent::KeyUp { key: event.key() });
-            },
-        );
+        self.listen(&document, "keydown", |event: &web_sys::KeyboardEvent| {
+            Some(InputEvent::KeyDown { key: event.key() })
+        })?;
+
+        self.listen(&document, "keyup", |event: &web_sys::KeyboardEvent| {
+            Some(InputEvent::KeyUp { key: event.key() })
+        })?;
+
+        for event_type in ["touchstart", "touchmove", "touchend", "touchcancel"] {
+            let canvas = el.clone();
+            self.listen(el, event_type, move |event: &TouchEvent| {
+                // Keep the browser from scrolling or zooming instead
+                event.prevent_default();
+
+                changed_touches(&canvas, event)
+                    .into_iter()
+                    .map(|(id, x, y)| match event_type {
+                        "touchstart" => InputEvent::TouchStart { id, x, y },
+                        "touchmove" => InputEvent::TouchMove { id, x, y },
+                        _ => InputEvent::TouchEnd { id },
+                    })
+                    .collect::<Vec<_>>()
+            })?;
+        }
+
+        Ok(())
     }
 
-    fn listen<
-        T: FromWasmAbi + 'static,
-        F: FnMut(&Arc<Mutex<VecDeque<InputEvent>>>, T) + 'static,
-    >(
-        &self,
-        el: &EventTarget,
-        event: &str,
-        mut cb: F,
-    ) {
+    /// Queues the input events `cb` makes of each DOM event, stamped with when it happened
+    fn listen<T, F, I>(&self, el: &EventTarget, event: &str, mut cb: F) -> Result<(), JsValue>
+    where
+        T: FromWasmAbi + AsRef<Event> + 'static,
+        F: FnMut(&T) -> I + 'static,
+        I: IntoIterator<Item = InputEvent>,
+    {
         let queue = self.queue.clone();
 
         let closure = Closure::<dyn FnMut(_)>::new(move |event: T| {
-            cb(&queue, event);
+            let time = event.as_ref().time_stamp();
+            queue.lock().unwrap().extend(
+                cb(&event)
+                    .into_iter()
+                    .map(|event| TimedInput { time, event }),
+            );
         });
-        el.add_event_listener_with_callback(event, closure.as_ref().unchecked_ref())
-            .unwrap();
+        el.add_event_listener_with_callback(event, closure.as_ref().unchecked_ref())?;
         closure.forget();
+
+        Ok(())
     }
 }
 
 impl EventQueue {
-    pub fn items(&self) -> &Arc<Mutex<VecDeque<InputEvent>>> {
+    pub fn items(&self) -> &Arc<Mutex<VecDeque<TimedInput>>> {
         &self.queue
     }
+
+    /// Gamepads have no events of their own, so this should be called every frame
+    pub fn poll_gamepads(&self) -> Result<(), JsValue> {
+        let window = web_sys::window().ok_or_else(|| JsValue::from_str("No window"))?;
+        let events = self.gamepads.lock().unwrap().poll(&window.navigator())?;
+
+        // Polling only tells us something changed since the last frame, not when
+        let time = now();
+        self.queue
+            .lock()
+            .unwrap()
+            .extend(events.into_iter().map(|event| TimedInput { time, event }));
+
+        Ok(())
+    }
+
+    /// Sets how far a stick must be pushed before it registers, from 0 to just under 1
+    pub fn set_gamepad_deadzone(&self, deadzone: f32) {
+        if deadzone.is_finite() {
+            self.gamepads.lock().unwrap().deadzone = deadzone.clamp(0., 0.95);
+        }
+    }
+}
+
+/// The touches that changed in an event, in canvas pixels with y pointing up
+fn changed_touches(canvas: &HtmlCanvasElement, event: &TouchEvent) -> Vec<(i32, f32, f32)> {
+    let rect = canvas.get_bounding_client_rect();
+    let height = canvas.height() as f64;
+    let scale_x = canvas.width() as f64 / rect.width();
+    let scale_y = height / rect.height();
+
+    let touches = event.changed_touches();
+    (0..touches.length())
+        .filter_map(|i| touches.get(i))
+        .map(|touch| {
+            let x = (touch.client_x() as f64 - rect.left()) * scale_x;
+            let y = height - (touch.client_y() as f64 - rect.top()) * scale_y;
+            (touch.identifier(), x as f32, y as f32)
+        })
+        .collect()
 }
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use crate::action::{Action, Direction};
+
+/// What a key or button does
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Binding {
+    Move(Direction),
+    Jump,
+    Fire,
+}
+
+impl Binding {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "up" => Self::Move(Direction::Up),
+            "down" => Self::Move(Direction::Down),
+            "left" => Self::Move(Direction::Left),
+            "right" => Self::Move(Direction::Right),
+            "jump" => Self::Jump,
+            "fire" => Self::Fire,
+            _ => return None,
+        })
+    }
+
+    /// The action for the bound input being pressed
+    pub fn pressed(self) -> Action {
+        match self {
+            Self::Move(dir) => Action::StartMoving { dir },
+            Self::Jump => Action::Jump,
+            Self::Fire => Action::Fire,
+        }
+    }
+
+    /// The action for the bound input being released, if there is one
+    pub fn released(self) -> Option<Action> {
+        match self {
+            Self::Move(dir) => Some(Action::StopMoving { dir }),
+            Self::Jump | Self::Fire => None,
+        }
+    }
+}
+
+/// Maps keys and gamepad buttons to what they do
+pub struct Bindings {
+    keys: HashMap<String, Binding>,
+
+    /// Gamepad buttons by index in the standard gamepad layout
+    buttons: HashMap<u32, Binding>,
+}
+
+impl Bindings {
+    pub fn new() -> Self {
+        let keys = [
+            ("w", Binding::Move(Direction::Up)),
+            ("a", Binding::Move(Direction::Left)),
+            ("s", Binding::Move(Direction::Down)),
+            ("d", Binding::Move(Direction::Right)),
+            (" ", Binding::Fire),
+        ];
+
+        let buttons = [
+            (0, Binding::Fire),
+            (1, Binding::Jump),
+            (12, Binding::Move(Direction::Up)),
+            (13, Binding::Move(Direction::Down)),
+            (14, Binding::Move(Direction::Left)),
+            (15, Binding::Move(Direction::Right)),
+        ];
+
+        Self {
+            keys: keys.map(|(k, b)| (k.to_owned(), b)).into(),
+            buttons: buttons.into(),
+        }
+    }
+
+    pub fn key(&self, key: &str) -> Option<Binding> {
+        self.keys.get(key).copied()
+    }
+
+    pub fn button(&self, button: u32) -> Option<Binding> {
+        self.buttons.get(&button).copied()
+    }
+
+    pub fn bind_key(&mut self, key: &str, binding: Binding) {
+        self.keys.insert(key.to_owned(), binding);
+    }
+
+    pub fn bind_button(&mut self, button: u32, binding: Binding) {
+        self.buttons.insert(button, binding);
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
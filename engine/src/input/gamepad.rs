@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+
+use js_sys::Array;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{Gamepad, GamepadButton, Navigator};
+
+use super::InputEvent;
+
+/// Stick positions are rounded to this many steps per unit, so noise doesn't flood the queue
+const STICK_STEPS: f32 = 64.;
+
+/// What we last saw of one gamepad
+#[derive(Default)]
+struct PadState {
+    buttons: Vec<bool>,
+    stick: (f32, f32),
+}
+
+/// Turns gamepad state, which the browser only offers by polling, into input events
+pub struct GamepadPoller {
+    /// Stick deflection below this counts as centered
+    pub deadzone: f32,
+
+    pads: HashMap<u32, PadState>,
+}
+
+impl GamepadPoller {
+    pub fn new() -> Self {
+        Self {
+            deadzone: 0.15,
+            pads: HashMap::new(),
+        }
+    }
+
+    /// Compares the current state of every gamepad with the last poll,
+    /// producing events for whatever changed
+    pub fn poll(&mut self, navigator: &Navigator) -> Result<Vec<InputEvent>, JsValue> {
+        let mut events = Vec::new();
+        let mut connected = Vec::new();
+
+        for pad in navigator.get_gamepads()?.iter() {
+            let Ok(pad) = pad.dyn_into::<Gamepad>() else {
+                // Empty slots are null
+                continue;
+            };
+
+            if !pad.connected() {
+                continue;
+            }
+
+            let index = pad.index();
+            connected.push(index);
+            let state = self.pads.entry(index).or_default();
+
+            let buttons: Vec<bool> = pad
+                .buttons()
+                .iter()
+                .map(|button| {
+                    button
+                        .dyn_into::<GamepadButton>()
+                        .is_ok_and(|button| button.pressed())
+                })
+                .collect();
+            state.buttons.resize(buttons.len(), false);
+
+            for (button, (&now, was)) in buttons.iter().zip(&mut state.buttons).enumerate() {
+                if now != *was {
+                    *was = now;
+                    events.push(button_event(index, button as u32, now));
+                }
+            }
+
+            let stick = apply_deadzone(axis(&pad.axes(), 0), axis(&pad.axes(), 1), self.deadzone);
+            if stick != state.stick {
+                state.stick = stick;
+                events.push(InputEvent::GamepadStick {
+                    pad: index,
+                    x: stick.0,
+                    y: stick.1,
+                });
+            }
+        }
+
+        // Let go of everything held on pads that went away
+        self.pads.retain(|&index, state| {
+            if connected.contains(&index) {
+                return true;
+            }
+
+            for (button, &held) in state.buttons.iter().enumerate() {
+                if held {
+                    events.push(button_event(index, button as u32, false));
+                }
+            }
+
+            if state.stick != (0., 0.) {
+                events.push(InputEvent::GamepadStick {
+                    pad: index,
+                    x: 0.,
+                    y: 0.,
+                });
+            }
+
+            false
+        });
+
+        Ok(events)
+    }
+}
+
+impl Default for GamepadPoller {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn button_event(pad: u32, button: u32, pressed: bool) -> InputEvent {
+    if pressed {
+        InputEvent::GamepadButtonDown { pad, button }
+    } else {
+        InputEvent::GamepadButtonUp { pad, button }
+    }
+}
+
+fn axis(axes: &Array, index: u32) -> f32 {
+    axes.get(index).as_f64().unwrap_or(0.) as f32
+}
+
+/// Radial deadzone, rescaled so the output still covers the whole range past it
+fn apply_deadzone(x: f32, y: f32, deadzone: f32) -> (f32, f32) {
+    let length = (x * x + y * y).sqrt();
+    if length.is_nan() || length <= deadzone {
+        return (0., 0.);
+    }
+
+    let scaled = ((length - deadzone) / (1. - deadzone)).min(1.);
+    let quantize = |v: f32| (v * scaled / length * STICK_STEPS).round() / STICK_STEPS;
+
+    (quantize(x), quantize(y))
+}
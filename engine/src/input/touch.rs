@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use crate::action::{Action, Direction};
+
+use super::Binding;
+
+/// How far the joystick knob can travel from where the touch started, in pixels
+const STICK_RADIUS: f32 = 60.;
+
+/// Fraction of the stick's travel before a direction counts as held
+const STICK_THRESHOLD: f32 = 0.35;
+
+const BUTTON_RADIUS: f32 = 50.;
+
+/// Distance of the button's center from the bottom right corner
+const BUTTON_INSET: f32 = 90.;
+
+const OVERLAY_COLOR: [f32; 4] = [255., 255., 255., 160.];
+
+/// What a touch is controlling
+enum TouchRole {
+    Stick,
+    Button(Binding),
+}
+
+struct Stick {
+    origin: (f32, f32),
+    current: (f32, f32),
+}
+
+/// An on-screen joystick and fire button. Touches on the left half of the
+/// canvas drive the joystick, which is centered wherever they start.
+///
+/// Positions are in canvas pixels with y pointing up, as the renderer uses.
+pub struct TouchControls {
+    /// Width of the canvas, which decides where the button goes
+    width: f32,
+
+    /// The touches currently down that are controlling something, by identifier
+    touches: HashMap<i32, TouchRole>,
+
+    stick: Option<Stick>,
+
+    /// Directions held by the joystick
+    held: Direction,
+
+    /// Only shown once the screen has been touched
+    pub visible: bool,
+}
+
+impl TouchControls {
+    pub fn new(width: f32) -> Self {
+        Self {
+            width,
+            touches: HashMap::new(),
+            stick: None,
+            held: Direction::none(),
+            visible: false,
+        }
+    }
+
+    fn button_center(&self) -> (f32, f32) {
+        (self.width - BUTTON_INSET, BUTTON_INSET)
+    }
+
+    pub fn touch_start(&mut self, id: i32, x: f32, y: f32) -> Vec<Action> {
+        self.visible = true;
+
+        let (bx, by) = self.button_center();
+        if (x - bx).hypot(y - by) <= BUTTON_RADIUS {
+            self.touches.insert(id, TouchRole::Button(Binding::Fire));
+            return vec![Binding::Fire.pressed()];
+        }
+
+        if x < self.width / 2. && self.stick.is_none() {
+            self.touches.insert(id, TouchRole::Stick);
+            self.stick = Some(Stick {
+                origin: (x, y),
+                current: (x, y),
+            });
+        }
+
+        Vec::new()
+    }
+
+    pub fn touch_move(&mut self, id: i32, x: f32, y: f32) -> Vec<Action> {
+        match (self.touches.get(&id), &mut self.stick) {
+            (Some(TouchRole::Stick), Some(stick)) => {
+                stick.current = (x, y);
+                self.update_held()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn touch_end(&mut self, id: i32) -> Vec<Action> {
+        match self.touches.remove(&id) {
+            Some(TouchRole::Stick) => {
+                self.stick = None;
+                self.update_held()
+            }
+            Some(TouchRole::Button(binding)) => binding.released().into_iter().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Presses and releases directions to match the joystick, as the keys would
+    fn update_held(&mut self) -> Vec<Action> {
+        let mut held = Direction::none();
+
+        if let Some(stick) = &self.stick {
+            let threshold = STICK_RADIUS * STICK_THRESHOLD;
+            let dx = stick.current.0 - stick.origin.0;
+            let dy = stick.current.1 - stick.origin.1;
+
+            for (offset, negative, positive) in [
+                (dx, Direction::Left, Direction::Right),
+                (dy, Direction::Down, Direction::Up),
+            ] {
+                if offset > threshold {
+                    held = held.or(positive);
+                } else if offset < -threshold {
+                    held = held.or(negative);
+                }
+            }
+        }
+
+        let released = self.held.and(held.not());
+        let pressed = held.and(self.held.not());
+        self.held = held;
+
+        let mut actions = Vec::new();
+        if !released.is_none() {
+            actions.push(Action::StopMoving { dir: released });
+        }
+        if !pressed.is_none() {
+            actions.push(Action::StartMoving { dir: pressed });
+        }
+
+        actions
+    }
+
+    /// Circles to draw for the overlay, as `(x, y, radius, color)`
+    pub fn shapes(&self) -> Vec<(f32, f32, f32, [f32; 4])> {
+        if !self.visible {
+            return Vec::new();
+        }
+
+        let (bx, by) = self.button_center();
+        let mut shapes = vec![(bx, by, BUTTON_RADIUS, OVERLAY_COLOR)];
+
+        if let Some(stick) = &self.stick {
+            let (ox, oy) = stick.origin;
+            let (dx, dy) = (stick.current.0 - ox, stick.current.1 - oy);
+
+            // Keep the knob within the base
+            let length = dx.hypot(dy);
+            let scale = if length > STICK_RADIUS {
+                STICK_RADIUS / length
+            } else {
+                1.
+            };
+
+            shapes.push((ox, oy, STICK_RADIUS, OVERLAY_COLOR));
+            shapes.push((
+                ox + dx * scale,
+                oy + dy * scale,
+                STICK_RADIUS / 3.,
+                OVERLAY_COLOR,
+            ));
+        }
+
+        shapes
+    }
+}
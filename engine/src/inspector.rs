@@ -0,0 +1,125 @@
+//! Runtime inspection and editing of entities, for debug tools.
+//!
+//! Edits go straight into the world rather than through the action queue,
+//! so they are local to this peer and will desync a networked game.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use specs::prelude::*;
+
+use crate::{
+    components::{registry, ComponentInfo},
+    resources::{EngineEvent, EventBus, Prefab},
+};
+
+/// An entity and the names of the components it has
+#[derive(Serialize)]
+pub struct EntitySummary {
+    pub id: u32,
+    pub components: Vec<&'static str>,
+}
+
+/// A component type and the fields it has
+#[derive(Serialize)]
+pub struct ComponentType {
+    pub name: &'static str,
+    pub fields: &'static [&'static str],
+}
+
+pub fn component_types() -> Vec<ComponentType> {
+    registry()
+        .iter()
+        .map(|info| ComponentType {
+            name: info.name,
+            fields: info.fields,
+        })
+        .collect()
+}
+
+pub fn list_entities(world: &World) -> Vec<EntitySummary> {
+    world
+        .entities()
+        .join()
+        .map(|entity| EntitySummary {
+            id: entity.id(),
+            components: registry()
+                .iter()
+                .filter(|info| info.read(world, entity).is_some())
+                .map(|info| info.name)
+                .collect(),
+        })
+        .collect()
+}
+
+/// Looks up a live entity by id
+fn entity(world: &World, id: u32) -> Result<Entity, String> {
+    let entity = world.entities().entity(id);
+    if world.is_alive(entity) {
+        Ok(entity)
+    } else {
+        Err(format!("No entity with id {id}"))
+    }
+}
+
+/// All of an entity's components as a JSON object keyed by name
+pub fn components(world: &World, id: u32) -> Result<Value, String> {
+    let entity = entity(world, id)?;
+
+    let components: Map<String, Value> = registry()
+        .iter()
+        .filter_map(|info| Some((info.name.to_owned(), info.read(world, entity)?)))
+        .collect();
+
+    Ok(Value::Object(components))
+}
+
+pub fn component(world: &World, id: u32, name: &str) -> Result<Option<Value>, String> {
+    let entity = entity(world, id)?;
+    let info = component_info(name)?;
+
+    Ok(info.read(world, entity))
+}
+
+/// Adds or replaces a component
+pub fn set_component(world: &World, id: u32, name: &str, value: &Value) -> Result<(), String> {
+    let entity = entity(world, id)?;
+    let info = component_info(name)?;
+
+    info.insert(world, entity, value)
+        .map_err(|e| format!("{name}: {e}"))
+}
+
+pub fn remove_component(world: &World, id: u32, name: &str) -> Result<(), String> {
+    let entity = entity(world, id)?;
+    component_info(name)?.remove(world, entity);
+
+    Ok(())
+}
+
+/// Creates an entity from a set of components, returning its id
+pub fn spawn(world: &mut World, components: &Prefab) -> u32 {
+    let id = components.spawn(world).id();
+    world
+        .write_resource::<EventBus>()
+        .push(EngineEvent::EntitySpawned { id });
+
+    id
+}
+
+pub fn despawn(world: &mut World, id: u32) -> Result<(), String> {
+    let entity = entity(world, id)?;
+    world.delete_entity(entity).map_err(|e| e.to_string())?;
+    world.maintain();
+
+    world
+        .write_resource::<EventBus>()
+        .push(EngineEvent::EntityDespawned { id });
+
+    Ok(())
+}
+
+fn component_info(name: &str) -> Result<&'static ComponentInfo, String> {
+    registry()
+        .get(name)
+        .ok_or_else(|| format!("Unknown component {name}"))
+}
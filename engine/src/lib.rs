@@ -1,12 +1,19 @@
-#![feature(box_syntax)]
-
-mod components;
-mod resources;
+pub mod components;
+pub mod resources;
 mod systems;
 mod utils;
 
-mod action;
+pub mod math;
+
+pub mod action;
+pub mod error;
 mod input;
+mod inspector;
+pub mod net;
+pub mod scene;
+pub mod simulation;
+pub mod snapshot;
+pub mod state_hash;
 
 use wasm_bindgen::prelude::*;
 mod engine;
@@ -15,10 +22,13 @@ use engine::init_engine;
 
 use crate::engine::Engine;
 
+/// Seed used when the host doesn't supply one
+const DEFAULT_SEED: u32 = 0x5eed;
+
 #[wasm_bindgen]
-pub fn init(canvas: web_sys::HtmlCanvasElement) -> Result<Engine, JsValue> {
+pub fn init(canvas: web_sys::HtmlCanvasElement, seed: Option<u32>) -> Result<Engine, JsValue> {
     utils::set_panic_hook();
-    let engine = init_engine(canvas);
+    let engine = init_engine(canvas, seed.unwrap_or(DEFAULT_SEED) as u64)?;
 
     Ok(engine)
 }
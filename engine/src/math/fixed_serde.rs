@@ -0,0 +1,75 @@
+//! Serde support for `FixedPoint`, use with `#[serde(with = "fixed_serde")]`.
+//!
+//! Human readable formats get a plain number, which is exact for any value we'd
+//! reasonably use, and compact formats get the raw bits.
+
+use std::fmt;
+
+use serde::{
+    de::{Error, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use super::FixedPoint;
+
+pub fn serialize<S: Serializer>(v: &FixedPoint, serializer: S) -> Result<S::Ok, S::Error> {
+    if serializer.is_human_readable() {
+        serializer.serialize_f64(v.to_num())
+    } else {
+        serializer.serialize_i64(v.to_bits())
+    }
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<FixedPoint, D::Error> {
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_f64(NumberVisitor)
+    } else {
+        i64::deserialize(deserializer).map(FixedPoint::from_bits)
+    }
+}
+
+struct NumberVisitor;
+
+impl<'de> Visitor<'de> for NumberVisitor {
+    type Value = FixedPoint;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a number")
+    }
+
+    fn visit_f64<E: Error>(self, v: f64) -> Result<FixedPoint, E> {
+        FixedPoint::checked_from_num(v)
+            .ok_or_else(|| E::custom(format!("{v} is out of range for a fixed point number")))
+    }
+
+    fn visit_i64<E: Error>(self, v: i64) -> Result<FixedPoint, E> {
+        FixedPoint::checked_from_num(v)
+            .ok_or_else(|| E::custom(format!("{v} is out of range for a fixed point number")))
+    }
+
+    fn visit_u64<E: Error>(self, v: u64) -> Result<FixedPoint, E> {
+        FixedPoint::checked_from_num(v)
+            .ok_or_else(|| E::custom(format!("{v} is out of range for a fixed point number")))
+    }
+}
+
+/// The same for `Option<FixedPoint>`, use with `#[serde(default, with = "fixed_serde::option")]`
+pub mod option {
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct Wrapper(#[serde(with = "super")] FixedPoint);
+
+    pub fn serialize<S: Serializer>(
+        v: &Option<FixedPoint>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        v.map(Wrapper).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<FixedPoint>, D::Error> {
+        Ok(Option::<Wrapper>::deserialize(deserializer)?.map(|w| w.0))
+    }
+}
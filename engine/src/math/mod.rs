@@ -0,0 +1,63 @@
+//! Deterministic math on `FixedPoint`.
+//!
+//! Everything here is integer arithmetic underneath, so results are
+//! bit-identical on every platform, which `f32::sin` and friends don't guarantee.
+
+use fixed::{types::extra::U12, FixedI64};
+
+pub type FixedPoint = FixedI64<U12>;
+
+mod trig;
+pub use trig::{atan2, cos, sin, sin_cos};
+
+mod vec2;
+pub use vec2::FixedVec2;
+
+pub mod fixed_serde;
+
+/// Square root rounded down, or `None` for negative values
+pub fn checked_sqrt(v: FixedPoint) -> Option<FixedPoint> {
+    if v < 0 {
+        return None;
+    }
+
+    let bits = (v.to_bits() as u128) << FixedPoint::FRAC_NBITS;
+    Some(FixedPoint::from_bits(bits.isqrt() as i64))
+}
+
+/// Square root rounded down, negative values give zero
+pub fn sqrt(v: FixedPoint) -> FixedPoint {
+    checked_sqrt(v).unwrap_or(FixedPoint::ZERO)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sqrt_rounds_down() {
+        let root = |v: f64| checked_sqrt(FixedPoint::from_num(v)).unwrap();
+
+        assert_eq!(root(0.), 0);
+        assert_eq!(root(1.), 1);
+        assert_eq!(root(4.), 2);
+        assert_eq!(root(0.25), 0.5);
+        assert_eq!(root(1_000_000.), 1000);
+        assert_eq!(checked_sqrt(-FixedPoint::DELTA), None);
+        assert_eq!(sqrt(-FixedPoint::ONE), 0);
+
+        for bits in (0..1 << 20).step_by(997).chain([i64::MAX]) {
+            let v = FixedPoint::from_bits(bits);
+            let r = checked_sqrt(v).unwrap();
+
+            // The largest value whose square doesn't go over
+            let square = |r: FixedPoint| (r.to_bits() as i128).pow(2);
+            let v = (v.to_bits() as i128) << FixedPoint::FRAC_NBITS;
+            assert!(square(r) <= v, "sqrt({bits} bits) = {r} is too big");
+            assert!(
+                square(r + FixedPoint::DELTA) > v,
+                "sqrt({bits} bits) = {r} is too small"
+            );
+        }
+    }
+}
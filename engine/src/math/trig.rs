@@ -0,0 +1,194 @@
+//! CORDIC based trigonometry.
+//!
+//! Internally works with 30 fractional bits so that the result is accurate
+//! to the last bit of `FixedPoint` once rounded back down.
+
+use super::FixedPoint;
+
+const FRAC: u32 = 30;
+const SHIFT: u32 = FRAC - FixedPoint::FRAC_NBITS;
+
+const PI: i64 = 3373259426;
+const HALF_PI: i64 = 1686629713;
+const TAU: i64 = 6746518852;
+
+/// Reciprocal of the CORDIC gain, prod(1 / sqrt(1 + 2^-2i))
+const INV_GAIN: i64 = 652032874;
+
+/// atan(2^-i) for each iteration
+const ATAN_TABLE: [i64; 31] = [
+    843314857, 497837829, 263043837, 133525159, 67021687, 33543516, 16775851, 8388437, 4194283,
+    2097149, 1048576, 524288, 262144, 131072, 65536, 32768, 16384, 8192, 4096, 2048, 1024, 512,
+    256, 128, 64, 32, 16, 8, 4, 2, 1,
+];
+
+/// Rounds from our internal precision back to `FixedPoint`
+fn round(v: i64) -> FixedPoint {
+    FixedPoint::from_bits((v + (1 << (SHIFT - 1))) >> SHIFT)
+}
+
+/// Sine and cosine of an angle in radians
+pub fn sin_cos(theta: FixedPoint) -> (FixedPoint, FixedPoint) {
+    // Reduce into (-π, π]
+    let mut angle = ((theta.to_bits() as i128) << SHIFT).rem_euclid(TAU as i128) as i64;
+    if angle > PI {
+        angle -= TAU;
+    }
+
+    // Then into [-π/2, π/2], where CORDIC converges
+    let mut flip = false;
+    if angle > HALF_PI {
+        angle -= PI;
+        flip = true;
+    } else if angle < -HALF_PI {
+        angle += PI;
+        flip = true;
+    }
+
+    let (mut x, mut y, mut z) = (INV_GAIN, 0i64, angle);
+    for (i, &step) in ATAN_TABLE.iter().enumerate() {
+        let (dx, dy) = (y >> i, x >> i);
+
+        if z >= 0 {
+            x -= dx;
+            y += dy;
+            z -= step;
+        } else {
+            x += dx;
+            y -= dy;
+            z += step;
+        }
+    }
+
+    if flip {
+        x = -x;
+        y = -y;
+    }
+
+    (round(y), round(x))
+}
+
+pub fn sin(theta: FixedPoint) -> FixedPoint {
+    sin_cos(theta).0
+}
+
+pub fn cos(theta: FixedPoint) -> FixedPoint {
+    sin_cos(theta).1
+}
+
+/// The angle of the vector `(x, y)` in radians, in (-π, π]
+pub fn atan2(y: FixedPoint, x: FixedPoint) -> FixedPoint {
+    if x == 0 && y == 0 {
+        return FixedPoint::ZERO;
+    }
+
+    let (mut x, mut y) = (x.to_bits() as i128, y.to_bits() as i128);
+
+    // Scaling both doesn't change the angle, but gives small vectors more bits to work with
+    let magnitude = x.unsigned_abs().max(y.unsigned_abs());
+    let scale = 62u32.saturating_sub(128 - magnitude.leading_zeros());
+    x <<= scale;
+    y <<= scale;
+
+    // Rotate into the right half plane first
+    let mut z = 0i64;
+    if x < 0 {
+        if y >= 0 {
+            (x, y) = (y, -x);
+            z = HALF_PI;
+        } else {
+            (x, y) = (-y, x);
+            z = -HALF_PI;
+        }
+    }
+
+    for (i, &step) in ATAN_TABLE.iter().enumerate() {
+        let (dx, dy) = (y >> i, x >> i);
+
+        if y > 0 {
+            x += dx;
+            y -= dy;
+            z += step;
+        } else {
+            x -= dx;
+            y += dy;
+            z -= step;
+        }
+    }
+
+    round(z)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI as PI_F64};
+
+    use super::*;
+
+    /// How far off a result may be, in units of the last bit
+    const TOLERANCE: i64 = 1;
+
+    fn assert_close(actual: FixedPoint, expected: f64) {
+        let error = (actual - FixedPoint::from_num(expected)).to_bits().abs();
+        assert!(
+            error <= TOLERANCE,
+            "{actual} is {error} bits off {expected}"
+        );
+    }
+
+    /// Angles all the way around a few times, in both directions
+    fn angles() -> impl Iterator<Item = f64> {
+        (-2000..=2000).map(|i| i as f64 * 0.01)
+    }
+
+    #[test]
+    fn known_values() {
+        let at = |v: f64| sin_cos(FixedPoint::from_num(v));
+
+        assert_eq!(at(0.), (FixedPoint::ZERO, FixedPoint::ONE));
+        assert_close(sin(FixedPoint::FRAC_PI_2), 1.);
+        assert_close(cos(FixedPoint::FRAC_PI_2), 0.);
+        assert_close(sin(FixedPoint::PI), 0.);
+        assert_close(cos(FixedPoint::PI), -1.);
+        assert_close(sin(FixedPoint::FRAC_PI_6), 0.5);
+        assert_close(cos(FixedPoint::FRAC_PI_3), 0.5);
+        assert_close(sin(-FixedPoint::FRAC_PI_4), -FRAC_PI_4.sin());
+    }
+
+    #[test]
+    fn sin_cos_within_a_bit() {
+        for theta in angles() {
+            // Compare against the angle as it was quantized
+            let fixed = FixedPoint::from_num(theta);
+            let exact = fixed.to_num::<f64>();
+
+            let (sin, cos) = sin_cos(fixed);
+            assert_close(sin, exact.sin());
+            assert_close(cos, exact.cos());
+        }
+    }
+
+    #[test]
+    fn atan2_within_a_bit() {
+        assert_eq!(atan2(FixedPoint::ZERO, FixedPoint::ZERO), 0);
+        assert_close(atan2(FixedPoint::ZERO, FixedPoint::ONE), 0.);
+        assert_close(atan2(FixedPoint::ONE, FixedPoint::ZERO), FRAC_PI_2);
+        assert_close(atan2(FixedPoint::ZERO, -FixedPoint::ONE), PI_F64);
+        assert_close(atan2(-FixedPoint::ONE, FixedPoint::ZERO), -FRAC_PI_2);
+        assert_close(atan2(FixedPoint::ONE, FixedPoint::ONE), FRAC_PI_4);
+
+        for theta in angles() {
+            for radius in [
+                FixedPoint::DELTA * 64,
+                FixedPoint::ONE,
+                FixedPoint::from_num(1e6),
+            ] {
+                let (y, x) = (
+                    radius * FixedPoint::from_num(theta.sin()),
+                    radius * FixedPoint::from_num(theta.cos()),
+                );
+                assert_close(atan2(y, x), y.to_num::<f64>().atan2(x.to_num()));
+            }
+        }
+    }
+}
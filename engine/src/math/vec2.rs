@@ -0,0 +1,223 @@
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+use fixed::traits::ToFixed;
+use serde::{Deserialize, Serialize};
+
+use super::{atan2, fixed_serde, sin_cos, FixedPoint};
+
+/// A two dimensional vector of `FixedPoint`s
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug, Serialize, Deserialize)]
+pub struct FixedVec2 {
+    #[serde(with = "fixed_serde")]
+    pub x: FixedPoint,
+    #[serde(with = "fixed_serde")]
+    pub y: FixedPoint,
+}
+
+impl FixedVec2 {
+    pub const ZERO: Self = Self::new(FixedPoint::ZERO, FixedPoint::ZERO);
+
+    pub const fn new(x: FixedPoint, y: FixedPoint) -> Self {
+        Self { x, y }
+    }
+
+    pub fn from_num<T: ToFixed>(x: T, y: T) -> Self {
+        Self::new(FixedPoint::from_num(x), FixedPoint::from_num(y))
+    }
+
+    /// The unit vector at an angle in radians
+    pub fn from_angle(theta: FixedPoint) -> Self {
+        let (sin, cos) = sin_cos(theta);
+        Self::new(cos, sin)
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.x == 0 && self.y == 0
+    }
+
+    pub fn dot(self, other: Self) -> FixedPoint {
+        self.x * other.x + self.y * other.y
+    }
+
+    pub fn checked_dot(self, other: Self) -> Option<FixedPoint> {
+        self.x
+            .checked_mul(other.x)?
+            .checked_add(self.y.checked_mul(other.y)?)
+    }
+
+    pub fn saturating_dot(self, other: Self) -> FixedPoint {
+        self.x
+            .saturating_mul(other.x)
+            .saturating_add(self.y.saturating_mul(other.y))
+    }
+
+    pub fn length_squared(self) -> FixedPoint {
+        self.saturating_dot(self)
+    }
+
+    /// The length, rounded down. Computed exactly, saturating at the largest
+    /// `FixedPoint` for the few vectors longer than that.
+    pub fn length(self) -> FixedPoint {
+        FixedPoint::from_bits(i64::try_from(self.length_bits()).unwrap_or(i64::MAX))
+    }
+
+    /// The bits of the exact length, rounded down. The squares are of magnitudes,
+    /// so even two of `i64::MIN` fit.
+    fn length_bits(self) -> u128 {
+        let x = self.x.to_bits().unsigned_abs() as u128;
+        let y = self.y.to_bits().unsigned_abs() as u128;
+
+        (x * x + y * y).isqrt()
+    }
+
+    /// A unit vector in the same direction, the zero vector stays zero
+    pub fn normalize(self) -> Self {
+        let length = self.length_bits() as i128;
+        if length == 0 {
+            return Self::ZERO;
+        }
+
+        let scale = |v: FixedPoint| {
+            FixedPoint::from_bits(
+                (((v.to_bits() as i128) << FixedPoint::FRAC_NBITS) / length) as i64,
+            )
+        };
+
+        Self::new(scale(self.x), scale(self.y))
+    }
+
+    /// The angle from the positive x axis in radians, in (-π, π]
+    pub fn angle(self) -> FixedPoint {
+        atan2(self.y, self.x)
+    }
+
+    /// Rotated a quarter turn counter-clockwise
+    pub fn perpendicular(self) -> Self {
+        Self::new(-self.y, self.x)
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        Some(Self::new(
+            self.x.checked_add(other.x)?,
+            self.y.checked_add(other.y)?,
+        ))
+    }
+
+    pub fn saturating_add(self, other: Self) -> Self {
+        Self::new(
+            self.x.saturating_add(other.x),
+            self.y.saturating_add(other.y),
+        )
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        Some(Self::new(
+            self.x.checked_sub(other.x)?,
+            self.y.checked_sub(other.y)?,
+        ))
+    }
+
+    pub fn saturating_sub(self, other: Self) -> Self {
+        Self::new(
+            self.x.saturating_sub(other.x),
+            self.y.saturating_sub(other.y),
+        )
+    }
+
+    pub fn checked_mul(self, scale: FixedPoint) -> Option<Self> {
+        Some(Self::new(
+            self.x.checked_mul(scale)?,
+            self.y.checked_mul(scale)?,
+        ))
+    }
+
+    pub fn saturating_mul(self, scale: FixedPoint) -> Self {
+        Self::new(self.x.saturating_mul(scale), self.y.saturating_mul(scale))
+    }
+}
+
+impl Add for FixedVec2 {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl AddAssign for FixedVec2 {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for FixedVec2 {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl SubAssign for FixedVec2 {
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
+impl Neg for FixedVec2 {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.x, -self.y)
+    }
+}
+
+impl Mul<FixedPoint> for FixedVec2 {
+    type Output = Self;
+
+    fn mul(self, scale: FixedPoint) -> Self {
+        Self::new(self.x * scale, self.y * scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn length_is_exact() {
+        assert_eq!(FixedVec2::from_num(3, 4).length(), 5);
+        assert_eq!(FixedVec2::from_num(-3, -4).length(), 5);
+        assert_eq!(FixedVec2::ZERO.length(), 0);
+        assert_eq!(
+            FixedVec2::new(FixedPoint::DELTA, FixedPoint::ZERO).length(),
+            FixedPoint::DELTA
+        );
+    }
+
+    #[test]
+    fn length_saturates() {
+        let min = FixedVec2::new(FixedPoint::MIN, FixedPoint::MIN);
+        assert_eq!(min.length(), FixedPoint::MAX);
+
+        let max = FixedVec2::new(FixedPoint::MAX, FixedPoint::ZERO);
+        assert_eq!(max.length(), FixedPoint::MAX);
+    }
+
+    #[test]
+    fn normalize_works_at_the_extremes() {
+        let unit = |v: FixedVec2| {
+            let length = v.normalize().length();
+            (length - FixedPoint::ONE).abs() <= FixedPoint::DELTA * 2
+        };
+
+        assert!(unit(FixedVec2::new(FixedPoint::MIN, FixedPoint::MIN)));
+        assert!(unit(FixedVec2::new(FixedPoint::MAX, -FixedPoint::MAX)));
+        assert!(unit(FixedVec2::from_num(3, 4)));
+        assert!(unit(FixedVec2::new(
+            FixedPoint::DELTA * 7,
+            FixedPoint::DELTA * 24
+        )));
+        assert_eq!(FixedVec2::ZERO.normalize(), FixedVec2::ZERO);
+    }
+}
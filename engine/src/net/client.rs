@@ -0,0 +1,420 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use specs::prelude::*;
+
+use super::{
+    ClientMessage, ClockSync, LobbyState, RefuseReason, ServerMessage, StateSync, SyncMode,
+    TickBundle, ENGINE_VERSION, PROTOCOL_VERSION,
+};
+use crate::{
+    action::{Action, PlayerAction, PlayerId},
+    error::EngineError,
+    resources::{ActionDelay, EngineEvent, EventBus, LocalPlayer, TickCoordinator, TICK_MS},
+    state_hash::state_hash,
+    utils,
+};
+
+/// How many of our own state hashes are remembered, to report a desync against
+const HASH_HISTORY: usize = 16;
+
+/// Milliseconds to wait for a welcome before asking to join again
+const JOIN_RETRY: f64 = 1000.;
+
+/// Milliseconds to wait at least before asking for missing bundles again
+const MIN_RESEND_INTERVAL: f64 = 100.;
+
+/// Close enough to the live tick to stop fast-forwarding, and leave the rest to the clock
+const CAUGHT_UP_MARGIN: usize = 10;
+
+/// The client half of a lockstep session. Applies what the server sends
+/// to the world, and collects what should be sent back.
+///
+/// Copes with a link that loses, repeats or reorders messages: joining and
+/// missing bundles are asked for again, and bundles are applied in order.
+///
+/// Joining a session that is already going starts from the server's latest
+/// checkpoint, fast-forwarding through the bundles since until caught up.
+///
+/// In state sync there are no bundles, and the world follows the server's states instead.
+pub struct NetClient {
+    /// Our player, once the server has let us in
+    pub player: Option<PlayerId>,
+
+    /// The lobby as last we heard
+    pub lobby: Option<LobbyState>,
+
+    /// Why the server won't have us, after which we stop trying
+    pub refused: Option<RefuseReason>,
+
+    /// How the server keeps us up to date, as it told us when letting us in
+    pub sync: SyncMode,
+    state_sync: Option<StateSync>,
+
+    /// Ticks between state hash reports
+    pub hash_interval: usize,
+
+    /// Milliseconds between pings
+    pub ping_interval: f64,
+
+    /// Our estimate of the server's clock
+    pub clock: ClockSync,
+    last_ping: f64,
+
+    name: String,
+    last_join: f64,
+    next_seq: u32,
+
+    /// Bundles that arrived ahead of one still missing, or too far ahead of us to queue yet
+    pending: BTreeMap<usize, Vec<PlayerAction>>,
+    last_resend: f64,
+
+    catch_up: Option<CatchUp>,
+
+    outbox: Vec<ClientMessage>,
+    sent_hashes: VecDeque<(usize, u64)>,
+}
+
+impl NetClient {
+    /// A client that will ask to join under `name`
+    pub fn new(name: &str) -> Self {
+        Self {
+            player: None,
+            lobby: None,
+            refused: None,
+            sync: SyncMode::Lockstep,
+            state_sync: None,
+            hash_interval: 60,
+            ping_interval: 500.,
+            clock: ClockSync::new(),
+            last_ping: f64::NEG_INFINITY,
+            name: name.to_owned(),
+            last_join: f64::NEG_INFINITY,
+            next_seq: 0,
+            pending: BTreeMap::new(),
+            last_resend: f64::NEG_INFINITY,
+            catch_up: None,
+            outbox: Vec::new(),
+            sent_hashes: VecDeque::new(),
+        }
+    }
+
+    pub fn receive(
+        &mut self,
+        world: &mut World,
+        message: ServerMessage,
+    ) -> Result<(), EngineError> {
+        match message {
+            ServerMessage::Welcome {
+                player,
+                snapshot,
+                live_tick,
+                sync,
+            } => {
+                // A copy, or an answer to asking again
+                if self.player.is_some() {
+                    return Ok(());
+                }
+
+                snapshot.restore(world);
+                world.write_resource::<LocalPlayer>().0 = player;
+                self.player = Some(player);
+
+                self.sync = sync;
+                if sync == SyncMode::State {
+                    self.state_sync = Some(StateSync::new(world));
+                }
+
+                if live_tick > snapshot.tick + CAUGHT_UP_MARGIN {
+                    self.catch_up = Some(CatchUp {
+                        from: snapshot.tick,
+                        live_tick,
+                        welcomed: utils::now(),
+                    });
+                }
+
+                // Nothing may run until the server tells us what happens on the next tick,
+                // though bundles that overtook the welcome may already be here
+                let mut tc = world.write_resource::<TickCoordinator>();
+                tc.hold();
+                tc.catching_up = self.catch_up.is_some();
+                self.pending = self.pending.split_off(&tc.current_tick);
+                self.apply_pending(&mut tc)?;
+            }
+
+            ServerMessage::Lobby(lobby) => {
+                self.lobby = Some(lobby.clone());
+                world
+                    .write_resource::<EventBus>()
+                    .push(EngineEvent::LobbyChanged { lobby });
+            }
+
+            ServerMessage::Refused(reason) => {
+                self.refused = Some(reason.clone());
+                world
+                    .write_resource::<EventBus>()
+                    .push(EngineEvent::Refused { reason });
+            }
+
+            ServerMessage::Bundle(TickBundle { tick, actions }) => {
+                // Overtook the welcome, and belongs to the world that comes with it
+                if self.player.is_none() {
+                    self.pending.insert(tick, actions);
+                    return Ok(());
+                }
+
+                let mut tc = world.write_resource::<TickCoordinator>();
+                // Copies of ones we already have are ignored
+                if tick >= tc.max_tick {
+                    self.pending.insert(tick, actions);
+                    self.apply_pending(&mut tc)?;
+                }
+            }
+
+            ServerMessage::State(delta) => {
+                if let Some(state_sync) = &mut self.state_sync {
+                    if let Some(tick) = state_sync.receive(delta)? {
+                        self.outbox.push(ClientMessage::Ack { tick });
+                    }
+                }
+            }
+
+            ServerMessage::Pong { sent, time, tick } => {
+                if self.clock.add_sample(sent, time, tick, utils::now()) {
+                    world
+                        .write_resource::<ActionDelay>()
+                        .set_estimate(self.clock.round_trip, self.clock.jitter);
+                }
+            }
+
+            ServerMessage::Rejected { tick, reason } => {
+                if let Some(player) = self.player {
+                    world
+                        .write_resource::<EventBus>()
+                        .push(EngineEvent::ActionRejected {
+                            player,
+                            tick,
+                            reason,
+                        });
+                }
+            }
+
+            ServerMessage::Desync { tick, expected } => {
+                let local = self.sent_hashes.iter().find(|(t, _)| *t == tick);
+                if let Some(&(_, local)) = local {
+                    world
+                        .write_resource::<EventBus>()
+                        .push(EngineEvent::Desync {
+                            tick,
+                            local,
+                            remote: expected,
+                        });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Hands the tick coordinator every pending bundle that follows on from its horizon
+    fn apply_pending(&mut self, tc: &mut TickCoordinator) -> Result<(), EngineError> {
+        while tc.has_room_for(tc.max_tick) {
+            let Some(actions) = self.pending.remove(&tc.max_tick) else {
+                break;
+            };
+            tc.receive_bundle(tc.max_tick, actions)?;
+        }
+
+        Ok(())
+    }
+
+    /// Joins, pings and asks for missing bundles when due, and nudges the
+    /// simulation rate to stay just behind the server's tick. To be called every frame.
+    pub fn keep_time(&mut self, world: &World) -> Result<(), EngineError> {
+        let now = utils::now();
+        let mut tc = world.write_resource::<TickCoordinator>();
+        tc.rate = self.clock.rate(tc.current_tick, now);
+
+        if self.refused.is_some() {
+            return Ok(());
+        }
+
+        // The server only talks to players
+        if self.player.is_none() {
+            let queued = self
+                .outbox
+                .iter()
+                .any(|message| matches!(message, ClientMessage::Join { .. }));
+            if !queued && now - self.last_join >= JOIN_RETRY {
+                self.outbox.push(ClientMessage::Join {
+                    protocol: PROTOCOL_VERSION,
+                    engine: ENGINE_VERSION.to_owned(),
+                    name: self.name.clone(),
+                });
+                self.last_join = now;
+            }
+
+            return Ok(());
+        }
+
+        if now - self.last_ping >= self.ping_interval {
+            self.outbox.push(ClientMessage::Ping { sent: now });
+            self.last_ping = now;
+        }
+
+        // Our horizon moved on since the last bundle arrived
+        self.apply_pending(&mut tc)?;
+
+        // A later bundle being here means the next one went missing
+        let missing = self
+            .pending
+            .first_key_value()
+            .is_some_and(|(&tick, _)| tick > tc.max_tick);
+        let resend_interval = (2. * self.clock.round_trip).max(MIN_RESEND_INTERVAL);
+        if missing && now - self.last_resend >= resend_interval {
+            self.outbox
+                .push(ClientMessage::Resend { tick: tc.max_tick });
+            self.last_resend = now;
+        }
+
+        self.keep_catching_up(world, &mut tc, now);
+
+        Ok(())
+    }
+
+    /// Stops fast-forwarding once close enough to where the server is at,
+    /// and reports how far along we are until then
+    fn keep_catching_up(&mut self, world: &World, tc: &mut TickCoordinator, now: f64) {
+        let Some(catch_up) = &self.catch_up else {
+            return;
+        };
+
+        // Until the clock is synced, the server is assumed to have carried on from its welcome
+        let target = self
+            .clock
+            .target_tick(now)
+            .filter(|_| self.clock.is_synced())
+            .unwrap_or(catch_up.live_tick as f64 + (now - catch_up.welcomed) / TICK_MS)
+            .max(0.) as usize;
+
+        let mut events = world.write_resource::<EventBus>();
+        if tc.current_tick + CAUGHT_UP_MARGIN >= target {
+            self.catch_up = None;
+            tc.catching_up = false;
+            events.push(EngineEvent::CaughtUp {
+                tick: tc.current_tick,
+            });
+        } else {
+            let done = tc.current_tick.saturating_sub(catch_up.from);
+            let total = target - catch_up.from;
+            events.push(EngineEvent::CatchingUp {
+                tick: tc.current_tick,
+                target,
+                progress: done as f32 / total as f32,
+            });
+        }
+    }
+
+    /// In state sync, shows the world as the server's states have it. To be called every frame.
+    pub fn follow_state(&mut self, world: &mut World) -> Result<(), EngineError> {
+        let Some(state_sync) = &mut self.state_sync else {
+            return Ok(());
+        };
+        let Some(newest) = state_sync.newest() else {
+            return Ok(());
+        };
+
+        // Where the server's states should have got to by now
+        let tick = self
+            .clock
+            .target_tick(utils::now())
+            .filter(|_| self.clock.is_synced())
+            .unwrap_or(newest as f64);
+
+        state_sync.update(world, tick)
+    }
+
+    /// Whether we are still fast-forwarding to where the session is at
+    pub fn is_catching_up(&self) -> bool {
+        self.catch_up.is_some()
+    }
+
+    /// Asks to join again after losing the connection, as whoever we were is gone
+    pub fn rejoin(&mut self, world: &World) {
+        self.player = None;
+        self.lobby = None;
+        self.clock = ClockSync::new();
+        self.last_ping = f64::NEG_INFINITY;
+        self.last_join = f64::NEG_INFINITY;
+        self.pending.clear();
+        self.catch_up = None;
+        self.state_sync = None;
+        self.outbox.clear();
+
+        let mut tc = world.write_resource::<TickCoordinator>();
+        tc.hold();
+        tc.catching_up = false;
+    }
+
+    /// Tells the lobby whether we're ready to start
+    pub fn set_ready(&mut self, ready: bool) {
+        if self.player.is_some() {
+            self.outbox.push(ClientMessage::Ready { ready });
+        }
+    }
+
+    /// Asks for a player to be thrown out, which the server ignores unless we are the host
+    pub fn kick(&mut self, player: PlayerId) {
+        if self.player.is_some() {
+            self.outbox.push(ClientMessage::Kick { player });
+        }
+    }
+
+    /// Whether actions would be sent. Until the server has let us in there's
+    /// nobody to act for, and until we caught up they would be too late.
+    pub fn can_act(&self) -> bool {
+        self.player.is_some() && self.catch_up.is_none()
+    }
+
+    /// Sends actions made locally, which only take effect once they come back in a bundle.
+    /// They are dropped while we can't act.
+    pub fn send_actions(&mut self, tick: usize, actions: Vec<Action>) {
+        if self.can_act() && !actions.is_empty() {
+            self.outbox.push(ClientMessage::Actions {
+                seq: self.next_seq,
+                tick,
+                actions,
+            });
+            self.next_seq = self.next_seq.wrapping_add(1);
+        }
+    }
+
+    /// Reports our state hash every `hash_interval` ticks, to be called after each tick
+    pub fn tick_completed(&mut self, world: &World) {
+        let tick = world.read_resource::<TickCoordinator>().current_tick;
+        if self.hash_interval == 0 || tick % self.hash_interval != 0 {
+            return;
+        }
+
+        let hash = state_hash(world);
+        if self.sent_hashes.len() == HASH_HISTORY {
+            self.sent_hashes.pop_front();
+        }
+        self.sent_hashes.push_back((tick, hash));
+
+        self.outbox.push(ClientMessage::StateHash { tick, hash });
+    }
+
+    /// Messages to send to the server, oldest first
+    pub fn take_outbox(&mut self) -> Vec<ClientMessage> {
+        std::mem::take(&mut self.outbox)
+    }
+}
+
+/// Where fast-forwarding started from, and where the server was at when it let us in
+struct CatchUp {
+    from: usize,
+    live_tick: usize,
+
+    /// Our time when the welcome arrived
+    welcomed: f64,
+}
@@ -0,0 +1,250 @@
+use std::collections::VecDeque;
+
+use crate::resources::TICK_MS;
+
+/// How many recent round trips outliers are judged against
+const WINDOW: usize = 16;
+
+/// Samples needed before outliers can be told apart, and before we trust the estimate
+const MIN_SAMPLES: usize = 4;
+
+/// A round trip this many times the recent median is an outlier...
+const OUTLIER_FACTOR: f64 = 2.;
+
+/// ...give or take this many milliseconds, so tiny LAN round trips don't all look like outliers
+const OUTLIER_SLACK: f64 = 5.;
+
+/// Weight of each new sample in the smoothed estimates
+const SMOOTHING: f64 = 0.125;
+
+/// How hard the simulation rate is pulled towards where it should be, per tick off
+const RATE_GAIN: f64 = 0.01;
+
+/// The furthest the simulation rate may stray from normal speed
+const MAX_RATE_ADJUSTMENT: f64 = 0.1;
+
+/// Estimates the server's clock from ping round trips, the way NTP does, and
+/// from it which tick the server is on. Samples delayed on the way are
+/// rejected as outliers, and the rest smoothed.
+///
+/// All times are in milliseconds, ours from `utils::now` and the server's from its own clock.
+#[derive(Clone, Debug)]
+pub struct ClockSync {
+    /// Server time minus ours
+    pub offset: f64,
+
+    /// Smoothed round trip time
+    pub round_trip: f64,
+
+    /// Smoothed deviation of the round trip time
+    pub jitter: f64,
+
+    /// How many samples were rejected as outliers
+    pub outliers: usize,
+
+    accepted: usize,
+    recent_round_trips: VecDeque<f64>,
+
+    /// A tick the server was on, and its time then
+    reference: Option<(usize, f64)>,
+}
+
+impl ClockSync {
+    pub fn new() -> Self {
+        Self {
+            offset: 0.,
+            round_trip: 0.,
+            jitter: 0.,
+            outliers: 0,
+            accepted: 0,
+            recent_round_trips: VecDeque::new(),
+            reference: None,
+        }
+    }
+
+    /// Whether there have been enough samples to go by
+    pub fn is_synced(&self) -> bool {
+        self.accepted >= MIN_SAMPLES
+    }
+
+    /// Folds in one ping, sent at `sent` and answered at `received` by our clock,
+    /// which the server handled at `server_time` while on `server_tick`.
+    /// Returns whether it was believable enough to use.
+    pub fn add_sample(
+        &mut self,
+        sent: f64,
+        server_time: f64,
+        server_tick: usize,
+        received: f64,
+    ) -> bool {
+        let round_trip = received - sent;
+        if !round_trip.is_finite() || !server_time.is_finite() || round_trip < 0. {
+            return false;
+        }
+
+        // Assumes the ping took as long each way, which is only close on a quick round trip
+        let offset = server_time - (sent + received) / 2.;
+
+        // Outliers still count towards the median, so a lasting change in latency is followed
+        let outlier = self.recent_round_trips.len() >= MIN_SAMPLES
+            && round_trip > self.median_round_trip() * OUTLIER_FACTOR + OUTLIER_SLACK;
+        if self.recent_round_trips.len() == WINDOW {
+            self.recent_round_trips.pop_front();
+        }
+        self.recent_round_trips.push_back(round_trip);
+
+        if outlier {
+            self.outliers += 1;
+            return false;
+        }
+
+        if self.accepted == 0 {
+            self.offset = offset;
+            self.round_trip = round_trip;
+            self.jitter = round_trip / 2.;
+        } else {
+            self.offset += SMOOTHING * (offset - self.offset);
+            self.jitter += 2. * SMOOTHING * ((round_trip - self.round_trip).abs() - self.jitter);
+            self.round_trip += SMOOTHING * (round_trip - self.round_trip);
+        }
+        self.accepted += 1;
+        self.reference = Some((server_tick, server_time));
+
+        true
+    }
+
+    fn median_round_trip(&self) -> f64 {
+        let mut sorted: Vec<_> = self.recent_round_trips.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        sorted[sorted.len() / 2]
+    }
+
+    /// Which tick the server is on at `now`, with the fraction of the way into the next
+    pub fn server_tick(&self, now: f64) -> Option<f64> {
+        let (tick, time) = self.reference?;
+        Some(tick as f64 + (now + self.offset - time) / TICK_MS)
+    }
+
+    /// Where we should be at `now`: behind the server by as long as its bundles take
+    /// to reach us, plus a margin for jitter, so they are in before we need them
+    pub fn target_tick(&self, now: f64) -> Option<f64> {
+        let lag = (self.round_trip / 2. + 2. * self.jitter) / TICK_MS + 1.;
+        Some(self.server_tick(now)? - lag)
+    }
+
+    /// Simulation rate to converge on the target tick from `current_tick`,
+    /// slightly faster when behind and slower when ahead
+    pub fn rate(&self, current_tick: usize, now: f64) -> f32 {
+        let Some(target) = self.target_tick(now).filter(|_| self.is_synced()) else {
+            return 1.;
+        };
+
+        let behind = target - current_tick as f64;
+        (1. + (behind * RATE_GAIN).clamp(-MAX_RATE_ADJUSTMENT, MAX_RATE_ADJUSTMENT)) as f32
+    }
+}
+
+impl Default for ClockSync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Server time minus ours in the traces
+    const OFFSET: f64 = 1000.;
+
+    /// Feeds a ping sent at `sent` that took `round_trip`, split evenly each way
+    fn ping(clock: &mut ClockSync, sent: f64, round_trip: f64) -> bool {
+        let server_time = sent + round_trip / 2. + OFFSET;
+        let tick = (server_time / TICK_MS) as usize;
+        clock.add_sample(sent, server_time, tick, sent + round_trip)
+    }
+
+    /// Pings once a second with the given round trips, returning which were used
+    fn trace(clock: &mut ClockSync, round_trips: impl IntoIterator<Item = f64>) -> Vec<bool> {
+        let start = clock.accepted as f64 + clock.outliers as f64;
+        round_trips
+            .into_iter()
+            .enumerate()
+            .map(|(i, round_trip)| ping(clock, (start + i as f64) * 1000., round_trip))
+            .collect()
+    }
+
+    #[test]
+    fn constant_round_trip_settles_on_offset() {
+        let mut clock = ClockSync::new();
+        assert!(!clock.is_synced());
+
+        assert!(trace(&mut clock, [50.; 20]).into_iter().all(|used| used));
+
+        assert!(clock.is_synced());
+        assert_eq!(clock.outliers, 0);
+        assert_eq!(clock.offset, OFFSET);
+        assert_eq!(clock.round_trip, 50.);
+        assert!(clock.jitter < 1., "jitter {}", clock.jitter);
+
+        // The server's tick follows its clock, which runs ahead of ours by the offset
+        let now = 30_000.;
+        let expected = (now + OFFSET) / TICK_MS;
+        assert!((clock.server_tick(now).unwrap() - expected).abs() < 1.);
+    }
+
+    #[test]
+    fn single_spike_is_an_outlier() {
+        let mut clock = ClockSync::new();
+        trace(&mut clock, [50.; 10]);
+        let before = clock.clone();
+
+        assert_eq!(trace(&mut clock, [500.]), [false]);
+        assert_eq!(clock.outliers, 1);
+        assert_eq!(clock.round_trip, before.round_trip);
+        assert_eq!(clock.offset, before.offset);
+
+        assert!(trace(&mut clock, [50.; 5]).into_iter().all(|used| used));
+        assert_eq!(clock.outliers, 1);
+    }
+
+    #[test]
+    fn lasting_step_is_followed() {
+        let mut clock = ClockSync::new();
+        trace(&mut clock, [50.; 10]);
+
+        let used = trace(&mut clock, [200.; 30]);
+
+        // Rejected only until the higher round trips make up half the window
+        let rejected = used.iter().take_while(|used| !**used).count();
+        assert!((1..WINDOW).contains(&rejected), "rejected {rejected}");
+        assert!(used[rejected..].iter().all(|used| *used));
+        assert_eq!(clock.outliers, rejected);
+
+        assert!(clock.round_trip > 180., "round trip {}", clock.round_trip);
+        assert!((clock.offset - OFFSET).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rate_is_clamped() {
+        let mut clock = ClockSync::new();
+        let now = 10_000.;
+        assert_eq!(clock.rate(0, now), 1.);
+
+        trace(&mut clock, [20.; 8]);
+        let target = clock.target_tick(now).unwrap();
+        let rate = |tick: f64| clock.rate(tick as usize, now) as f64;
+
+        let max = 1. + MAX_RATE_ADJUSTMENT;
+        let min = 1. - MAX_RATE_ADJUSTMENT;
+        assert!((rate(0.) - max).abs() < 1e-6);
+        assert!((rate(target + 10_000.) - min).abs() < 1e-6);
+
+        // Within the clamps the rate is proportional to how far off we are
+        let behind = rate(target - 5.);
+        assert!(behind > 1. && behind < max);
+        let ahead = rate(target + 5.);
+        assert!(ahead < 1. && ahead > min);
+        assert!((rate(target.round()) - 1.).abs() <= RATE_GAIN);
+    }
+}
@@ -0,0 +1,296 @@
+use specs::prelude::*;
+
+use super::{
+    ClientMessage, ConnectionState, LobbyPhase, NetClient, ServerMessage, SyncMode, Transport,
+};
+use crate::{
+    error::EngineError,
+    resources::{EngineEvent, Errors, EventBus, NetStats, TickCoordinator},
+    utils,
+};
+
+/// Joins the world's `NetClient` to a transport, passing messages between them,
+/// and keeps the world's `NetStats` up to date if it has one
+pub struct Connection<T: Transport> {
+    transport: T,
+    state: ConnectionState,
+}
+
+impl<T: Transport> Connection<T> {
+    pub fn new(transport: T) -> Self {
+        Self {
+            state: transport.state(),
+            transport,
+        }
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    /// Applies everything the server sent since the last call, stopping at
+    /// the first message that can't be
+    pub fn receive(&mut self, world: &mut World) -> Result<(), EngineError> {
+        // The client needs the world to itself while it applies messages
+        let Some(mut client) = world.remove::<NetClient>() else {
+            return Ok(());
+        };
+
+        let state = self.transport.state();
+        if state != self.state {
+            if state == ConnectionState::Open && self.state == ConnectionState::Reconnecting {
+                client.rejoin(world);
+            }
+
+            self.state = state;
+            world
+                .write_resource::<EventBus>()
+                .push(EngineEvent::ConnectionChanged { state });
+        }
+
+        let mut result = Ok(());
+        while let Some(text) = self.transport.receive() {
+            result = ServerMessage::decode(&text).and_then(|message| {
+                if let Some(mut stats) = world.try_fetch_mut::<NetStats>() {
+                    stats.received(text.len());
+                    if let ServerMessage::Pong { sent, .. } = message {
+                        stats.pong_received(sent);
+                    }
+                }
+
+                client.receive(world, message)
+            });
+            if result.is_err() {
+                break;
+            }
+        }
+        if result.is_ok() {
+            result = client
+                .keep_time(world)
+                .and_then(|()| client.follow_state(world));
+        }
+
+        // Reconnecting would only be refused again
+        if client.refused.is_some() && self.transport.state() != ConnectionState::Closed {
+            self.transport.close();
+        }
+
+        world.insert(client);
+        result
+    }
+
+    /// Sends whatever the client has queued up, once there's someone to send it to,
+    /// and takes stock of the frame. To be called after its ticks have run.
+    pub fn flush(&mut self, world: &World) {
+        let Some(mut client) = world.try_fetch_mut::<NetClient>() else {
+            return;
+        };
+        let mut stats = world.try_fetch_mut::<NetStats>();
+
+        if self.state == ConnectionState::Open {
+            for message in client.take_outbox() {
+                let text = match message.encode() {
+                    Ok(text) => text,
+                    Err(e) => {
+                        world.write_resource::<Errors>().push(e);
+                        continue;
+                    }
+                };
+                if let Some(stats) = &mut stats {
+                    stats.sent(text.len());
+                    if let ClientMessage::Ping { sent } = message {
+                        stats.ping_sent(sent);
+                    }
+                }
+
+                self.transport.send(&text);
+            }
+        }
+
+        if let Some(stats) = &mut stats {
+            // Only while playing in lockstep is there a horizon to be ahead of
+            let playing = client
+                .lobby
+                .as_ref()
+                .is_some_and(|lobby| matches!(lobby.phase, LobbyPhase::Playing { .. }));
+            let tc = world.read_resource::<TickCoordinator>();
+            let ahead = (playing && client.sync == SyncMode::Lockstep)
+                .then(|| tc.max_tick.saturating_sub(tc.current_tick));
+
+            stats.update(
+                utils::now(),
+                client.clock.round_trip,
+                client.clock.jitter,
+                ahead,
+                tc.stalled,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::*;
+    use crate::{
+        net::{MemorySocket, RefuseReason},
+        simulation::init_world,
+        snapshot::Snapshot,
+    };
+
+    /// A `MemorySocket` that can be made to look like it lost the connection
+    struct Switchable {
+        socket: MemorySocket,
+        state: Rc<Cell<ConnectionState>>,
+    }
+
+    impl Transport for Switchable {
+        fn send(&mut self, message: &str) {
+            self.socket.send(message);
+        }
+
+        fn receive(&mut self) -> Option<String> {
+            self.socket.receive()
+        }
+
+        fn state(&self) -> ConnectionState {
+            match self.socket.state() {
+                ConnectionState::Closed => ConnectionState::Closed,
+                _ => self.state.get(),
+            }
+        }
+
+        fn close(&mut self) {
+            self.socket.close();
+            self.state.set(ConnectionState::Closed);
+        }
+    }
+
+    struct Setup {
+        world: World,
+        connection: Connection<Switchable>,
+        state: Rc<Cell<ConnectionState>>,
+        server: MemorySocket,
+    }
+
+    fn setup() -> Setup {
+        let mut world = init_world(1).unwrap();
+        world.insert(NetClient::new("test"));
+
+        let (socket, server) = MemorySocket::pair();
+        let state = Rc::new(Cell::new(ConnectionState::Open));
+        let connection = Connection::new(Switchable {
+            socket,
+            state: state.clone(),
+        });
+
+        Setup {
+            world,
+            connection,
+            state,
+            server,
+        }
+    }
+
+    impl Setup {
+        /// Runs a frame without any ticks, returning what the client sent
+        fn frame(&mut self) -> Vec<ClientMessage> {
+            self.connection.receive(&mut self.world).unwrap();
+            self.connection.flush(&self.world);
+
+            std::iter::from_fn(|| self.server.receive())
+                .map(|text| ClientMessage::decode(&text).unwrap())
+                .collect()
+        }
+
+        fn welcome(&mut self, player: u32) {
+            let message = ServerMessage::Welcome {
+                player,
+                snapshot: Snapshot::take(&self.world),
+                live_tick: 0,
+                sync: SyncMode::Lockstep,
+            };
+            self.server.send(&message.encode().unwrap());
+            self.frame();
+        }
+
+        fn player(&self) -> Option<u32> {
+            self.world.read_resource::<NetClient>().player
+        }
+    }
+
+    fn joins(messages: &[ClientMessage]) -> usize {
+        messages
+            .iter()
+            .filter(|message| matches!(message, ClientMessage::Join { .. }))
+            .count()
+    }
+
+    #[test]
+    fn rejoins_once_the_connection_is_back() {
+        let mut setup = setup();
+        assert_eq!(joins(&setup.frame()), 1);
+        setup.welcome(1);
+        assert_eq!(setup.player(), Some(1));
+
+        // Nothing goes out while the connection is down, and we are still who we were
+        setup.state.set(ConnectionState::Reconnecting);
+        assert!(setup.frame().is_empty());
+        assert_eq!(setup.connection.state(), ConnectionState::Reconnecting);
+        assert_eq!(setup.player(), Some(1));
+
+        setup.state.set(ConnectionState::Open);
+        assert_eq!(joins(&setup.frame()), 1);
+        assert_eq!(setup.player(), None);
+        {
+            // Held until the new welcome says where the session is at
+            let tc = setup.world.read_resource::<TickCoordinator>();
+            assert_eq!(tc.max_tick, tc.current_tick);
+        }
+
+        let changes: Vec<_> = setup
+            .world
+            .write_resource::<EventBus>()
+            .take()
+            .into_iter()
+            .filter_map(|event| match event {
+                EngineEvent::ConnectionChanged { state } => Some(state),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            [ConnectionState::Reconnecting, ConnectionState::Open]
+        );
+
+        setup.welcome(2);
+        assert_eq!(setup.player(), Some(2));
+    }
+
+    #[test]
+    fn refused_hangs_up() {
+        let mut setup = setup();
+        setup.frame();
+
+        setup.server.send(
+            &ServerMessage::Refused(RefuseReason::SessionFull)
+                .encode()
+                .unwrap(),
+        );
+        assert!(setup.frame().is_empty());
+
+        assert_eq!(
+            setup.world.read_resource::<NetClient>().refused,
+            Some(RefuseReason::SessionFull)
+        );
+        assert_eq!(setup.state.get(), ConnectionState::Closed);
+        assert_eq!(setup.server.receive(), None);
+        assert_eq!(setup.server.state(), ConnectionState::Closed);
+
+        // And stays that way, rather than asking to join again
+        assert!(setup.frame().is_empty());
+        assert_eq!(setup.connection.state(), ConnectionState::Closed);
+        assert_eq!(setup.player(), None);
+    }
+}
@@ -0,0 +1,315 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use specs::prelude::*;
+
+use super::state_sync::{EntityState, StateDelta, WorldState, SENT_HISTORY};
+use crate::components::physics::Position;
+
+/// How far from a player's entity others are sent to them at all
+pub const RELEVANT_DISTANCE: f64 = 2048.;
+
+/// The distance at which an entity is sent half as often as one right next to the player
+const FALLOFF_DISTANCE: f64 = 512.;
+
+/// Bytes of entity updates in one state, a little under a typical packet
+pub const STATE_BUDGET: usize = 1200;
+
+/// Side of the cells the spatial index buckets entities into
+const CELL_SIZE: f64 = 512.;
+
+/// Where every entity with a `Position` is, bucketed into a grid to find those
+/// near a point without going through them all
+pub struct SpatialIndex {
+    positions: HashMap<u32, (f64, f64)>,
+    cells: HashMap<(i64, i64), Vec<u32>>,
+}
+
+impl SpatialIndex {
+    pub fn build(world: &World) -> Self {
+        let mut index = Self {
+            positions: HashMap::new(),
+            cells: HashMap::new(),
+        };
+
+        let positions = world.read_storage::<Position>();
+        for (entity, position) in (&world.entities(), &positions).join() {
+            let (x, y) = (position.x.to_num::<f64>(), position.y.to_num::<f64>());
+            index.positions.insert(entity.id(), (x, y));
+            index.cells.entry(cell(x, y)).or_default().push(entity.id());
+        }
+
+        index
+    }
+
+    pub fn position(&self, id: u32) -> Option<(f64, f64)> {
+        self.positions.get(&id).copied()
+    }
+
+    /// Entities within `radius` of a point, with their distance from it
+    pub fn within(&self, x: f64, y: f64, radius: f64) -> impl Iterator<Item = (u32, f64)> + '_ {
+        let (min, max) = (cell(x - radius, y - radius), cell(x + radius, y + radius));
+
+        (min.0..=max.0)
+            .flat_map(move |cx| (min.1..=max.1).map(move |cy| (cx, cy)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .filter_map(move |&id| {
+                let (ex, ey) = self.positions[&id];
+                let distance = (ex - x).hypot(ey - y);
+                (distance <= radius).then_some((id, distance))
+            })
+    }
+}
+
+fn cell(x: f64, y: f64) -> (i64, i64) {
+    (
+        (x / CELL_SIZE).floor() as i64,
+        (y / CELL_SIZE).floor() as i64,
+    )
+}
+
+/// What one client of state sync gets to see. Only entities near the player are
+/// sent, and of those the nearer ones more often: each state adds to every
+/// relevant entity's priority by how close it is, and an entity is due once it
+/// gets to one. Due entities go in highest priority first, until the budget
+/// for the state is spent, and the rest wait for the next.
+///
+/// The budget is charged for what the delta carries, which is everything that
+/// changed since the state the client acknowledged. Updates it hasn't
+/// acknowledged yet go again first, and a client that hasn't acknowledged
+/// anything gets the world a budget at a time.
+///
+/// Anything that leaves the world or goes out of range is dropped straight
+/// away, which costs next to nothing.
+pub struct Interest {
+    /// The recent views sent, each what the client has if it got that state
+    sent: BTreeMap<usize, WorldState>,
+
+    priority: HashMap<u32, f64>,
+}
+
+impl Interest {
+    pub fn new() -> Self {
+        Self {
+            sent: BTreeMap::new(),
+            priority: HashMap::new(),
+        }
+    }
+
+    /// The client's view of `state`, relative to `acked`, the latest state it
+    /// acknowledged. `focus` is the entity it controls, everything is relevant
+    /// without one.
+    pub fn next(
+        &mut self,
+        state: &WorldState,
+        index: &SpatialIndex,
+        focus: Option<u32>,
+        acked: Option<usize>,
+    ) -> StateDelta {
+        // How often each entity should be sent, from 1 for every time down
+        let weights: HashMap<u32, f64> = match focus.and_then(|id| index.position(id)) {
+            Some((x, y)) => index
+                .within(x, y, RELEVANT_DISTANCE)
+                .map(|(id, distance)| (id, 1. / (1. + distance / FALLOFF_DISTANCE)))
+                .chain(
+                    // Those that aren't anywhere are always relevant
+                    state
+                        .entities
+                        .keys()
+                        .filter(|id| index.position(**id).is_none())
+                        .map(|&id| (id, 1.)),
+                )
+                .collect(),
+            None => state.entities.keys().map(|&id| (id, 1.)).collect(),
+        };
+
+        self.priority.retain(|id, _| weights.contains_key(id));
+        for (&id, weight) in &weights {
+            *self.priority.entry(id).or_default() += weight;
+        }
+
+        // The delta is against what the client acknowledged, so that is what the budget
+        // is charged against, and without it everything sent is sent in full
+        let baseline = acked.and_then(|tick| self.sent.get(&tick));
+        let relevant = |id: &u32| state.entities.contains_key(id) && weights.contains_key(id);
+
+        let mut view = WorldState {
+            tick: state.tick,
+            entities: baseline
+                .map(|baseline| {
+                    baseline
+                        .entities
+                        .iter()
+                        .filter(|(id, _)| relevant(id))
+                        .map(|(&id, components)| (id, components.clone()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        };
+
+        // Sent since, so the client may be showing them already, and leaving them
+        // out would take them back to how they were
+        let unacked: HashSet<u32> = self
+            .sent
+            .values()
+            .next_back()
+            .map(|latest| {
+                latest
+                    .entities
+                    .iter()
+                    .filter(|&(id, components)| {
+                        relevant(id) && view.entities.get(id) != Some(components)
+                    })
+                    .map(|(&id, _)| id)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut due: Vec<_> = self
+            .priority
+            .iter()
+            .filter(|(id, &priority)| {
+                (priority >= 1. || unacked.contains(id)) && state.entities.contains_key(id)
+            })
+            .map(|(&id, &priority)| (id, priority))
+            .collect();
+        // Their own entity first, as nothing else shows them where they are,
+        // then what they may have already
+        due.sort_by(|a, b| {
+            (Some(b.0) == focus)
+                .cmp(&(Some(a.0) == focus))
+                .then(unacked.contains(&b.0).cmp(&unacked.contains(&a.0)))
+                .then(b.1.total_cmp(&a.1))
+                .then(a.0.cmp(&b.0))
+        });
+
+        let mut spent = 0;
+        for (id, _) in due {
+            let components = &state.entities[&id];
+            let cost = cost(components, baseline.and_then(|b| b.entities.get(&id)));
+            if spent + cost > STATE_BUDGET {
+                continue;
+            }
+
+            spent += cost;
+            view.entities.insert(id, components.clone());
+            self.priority.insert(id, 0.);
+        }
+
+        let delta = view.delta_from(baseline);
+
+        self.sent.insert(view.tick, view);
+        while self.sent.len() > SENT_HISTORY {
+            self.sent.pop_first();
+        }
+
+        delta
+    }
+}
+
+impl Default for Interest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Roughly how many bytes updating an entity from `old` takes
+fn cost(components: &EntityState, old: Option<&EntityState>) -> usize {
+    let changed: EntityState = components
+        .iter()
+        .filter(|(name, value)| old.and_then(|old| old.get(*name)) != Some(value))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+
+    if changed.is_empty() {
+        0
+    } else {
+        serde_json::to_string(&changed).map_or(0, |json| json.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const ENTITIES: u32 = 100;
+
+    /// Every entity somewhere different each tick, and sizeable enough that
+    /// far from all of them fit in one state
+    fn state(tick: usize) -> WorldState {
+        WorldState {
+            tick,
+            entities: (0..ENTITIES)
+                .map(|id| {
+                    let components = [
+                        ("Position".to_owned(), json!({ "x": tick, "y": id })),
+                        (
+                            "Name".to_owned(),
+                            json!("a name long enough to cost something"),
+                        ),
+                    ];
+                    (id, components.into_iter().collect())
+                })
+                .collect(),
+        }
+    }
+
+    fn index() -> SpatialIndex {
+        let mut world = World::new();
+        world.register::<Position>();
+        SpatialIndex::build(&world)
+    }
+
+    fn size(delta: &StateDelta) -> usize {
+        delta
+            .changed
+            .iter()
+            .map(|(_, components)| cost(components, None))
+            .sum()
+    }
+
+    #[test]
+    fn without_a_baseline_a_state_stays_within_budget() {
+        let (mut interest, index) = (Interest::new(), index());
+
+        for tick in 0..10 {
+            let delta = interest.next(&state(tick), &index, None, None);
+            assert_eq!(delta.baseline, None);
+            assert!(!delta.changed.is_empty());
+            assert!(size(&delta) <= STATE_BUDGET, "{} bytes", size(&delta));
+        }
+    }
+
+    #[test]
+    fn unacknowledged_updates_are_charged_for() {
+        let (mut interest, index) = (Interest::new(), index());
+
+        // The client only ever acknowledges the first state, so everything since
+        // is in every delta and counts against the budget
+        let first = interest.next(&state(0), &index, None, None);
+        for tick in 1..10 {
+            let delta = interest.next(&state(tick), &index, None, Some(first.tick));
+            assert_eq!(delta.baseline, Some(first.tick));
+            assert!(size(&delta) <= STATE_BUDGET, "{} bytes", size(&delta));
+        }
+    }
+
+    #[test]
+    fn acknowledging_each_state_gets_everything_across() {
+        let (mut interest, index) = (Interest::new(), index());
+
+        let mut seen = HashSet::new();
+        let mut acked = None;
+        for tick in 0..50 {
+            let delta = interest.next(&state(tick), &index, None, acked);
+            assert!(size(&delta) <= STATE_BUDGET, "{} bytes", size(&delta));
+
+            seen.extend(delta.changed.iter().map(|(id, _)| *id));
+            acked = Some(tick);
+        }
+
+        assert_eq!(seen.len(), ENTITIES as usize);
+    }
+}
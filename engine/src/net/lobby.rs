@@ -0,0 +1,405 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{action::PlayerId, resources::TICKS_PER_SECOND};
+
+/// Bumped whenever messages change in a way older peers wouldn't understand
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Peers must run the same engine, or their simulations drift apart
+pub const ENGINE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The longest a player name may be, in characters
+const MAX_NAME_LENGTH: usize = 32;
+
+/// Where a session is at
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(tag = "phase", rename_all = "snake_case")]
+pub enum LobbyPhase {
+    /// Waiting for everyone to be ready
+    Waiting,
+    /// Everyone is ready, and the game starts once this runs out
+    Countdown { seconds: usize },
+    /// The game started on `start_tick`
+    Playing { start_tick: usize },
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct LobbyPlayer {
+    pub id: PlayerId,
+    pub name: String,
+    pub ready: bool,
+}
+
+/// What every player is told about the lobby whenever it changes
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct LobbyState {
+    #[serde(flatten)]
+    pub phase: LobbyPhase,
+
+    /// Who may kick other players, the longest connected
+    pub host: Option<PlayerId>,
+
+    pub players: Vec<LobbyPlayer>,
+}
+
+/// Why the server won't have someone, or won't anymore
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum RefuseReason {
+    /// The versions the server runs, which the client's don't match
+    VersionMismatch {
+        protocol: u32,
+        engine: String,
+    },
+    SessionFull,
+    InvalidName,
+    Kicked,
+}
+
+#[derive(Clone, Debug)]
+pub struct LobbySettings {
+    /// Players needed before a countdown can start
+    pub min_players: usize,
+    pub max_players: usize,
+    pub countdown_ticks: usize,
+}
+
+impl Default for LobbySettings {
+    fn default() -> Self {
+        Self {
+            min_players: 1,
+            max_players: 8,
+            countdown_ticks: 3 * TICKS_PER_SECOND,
+        }
+    }
+}
+
+/// The server's side of the handshake and lobby, as a state machine driven by
+/// what players send and by ticks of the server loop. It only decides, so
+/// sending its state and starting the game is up to whoever runs it.
+pub struct Lobby {
+    pub settings: LobbySettings,
+    phase: LobbyPhase,
+    players: BTreeMap<PlayerId, LobbyPlayer>,
+    next_player: PlayerId,
+    countdown: usize,
+    changed: bool,
+}
+
+impl Lobby {
+    pub fn new(settings: LobbySettings) -> Self {
+        Self {
+            settings,
+            phase: LobbyPhase::Waiting,
+            players: BTreeMap::new(),
+            // Player 0 is whoever plays locally without a server
+            next_player: 1,
+            countdown: 0,
+            changed: false,
+        }
+    }
+
+    pub fn phase(&self) -> LobbyPhase {
+        self.phase
+    }
+
+    pub fn is_playing(&self) -> bool {
+        matches!(self.phase, LobbyPhase::Playing { .. })
+    }
+
+    pub fn player_count(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn name(&self, player: PlayerId) -> Option<&str> {
+        self.players.get(&player).map(|p| p.name.as_str())
+    }
+
+    pub fn players(&self) -> impl Iterator<Item = PlayerId> + '_ {
+        self.players.keys().copied()
+    }
+
+    /// The first player still here
+    pub fn host(&self) -> Option<PlayerId> {
+        self.players.keys().next().copied()
+    }
+
+    /// Checks a player's handshake, and gives them an id if they can come in
+    pub fn admit(
+        &mut self,
+        protocol: u32,
+        engine: &str,
+        name: &str,
+    ) -> Result<PlayerId, RefuseReason> {
+        if protocol != PROTOCOL_VERSION || engine != ENGINE_VERSION {
+            return Err(RefuseReason::VersionMismatch {
+                protocol: PROTOCOL_VERSION,
+                engine: ENGINE_VERSION.to_owned(),
+            });
+        }
+
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+            return Err(RefuseReason::InvalidName);
+        }
+
+        if self.players.len() >= self.settings.max_players {
+            return Err(RefuseReason::SessionFull);
+        }
+
+        let id = self.next_player;
+        self.next_player += 1;
+        self.players.insert(
+            id,
+            LobbyPlayer {
+                id,
+                name: name.to_owned(),
+                // Joining a game in progress, there's nothing to wait for
+                ready: self.is_playing(),
+            },
+        );
+
+        self.cancel_countdown();
+        self.changed = true;
+
+        Ok(id)
+    }
+
+    pub fn remove(&mut self, player: PlayerId) {
+        if self.players.remove(&player).is_some() {
+            self.changed = true;
+        }
+    }
+
+    /// Whether `by` may kick `player` out, which only the host can do to others
+    pub fn may_kick(&self, by: PlayerId, player: PlayerId) -> bool {
+        by != player && self.host() == Some(by) && self.players.contains_key(&player)
+    }
+
+    pub fn set_ready(&mut self, player: PlayerId, ready: bool) {
+        let Some(lobby_player) = self.players.get_mut(&player) else {
+            return;
+        };
+
+        if lobby_player.ready != ready {
+            lobby_player.ready = ready;
+            self.changed = true;
+
+            if !ready {
+                self.cancel_countdown();
+            }
+        }
+    }
+
+    fn cancel_countdown(&mut self) {
+        if let LobbyPhase::Countdown { .. } = self.phase {
+            self.phase = LobbyPhase::Waiting;
+            self.changed = true;
+        }
+    }
+
+    /// Moves the countdown along by one tick of the server loop, which is
+    /// on `current_tick` of the simulation. Returns whether the game starts now.
+    pub fn tick(&mut self, current_tick: usize) -> bool {
+        let everyone_ready = self.players.len() >= self.settings.min_players
+            && self.players.values().all(|player| player.ready);
+
+        match self.phase {
+            LobbyPhase::Waiting if everyone_ready => {
+                self.countdown = self.settings.countdown_ticks;
+                self.phase = LobbyPhase::Countdown {
+                    seconds: self.countdown.div_ceil(TICKS_PER_SECOND),
+                };
+                self.changed = true;
+                false
+            }
+
+            LobbyPhase::Countdown { .. } if !everyone_ready => {
+                self.cancel_countdown();
+                false
+            }
+
+            LobbyPhase::Countdown { seconds } => {
+                self.countdown = self.countdown.saturating_sub(1);
+                if self.countdown == 0 {
+                    self.phase = LobbyPhase::Playing {
+                        start_tick: current_tick,
+                    };
+                    self.changed = true;
+                    return true;
+                }
+
+                let remaining = self.countdown.div_ceil(TICKS_PER_SECOND);
+                if remaining != seconds {
+                    self.phase = LobbyPhase::Countdown { seconds: remaining };
+                    self.changed = true;
+                }
+                false
+            }
+
+            _ => false,
+        }
+    }
+
+    pub fn state(&self) -> LobbyState {
+        LobbyState {
+            phase: self.phase,
+            host: self.host(),
+            players: self.players.values().cloned().collect(),
+        }
+    }
+
+    /// Whether anything changed since the last call, i.e. whether players need telling
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+}
+
+impl Default for Lobby {
+    fn default() -> Self {
+        Self::new(LobbySettings::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn join(lobby: &mut Lobby, name: &str) -> PlayerId {
+        lobby.admit(PROTOCOL_VERSION, ENGINE_VERSION, name).unwrap()
+    }
+
+    fn lobby(min_players: usize, max_players: usize) -> Lobby {
+        Lobby::new(LobbySettings {
+            min_players,
+            max_players,
+            countdown_ticks: 2 * TICKS_PER_SECOND,
+        })
+    }
+
+    #[test]
+    fn admit_checks_the_handshake() {
+        let mut lobby = lobby(1, 2);
+
+        assert!(matches!(
+            lobby.admit(PROTOCOL_VERSION + 1, ENGINE_VERSION, "ann"),
+            Err(RefuseReason::VersionMismatch { .. })
+        ));
+        assert!(matches!(
+            lobby.admit(PROTOCOL_VERSION, "0.0.0-other", "ann"),
+            Err(RefuseReason::VersionMismatch { .. })
+        ));
+        assert_eq!(
+            lobby.admit(PROTOCOL_VERSION, ENGINE_VERSION, "   "),
+            Err(RefuseReason::InvalidName)
+        );
+        assert_eq!(
+            lobby.admit(
+                PROTOCOL_VERSION,
+                ENGINE_VERSION,
+                &"a".repeat(MAX_NAME_LENGTH + 1)
+            ),
+            Err(RefuseReason::InvalidName)
+        );
+        assert_eq!(lobby.player_count(), 0);
+
+        let ann = join(&mut lobby, " ann ");
+        let bob = join(&mut lobby, "bob");
+        assert_eq!((ann, bob), (1, 2));
+        assert_eq!(lobby.name(ann), Some("ann"));
+        assert_eq!(lobby.host(), Some(ann));
+        assert!(lobby.take_changed());
+
+        assert_eq!(
+            lobby.admit(PROTOCOL_VERSION, ENGINE_VERSION, "cat"),
+            Err(RefuseReason::SessionFull)
+        );
+
+        // Ids aren't handed out twice, and the next longest connected takes over
+        lobby.remove(ann);
+        assert_eq!(lobby.host(), Some(bob));
+        assert_eq!(join(&mut lobby, "cat"), 3);
+    }
+
+    #[test]
+    fn readying_up_counts_down_to_the_start() {
+        let mut lobby = lobby(2, 4);
+        let ann = join(&mut lobby, "ann");
+        lobby.set_ready(ann, true);
+
+        // Not enough players yet
+        assert!(!lobby.tick(0));
+        assert_eq!(lobby.phase(), LobbyPhase::Waiting);
+
+        let bob = join(&mut lobby, "bob");
+        lobby.set_ready(bob, true);
+        lobby.take_changed();
+
+        assert!(!lobby.tick(1));
+        assert_eq!(lobby.phase(), LobbyPhase::Countdown { seconds: 2 });
+        assert!(lobby.take_changed());
+
+        for tick in 2..2 + TICKS_PER_SECOND {
+            assert!(!lobby.tick(tick));
+        }
+        assert_eq!(lobby.phase(), LobbyPhase::Countdown { seconds: 1 });
+
+        let start = 1 + 2 * TICKS_PER_SECOND;
+        for tick in 2 + TICKS_PER_SECOND..start {
+            assert!(!lobby.tick(tick));
+        }
+        assert!(lobby.tick(start));
+        assert_eq!(lobby.phase(), LobbyPhase::Playing { start_tick: start });
+
+        // Latecomers have nothing to wait for
+        let cat = join(&mut lobby, "cat");
+        assert!(lobby.state().players.iter().any(|p| p.id == cat && p.ready));
+        assert!(lobby.is_playing());
+    }
+
+    #[test]
+    fn countdown_is_cancelled() {
+        let mut lobby = lobby(1, 4);
+        let ann = join(&mut lobby, "ann");
+        lobby.set_ready(ann, true);
+        lobby.tick(0);
+        assert!(matches!(lobby.phase(), LobbyPhase::Countdown { .. }));
+
+        // By someone changing their mind
+        lobby.set_ready(ann, false);
+        assert_eq!(lobby.phase(), LobbyPhase::Waiting);
+
+        // By someone new coming in
+        lobby.set_ready(ann, true);
+        lobby.tick(1);
+        let bob = join(&mut lobby, "bob");
+        assert_eq!(lobby.phase(), LobbyPhase::Waiting);
+        assert!(!lobby.tick(2));
+        assert_eq!(lobby.phase(), LobbyPhase::Waiting);
+
+        // And the countdown starts over from the top
+        lobby.set_ready(bob, true);
+        lobby.tick(3);
+        assert_eq!(lobby.phase(), LobbyPhase::Countdown { seconds: 2 });
+    }
+
+    #[test]
+    fn only_the_host_kicks() {
+        let mut lobby = lobby(1, 4);
+        let ann = join(&mut lobby, "ann");
+        let bob = join(&mut lobby, "bob");
+        let cat = join(&mut lobby, "cat");
+
+        assert!(lobby.may_kick(ann, bob));
+        assert!(!lobby.may_kick(bob, cat));
+        assert!(!lobby.may_kick(bob, ann));
+        assert!(!lobby.may_kick(ann, ann));
+        assert!(!lobby.may_kick(ann, 99));
+
+        lobby.remove(ann);
+        assert!(lobby.may_kick(bob, cat));
+        assert!(!lobby.may_kick(cat, bob));
+    }
+}
@@ -0,0 +1,43 @@
+//! Lockstep networking. An authoritative server gathers every player's actions
+//! into one bundle per tick, and every peer simulates the same bundles in order.
+//! Peers may only run ticks they have a bundle for, which is the `max_tick`
+//! horizon of the `TickCoordinator`.
+//!
+//! Alternatively in state sync only the server simulates, sending each client
+//! what changed since the last state it acknowledged, of what is near it.
+//!
+//! Messages are carried by a `Transport`, a WebSocket in the browser or a
+//! `MemorySocket` to run both ends in one process.
+
+mod protocol;
+pub use protocol::{ClientMessage, ServerMessage, TickBundle};
+
+mod client;
+pub use client::NetClient;
+
+mod clock;
+pub use clock::ClockSync;
+
+mod lobby;
+pub use lobby::{
+    Lobby, LobbyPhase, LobbyPlayer, LobbySettings, LobbyState, RefuseReason, ENGINE_VERSION,
+    PROTOCOL_VERSION,
+};
+
+mod state_sync;
+pub use state_sync::{EntityState, StateDelta, StateSync, SyncMode, WorldState, STATE_INTERVAL};
+
+mod interest;
+pub use interest::{Interest, SpatialIndex, RELEVANT_DISTANCE, STATE_BUDGET};
+
+mod transport;
+pub use transport::{ConnectionState, MemorySocket, Transport};
+
+mod connection;
+pub use connection::Connection;
+
+mod simulated;
+pub use simulated::{Delay, LinkConditions, SimulatedTransport};
+
+mod websocket;
+pub use websocket::WebSocketTransport;
@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+
+use super::{LobbyState, RefuseReason, StateDelta, SyncMode};
+use crate::{
+    action::{Action, PlayerAction, PlayerId},
+    error::EngineError,
+    resources::RejectReason,
+    snapshot::Snapshot,
+};
+
+/// Every action to be applied on one tick, in the order they are applied
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct TickBundle {
+    pub tick: usize,
+    pub actions: Vec<PlayerAction>,
+}
+
+/// Messages from a client to the server
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Asks to join the session, with the versions the sender runs
+    Join {
+        protocol: u32,
+        engine: String,
+        name: String,
+    },
+
+    /// Whether the sender is ready for the game to start
+    Ready { ready: bool },
+
+    /// Asks for another player to be thrown out, which only the host may do
+    Kick { player: PlayerId },
+
+    /// Actions made by the sender, to be applied on `tick` if the server allows.
+    /// `seq` counts up with each of these, so copies can be told apart.
+    Actions {
+        seq: u32,
+        tick: usize,
+        actions: Vec<Action>,
+    },
+
+    /// The sender's state hash at the start of `tick`
+    StateHash { tick: usize, hash: u64 },
+
+    /// Asks for the server's time, `sent` being ours when asking
+    Ping { sent: f64 },
+
+    /// Asks for the bundles from `tick` on again, after one went missing
+    Resend { tick: usize },
+
+    /// In state sync, the latest state the sender has, which later ones can build on
+    Ack { tick: usize },
+}
+
+/// Messages from the server to a client
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// Accepted into the session as `player`, starting from `snapshot` and its tick.
+    /// In lockstep the bundles from then on follow, up to `live_tick` where the server is at.
+    Welcome {
+        player: PlayerId,
+        snapshot: Snapshot,
+        live_tick: usize,
+        sync: SyncMode,
+    },
+
+    /// Who is in the session and whether it started, sent whenever that changes
+    Lobby(LobbyState),
+
+    /// Not let in, or not anymore, after which the server hangs up
+    Refused(RefuseReason),
+
+    /// The actions for a tick, which may now be simulated
+    Bundle(TickBundle),
+
+    /// In state sync, how the world changed since a state the recipient acknowledged
+    State(StateDelta),
+
+    /// One of the recipient's actions was refused
+    Rejected { tick: usize, reason: RejectReason },
+
+    /// The recipient's state hash for `tick` differs from the server's
+    Desync { tick: usize, expected: u64 },
+
+    /// Answers a ping: the server's `time` and `tick` when it got it
+    Pong { sent: f64, time: f64, tick: usize },
+}
+
+impl ClientMessage {
+    pub fn encode(&self) -> Result<String, EngineError> {
+        serde_json::to_string(self).map_err(|e| EngineError::Protocol(e.to_string()))
+    }
+
+    pub fn decode(text: &str) -> Result<Self, EngineError> {
+        serde_json::from_str(text).map_err(|e| EngineError::Protocol(e.to_string()))
+    }
+}
+
+impl ServerMessage {
+    pub fn encode(&self) -> Result<String, EngineError> {
+        serde_json::to_string(self).map_err(|e| EngineError::Protocol(e.to_string()))
+    }
+
+    pub fn decode(text: &str) -> Result<Self, EngineError> {
+        serde_json::from_str(text).map_err(|e| EngineError::Protocol(e.to_string()))
+    }
+}
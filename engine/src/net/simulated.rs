@@ -0,0 +1,334 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+};
+
+use super::{ConnectionState, Transport};
+use crate::{action::FixedPoint, resources::Rng, utils};
+
+/// How long a message takes one way, in milliseconds
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Delay {
+    Fixed(f64),
+    Uniform {
+        min: f64,
+        max: f64,
+    },
+    /// Never less than zero, however far the tail reaches
+    Normal {
+        mean: f64,
+        deviation: f64,
+    },
+}
+
+impl Delay {
+    fn sample(&self, rng: &mut Rng) -> f64 {
+        let delay = match *self {
+            Self::Fixed(delay) => delay,
+            Self::Uniform { min, max } => min + (max - min) * unit(rng),
+            Self::Normal { mean, deviation } => {
+                // Box-Muller, with the first draw kept away from zero
+                let (a, b) = (1. - unit(rng), unit(rng));
+                mean + deviation * (-2. * a.ln()).sqrt() * (std::f64::consts::TAU * b).cos()
+            }
+        };
+
+        delay.max(0.)
+    }
+}
+
+/// How a simulated link misbehaves, the same way in both directions
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LinkConditions {
+    pub delay: Delay,
+
+    /// Chance of a message never arriving
+    pub loss: f64,
+
+    /// Chance of a message arriving twice
+    pub duplication: f64,
+
+    /// Chance of a message being held up, letting the ones after it overtake
+    pub reordering: f64,
+
+    /// The longest a reordered message is held up for, in milliseconds
+    pub reorder_delay: f64,
+}
+
+impl LinkConditions {
+    /// A link that delivers everything in order, instantly
+    pub fn perfect() -> Self {
+        Self {
+            delay: Delay::Fixed(0.),
+            loss: 0.,
+            duplication: 0.,
+            reordering: 0.,
+            reorder_delay: 100.,
+        }
+    }
+}
+
+impl Default for LinkConditions {
+    fn default() -> Self {
+        Self::perfect()
+    }
+}
+
+/// Wraps a transport in a link with the given conditions, to see how the
+/// protocol copes with a bad network. Whether each message is lost, copied or
+/// held up, and for how long, is decided by a generator for each direction
+/// seeded up front, so the same seed misbehaves the same way for the same
+/// traffic at the same times, however often either end is polled.
+pub struct SimulatedTransport<T: Transport> {
+    inner: T,
+    conditions: LinkConditions,
+    clock: Box<dyn FnMut() -> f64>,
+    outgoing: Lane,
+    incoming: Lane,
+}
+
+/// Messages in flight one way, by when they arrive
+struct Lane {
+    rng: Rng,
+
+    in_flight: BinaryHeap<Reverse<(Arrival, String)>>,
+
+    /// When the last message that kept its place arrives, which later ones can't overtake
+    last_arrival: f64,
+
+    /// Breaks ties in arrival time, so those go in the order they were sent
+    sent: u64,
+}
+
+/// When a message arrives, and how many were sent before it
+#[derive(Clone, Copy, Debug)]
+struct Arrival(f64, u64);
+
+impl PartialEq for Arrival {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Arrival {}
+
+impl PartialOrd for Arrival {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Arrival {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+impl Lane {
+    fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+            in_flight: BinaryHeap::new(),
+            last_arrival: f64::NEG_INFINITY,
+            sent: 0,
+        }
+    }
+
+    fn send(&mut self, message: &str, now: f64, conditions: &LinkConditions) {
+        let rng = &mut self.rng;
+        if chance(rng, conditions.loss) {
+            return;
+        }
+
+        let copies = if chance(rng, conditions.duplication) {
+            2
+        } else {
+            1
+        };
+
+        for _ in 0..copies {
+            let mut arrival = now + conditions.delay.sample(rng);
+            if chance(rng, conditions.reordering) {
+                arrival += conditions.reorder_delay * unit(rng);
+            } else {
+                arrival = arrival.max(self.last_arrival);
+                self.last_arrival = arrival;
+            }
+
+            self.sent += 1;
+            self.in_flight
+                .push(Reverse((Arrival(arrival, self.sent), message.to_owned())));
+        }
+    }
+
+    /// The next message to have arrived by `now`
+    fn arrived(&mut self, now: f64) -> Option<String> {
+        match self.in_flight.peek() {
+            Some(Reverse((Arrival(arrival, _), _))) if *arrival <= now => {
+                self.in_flight.pop().map(|Reverse((_, message))| message)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<T: Transport> SimulatedTransport<T> {
+    pub fn new(inner: T, conditions: LinkConditions, seed: u64) -> Self {
+        Self {
+            inner,
+            conditions,
+            clock: Box::new(utils::now),
+            outgoing: Lane::new(seed),
+            incoming: Lane::new(!seed),
+        }
+    }
+
+    /// Reads the time in milliseconds from `clock` rather than the steady clock,
+    /// to replay a link exactly
+    pub fn with_clock(mut self, clock: impl FnMut() -> f64 + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Moves messages along both ways: into the link as they come in, and out of it as they arrive
+    fn pump(&mut self, now: f64) {
+        while let Some(message) = self.outgoing.arrived(now) {
+            self.inner.send(&message);
+        }
+
+        while let Some(message) = self.inner.receive() {
+            self.incoming.send(&message, now, &self.conditions);
+        }
+    }
+}
+
+impl<T: Transport> Transport for SimulatedTransport<T> {
+    fn send(&mut self, message: &str) {
+        let now = (self.clock)();
+        self.outgoing.send(message, now, &self.conditions);
+        self.pump(now);
+    }
+
+    fn receive(&mut self) -> Option<String> {
+        let now = (self.clock)();
+        self.pump(now);
+        self.incoming.arrived(now)
+    }
+
+    fn state(&self) -> ConnectionState {
+        self.inner.state()
+    }
+
+    fn close(&mut self) {
+        self.inner.close();
+    }
+}
+
+/// Uniform in `[0, 1)`
+fn unit(rng: &mut Rng) -> f64 {
+    rng.unit().to_num()
+}
+
+fn chance(rng: &mut Rng, probability: f64) -> bool {
+    probability > 0. && rng.chance(FixedPoint::from_num(probability.min(1.)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::*;
+    use crate::net::MemorySocket;
+
+    fn bad_link() -> LinkConditions {
+        LinkConditions {
+            delay: Delay::Normal {
+                mean: 40.,
+                deviation: 15.,
+            },
+            loss: 0.1,
+            duplication: 0.1,
+            reordering: 0.1,
+            reorder_delay: 50.,
+        }
+    }
+
+    /// Messages one end got, and when
+    type Received = Vec<(f64, String)>;
+
+    /// Sends a message each way every 5ms for a second, polling the simulated end
+    /// `polls` times a step, and returns what each end got
+    fn exchange(conditions: LinkConditions, seed: u64, polls: usize) -> (Received, Received) {
+        let time = Rc::new(Cell::new(0.));
+        let (near, mut far) = MemorySocket::pair();
+        let clock = time.clone();
+        let mut link =
+            SimulatedTransport::new(near, conditions, seed).with_clock(move || clock.get());
+
+        let (mut near_got, mut far_got) = (Vec::new(), Vec::new());
+        for step in 0..400 {
+            time.set(step as f64 * 5.);
+            if step < 200 {
+                link.send(&format!("out {step}"));
+                far.send(&format!("in {step}"));
+            }
+
+            for _ in 0..polls {
+                while let Some(message) = link.receive() {
+                    near_got.push((time.get(), message));
+                }
+            }
+            while let Some(message) = far.receive() {
+                far_got.push((time.get(), message));
+            }
+        }
+
+        (near_got, far_got)
+    }
+
+    #[test]
+    fn perfect_link_delivers_everything_in_order_at_once() {
+        let (near, far) = exchange(LinkConditions::perfect(), 1, 1);
+
+        let expected: Vec<_> = (0..200)
+            .map(|i| (i as f64 * 5., format!("out {i}")))
+            .collect();
+        assert_eq!(far, expected);
+        assert_eq!(near.len(), 200);
+        assert!(near
+            .iter()
+            .enumerate()
+            .all(|(i, (_, m))| *m == format!("in {i}")));
+    }
+
+    #[test]
+    fn same_seed_misbehaves_the_same() {
+        assert_eq!(exchange(bad_link(), 7, 1), exchange(bad_link(), 7, 1));
+        assert_ne!(exchange(bad_link(), 7, 1), exchange(bad_link(), 8, 1));
+    }
+
+    #[test]
+    fn polling_more_often_changes_nothing() {
+        assert_eq!(exchange(bad_link(), 7, 1), exchange(bad_link(), 7, 4));
+    }
+
+    #[test]
+    fn messages_are_lost_copied_and_reordered() {
+        let (near, far) = exchange(bad_link(), 7, 1);
+
+        for got in [&near, &far] {
+            let mut ids: Vec<usize> = got
+                .iter()
+                .map(|(_, m)| m.split(' ').nth(1).unwrap().parse().unwrap())
+                .collect();
+            let reordered = ids.windows(2).any(|w| w[0] > w[1]);
+            ids.sort();
+            let copied = ids.windows(2).any(|w| w[0] == w[1]);
+            ids.dedup();
+
+            // 10% of 200 lost, give or take
+            assert!((160..195).contains(&ids.len()), "{} arrived", ids.len());
+            assert!(reordered && copied);
+        }
+    }
+}
@@ -0,0 +1,500 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use specs::prelude::*;
+
+use crate::{
+    action::FixedPoint,
+    components::{physics::Position, registry},
+    error::EngineError,
+    resources::{EngineEvent, EventBus, TickCoordinator},
+};
+
+/// Ticks between the states the server sends
+pub const STATE_INTERVAL: usize = 3;
+
+/// How far behind the latest state the world is shown, so there's usually a later one to move towards
+const INTERPOLATION_TICKS: f64 = 2. * STATE_INTERVAL as f64;
+
+/// Fixed point numbers are sent rounded to this, still well under a pixel,
+/// which takes fewer digits and doesn't count changes too small to see
+const QUANTUM: f64 = 1. / 64.;
+
+/// How many received states are kept, to build later ones on. Twice as many as the
+/// server keeps to send against, so whichever one it last heard we have is still here.
+const RECEIVED_HISTORY: usize = 64;
+
+/// How many sent states the server keeps for each client, to send later ones against
+pub const SENT_HISTORY: usize = RECEIVED_HISTORY / 2;
+
+/// How the server keeps clients up to date
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncMode {
+    /// Every peer runs the simulation, from the same actions
+    #[default]
+    Lockstep,
+
+    /// Only the server runs the simulation, and sends clients what changed.
+    /// Costs more bandwidth, but clients can't desync and have nothing to cheat with.
+    State,
+}
+
+impl SyncMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Lockstep => "lockstep",
+            Self::State => "state",
+        }
+    }
+}
+
+/// An entity's components by name
+pub type EntityState = BTreeMap<String, Value>;
+
+/// Every entity's components at one tick, as sent in state sync
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct WorldState {
+    pub tick: usize,
+    pub entities: BTreeMap<u32, EntityState>,
+}
+
+/// How the world changed from one state to another
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct StateDelta {
+    pub tick: usize,
+
+    /// The state this builds on, or none if it has everything
+    pub baseline: Option<usize>,
+
+    /// Components added or changed by entity id, which is all of them for new
+    /// entities. Pairs rather than maps, as tagged messages can't have number keys.
+    pub changed: Vec<(u32, EntityState)>,
+
+    /// Components taken off entities that are still there
+    pub removed: Vec<(u32, Vec<String>)>,
+
+    pub despawned: Vec<u32>,
+}
+
+impl WorldState {
+    /// Every registered component of every entity, quantized
+    pub fn capture(world: &World) -> Self {
+        let entities = world
+            .entities()
+            .join()
+            .map(|entity| {
+                let components = registry()
+                    .iter()
+                    .filter_map(|info| {
+                        let mut value = info.read(world, entity)?;
+                        quantize(&mut value);
+                        Some((info.name.to_owned(), value))
+                    })
+                    .collect();
+
+                (entity.id(), components)
+            })
+            .collect();
+
+        Self {
+            tick: world.read_resource::<TickCoordinator>().current_tick,
+            entities,
+        }
+    }
+
+    /// What changed since `baseline`, or everything without one
+    pub fn delta_from(&self, baseline: Option<&WorldState>) -> StateDelta {
+        let empty = BTreeMap::new();
+        let before = baseline.map_or(&empty, |baseline| &baseline.entities);
+
+        let mut delta = StateDelta {
+            tick: self.tick,
+            baseline: baseline.map(|baseline| baseline.tick),
+            changed: Vec::new(),
+            removed: Vec::new(),
+            despawned: before
+                .keys()
+                .filter(|id| !self.entities.contains_key(id))
+                .copied()
+                .collect(),
+        };
+
+        for (&id, components) in &self.entities {
+            let old = before.get(&id);
+
+            let changed: EntityState = components
+                .iter()
+                .filter(|(name, value)| old.and_then(|old| old.get(*name)) != Some(value))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect();
+            if !changed.is_empty() {
+                delta.changed.push((id, changed));
+            }
+
+            let removed: Vec<_> = old
+                .into_iter()
+                .flat_map(|old| old.keys())
+                .filter(|name| !components.contains_key(*name))
+                .cloned()
+                .collect();
+            if !removed.is_empty() {
+                delta.removed.push((id, removed));
+            }
+        }
+
+        delta
+    }
+
+    /// The state `delta` leads to, which must build on this one
+    fn apply(&self, delta: &StateDelta) -> Self {
+        let mut entities = self.entities.clone();
+
+        for id in &delta.despawned {
+            entities.remove(id);
+        }
+        for (id, names) in &delta.removed {
+            if let Some(components) = entities.get_mut(id) {
+                for name in names {
+                    components.remove(name);
+                }
+            }
+        }
+        for (id, changed) in &delta.changed {
+            entities.entry(*id).or_default().extend(
+                changed
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.clone())),
+            );
+        }
+
+        Self {
+            tick: delta.tick,
+            entities,
+        }
+    }
+}
+
+/// Rounds every fractional number in a component to the nearest quantum
+fn quantize(value: &mut Value) {
+    match value {
+        Value::Number(number) if number.is_f64() => {
+            if let Some(quantized) = number
+                .as_f64()
+                .map(|v| (v / QUANTUM).round() * QUANTUM)
+                .and_then(serde_json::Number::from_f64)
+            {
+                *number = quantized;
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(quantize),
+        Value::Object(fields) => fields.values_mut().for_each(quantize),
+        _ => {}
+    }
+}
+
+/// The client side of state sync, which follows the server's states instead of
+/// simulating. The world is shown as it was a little while ago, moving
+/// entities smoothly between the two states either side of then.
+pub struct StateSync {
+    received: BTreeMap<usize, WorldState>,
+
+    /// Our entity for each of the server's
+    entities: HashMap<u32, Entity>,
+
+    /// The tick of the state the world was last set to
+    applied: Option<usize>,
+}
+
+impl StateSync {
+    /// Follows on from the world as it is, whose entities have the server's ids
+    pub fn new(world: &World) -> Self {
+        Self {
+            received: BTreeMap::new(),
+            entities: world
+                .entities()
+                .join()
+                .map(|entity| (entity.id(), entity))
+                .collect(),
+            applied: None,
+        }
+    }
+
+    /// Builds the state a delta describes, returning its tick to acknowledge unless it came too late
+    pub fn receive(&mut self, delta: StateDelta) -> Result<Option<usize>, EngineError> {
+        if self.newest().is_some_and(|newest| newest >= delta.tick) {
+            return Ok(None);
+        }
+
+        let state = match delta.baseline {
+            None => WorldState::default().apply(&delta),
+            Some(baseline) => self
+                .received
+                .get(&baseline)
+                .ok_or_else(|| {
+                    EngineError::Protocol(format!(
+                        "The state for tick {} builds on tick {baseline}, which we don't have",
+                        delta.tick
+                    ))
+                })?
+                .apply(&delta),
+        };
+
+        self.received.insert(state.tick, state);
+        while self.received.len() > RECEIVED_HISTORY {
+            self.received.pop_first();
+        }
+
+        Ok(Some(delta.tick))
+    }
+
+    pub fn newest(&self) -> Option<usize> {
+        self.received.keys().next_back().copied()
+    }
+
+    /// Shows the world as it was `INTERPOLATION_TICKS` before `tick`, a fractional
+    /// tick of the server's, or as the latest state if that's later
+    pub fn update(&mut self, world: &mut World, tick: f64) -> Result<(), EngineError> {
+        let Some(newest) = self.newest() else {
+            return Ok(());
+        };
+        let shown = (tick - INTERPOLATION_TICKS).min(newest as f64).max(0.);
+
+        // The latest state at or before then, or the oldest we have if there is none
+        let Some((&from_tick, from)) = self
+            .received
+            .range(..=shown as usize)
+            .next_back()
+            .or_else(|| self.received.iter().next())
+        else {
+            return Ok(());
+        };
+
+        if self.applied != Some(from_tick) {
+            set_world(world, &mut self.entities, from)?;
+            self.applied = Some(from_tick);
+        }
+
+        let Some((&to_tick, to)) = self.received.range(from_tick + 1..).next() else {
+            return Ok(());
+        };
+        let t = ((shown - from_tick as f64) / (to_tick - from_tick) as f64).clamp(0., 1.);
+
+        let name = registry()
+            .name_of::<Position>()
+            .expect("Components should be declared before use");
+        let mut positions = world.write_storage::<Position>();
+        for (id, components) in &from.entities {
+            let (Some(entity), Some(a), Some(b)) = (
+                self.entities.get(id),
+                position(components, name),
+                to.entities.get(id).and_then(|to| position(to, name)),
+            ) else {
+                continue;
+            };
+
+            let lerp = |a: FixedPoint, b: FixedPoint| {
+                let (a, b) = (a.to_num::<f64>(), b.to_num::<f64>());
+                FixedPoint::from_num(a + (b - a) * t)
+            };
+            let _ = positions.insert(*entity, Position::new(lerp(a.x, b.x), lerp(a.y, b.y)));
+        }
+
+        Ok(())
+    }
+}
+
+fn position(components: &EntityState, name: &str) -> Option<Position> {
+    serde_json::from_value(components.get(name)?.clone()).ok()
+}
+
+/// Makes the world match a state, spawning and despawning as needed
+fn set_world(
+    world: &mut World,
+    entities: &mut HashMap<u32, Entity>,
+    state: &WorldState,
+) -> Result<(), EngineError> {
+    let mut events = Vec::new();
+
+    entities.retain(|id, &mut entity| {
+        let keep = state.entities.contains_key(id);
+        if !keep && world.delete_entity(entity).is_ok() {
+            events.push(EngineEvent::EntityDespawned { id: entity.id() });
+        }
+        keep
+    });
+
+    for (&id, components) in &state.entities {
+        let entity = *entities.entry(id).or_insert_with(|| {
+            let entity = world.create_entity().build();
+            events.push(EngineEvent::EntitySpawned { id: entity.id() });
+            entity
+        });
+
+        for info in registry().iter() {
+            match components.get(info.name) {
+                Some(value) => info
+                    .insert(world, entity, value)
+                    .map_err(|e| EngineError::Protocol(format!("{}: {e}", info.name)))?,
+                None => info.remove(world, entity),
+            }
+        }
+    }
+    world.maintain();
+
+    world.write_resource::<EventBus>().extend(events);
+
+    // Nothing runs locally, the tick is only to show where we're at
+    let mut tc = world.write_resource::<TickCoordinator>();
+    tc.reset(state.tick);
+    tc.hold();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::simulation;
+
+    fn state(tick: usize, entities: Value) -> WorldState {
+        WorldState {
+            tick,
+            entities: serde_json::from_value(entities).unwrap(),
+        }
+    }
+
+    fn spawned_and_despawned(world: &World) -> (usize, usize) {
+        let events = world.write_resource::<EventBus>().take();
+        let spawned = events
+            .iter()
+            .filter(|e| matches!(e, EngineEvent::EntitySpawned { .. }))
+            .count();
+        (spawned, events.len() - spawned)
+    }
+
+    #[test]
+    fn following_reports_what_came_and_went() {
+        let server = simulation::init_world(1).unwrap();
+        let first = WorldState::capture(&server);
+        let mut second = WorldState {
+            tick: first.tick + STATE_INTERVAL,
+            ..first.clone()
+        };
+        second.entities.pop_first();
+
+        // Starting out empty, to have everything spawned
+        let mut client = simulation::init_world(1).unwrap();
+        client.delete_all();
+        client.maintain();
+        client.write_resource::<EventBus>().take();
+        let mut state_sync = StateSync::new(&client);
+
+        state_sync.receive(first.delta_from(None)).unwrap();
+        state_sync.update(&mut client, 1000.).unwrap();
+        assert_eq!(spawned_and_despawned(&client), (first.entities.len(), 0));
+
+        state_sync.receive(second.delta_from(Some(&first))).unwrap();
+        state_sync.update(&mut client, 1000.).unwrap();
+        assert_eq!(spawned_and_despawned(&client), (0, 1));
+        assert_eq!(client.entities().join().count(), second.entities.len());
+    }
+
+    #[test]
+    fn deltas_lead_to_the_state_they_were_taken_from() {
+        let baseline = state(
+            3,
+            json!({
+                "1": { "Position": { "x": 1.5, "y": 2 }, "Velocity": { "vx": 0, "vy": 0 } },
+                "2": { "Position": { "x": 0, "y": 0 } },
+                "3": { "Position": { "x": 9, "y": 9 }, "Color": [1, 2, 3, 4] },
+            }),
+        );
+        let current = state(
+            6,
+            json!({
+                "1": { "Position": { "x": 1.5, "y": 2.25 } },
+                "3": { "Position": { "x": 9, "y": 9 }, "Color": [1, 2, 3, 4] },
+                "4": { "Position": { "x": 5, "y": 5 } },
+            }),
+        );
+
+        let delta = current.delta_from(Some(&baseline));
+        assert_eq!(delta.baseline, Some(3));
+        assert_eq!(delta.despawned, vec![2]);
+        assert_eq!(delta.removed, vec![(1, vec!["Velocity".to_owned()])]);
+        assert_eq!(
+            delta.changed.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![1, 4],
+            "entity 3 didn't change, so shouldn't be sent"
+        );
+        assert_eq!(baseline.apply(&delta), current);
+
+        let everything = current.delta_from(None);
+        assert_eq!(everything.baseline, None);
+        assert!(everything.removed.is_empty() && everything.despawned.is_empty());
+        assert_eq!(WorldState::default().apply(&everything), current);
+
+        // Nothing changed, nothing to send
+        let same = current.delta_from(Some(&current));
+        assert!(same.changed.is_empty() && same.removed.is_empty() && same.despawned.is_empty());
+    }
+
+    #[test]
+    fn quantize_rounds_fractions_only() {
+        let mut value = json!({
+            "x": 0.3,
+            "y": -1.01,
+            "nested": [0.5, 7, { "z": 100.999 }],
+            "count": 12345,
+            "name": "ball",
+        });
+        quantize(&mut value);
+
+        assert_eq!(
+            value,
+            json!({
+                "x": 19. * QUANTUM,
+                "y": -65. * QUANTUM,
+                "nested": [0.5, 7, { "z": 101. }],
+                "count": 12345,
+                "name": "ball",
+            })
+        );
+        assert!(value["count"].is_u64());
+    }
+
+    #[test]
+    fn receive_drops_stale_states_and_needs_the_baseline() {
+        let mut state_sync = StateSync::new(&World::new());
+        let first = state(3, json!({ "1": { "Position": { "x": 0, "y": 0 } } }));
+        let second = state(6, json!({ "1": { "Position": { "x": 1, "y": 0 } } }));
+
+        assert_eq!(state_sync.receive(first.delta_from(None)).unwrap(), Some(3));
+        assert_eq!(
+            state_sync.receive(second.delta_from(Some(&first))).unwrap(),
+            Some(6)
+        );
+        assert_eq!(state_sync.received[&6], second);
+
+        // Arriving after something newer
+        assert_eq!(state_sync.receive(first.delta_from(None)).unwrap(), None);
+        assert_eq!(
+            state_sync.receive(second.delta_from(Some(&first))).unwrap(),
+            None
+        );
+        assert_eq!(state_sync.newest(), Some(6));
+
+        // Building on a state we never had
+        let unknown = state(1, json!({}));
+        let third = state(9, json!({}));
+        assert!(matches!(
+            state_sync.receive(third.delta_from(Some(&unknown))),
+            Err(EngineError::Protocol(_))
+        ));
+        assert_eq!(state_sync.newest(), Some(6));
+    }
+}
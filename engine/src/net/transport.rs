@@ -0,0 +1,255 @@
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+
+use serde::Serialize;
+
+/// Where a connection to the server is at
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+    /// Trying to connect for the first time
+    Connecting,
+    Open,
+    /// Lost the connection, and trying to get it back
+    Reconnecting,
+    /// Gone for good
+    Closed,
+}
+
+impl ConnectionState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Connecting => "connecting",
+            Self::Open => "open",
+            Self::Reconnecting => "reconnecting",
+            Self::Closed => "closed",
+        }
+    }
+}
+
+/// Carries encoded messages to and from the other end, one at a time and in order
+pub trait Transport {
+    /// Sends one message, only called while open
+    fn send(&mut self, message: &str);
+
+    /// The next message that arrived, if any, without waiting
+    fn receive(&mut self) -> Option<String>;
+
+    fn state(&self) -> ConnectionState;
+
+    /// Hangs up for good
+    fn close(&mut self);
+}
+
+/// One end of a connection within the same process, open until either end is closed or dropped
+pub struct MemorySocket {
+    outgoing: Sender<String>,
+    incoming: Receiver<String>,
+    closed: bool,
+}
+
+impl MemorySocket {
+    /// Both ends of a new connection
+    pub fn pair() -> (Self, Self) {
+        let (a_out, b_in) = mpsc::channel();
+        let (b_out, a_in) = mpsc::channel();
+
+        (
+            Self {
+                outgoing: a_out,
+                incoming: a_in,
+                closed: false,
+            },
+            Self {
+                outgoing: b_out,
+                incoming: b_in,
+                closed: false,
+            },
+        )
+    }
+
+    /// The channels underneath, for ends that are driven by other threads
+    pub fn into_channels(self) -> (Sender<String>, Receiver<String>) {
+        (self.outgoing, self.incoming)
+    }
+}
+
+impl Transport for MemorySocket {
+    fn send(&mut self, message: &str) {
+        if self.outgoing.send(message.to_owned()).is_err() {
+            self.closed = true;
+        }
+    }
+
+    fn receive(&mut self) -> Option<String> {
+        match self.incoming.try_recv() {
+            Ok(message) => Some(message),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.closed = true;
+                None
+            }
+        }
+    }
+
+    fn state(&self) -> ConnectionState {
+        if self.closed {
+            ConnectionState::Closed
+        } else {
+            ConnectionState::Open
+        }
+    }
+
+    fn close(&mut self) {
+        // The other end notices once our sender is gone
+        self.outgoing = mpsc::channel().0;
+        self.closed = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        action::{Action, Direction, FixedPoint, PlayerAction},
+        net::{
+            ClientMessage, LobbyPhase, LobbyPlayer, LobbyState, RefuseReason, ServerMessage,
+            SyncMode, TickBundle, WorldState,
+        },
+        resources::{Prefab, RejectReason},
+        simulation::init_world,
+        snapshot::Snapshot,
+    };
+
+    fn client_messages() -> Vec<ClientMessage> {
+        vec![
+            ClientMessage::Join {
+                protocol: 3,
+                engine: "1.2.3".to_owned(),
+                name: "ünïcode \"quoted\"".to_owned(),
+            },
+            ClientMessage::Ready { ready: true },
+            ClientMessage::Kick { player: 7 },
+            ClientMessage::Actions {
+                seq: u32::MAX,
+                tick: 120,
+                actions: vec![
+                    Action::StartMoving {
+                        dir: Direction::Up | Direction::Left,
+                    },
+                    Action::MoveAnalog {
+                        x: FixedPoint::from_num(-0.5),
+                        y: FixedPoint::from_num(0.25),
+                    },
+                    Action::Fire,
+                    Action::Spawn {
+                        prefab: "emitter".to_owned(),
+                        overrides: Prefab::new(),
+                    },
+                ],
+            },
+            ClientMessage::StateHash {
+                tick: 60,
+                hash: u64::MAX,
+            },
+            ClientMessage::Ping { sent: 1234.5 },
+            ClientMessage::Resend { tick: 0 },
+            ClientMessage::Ack { tick: 90 },
+        ]
+    }
+
+    fn server_messages() -> Vec<ServerMessage> {
+        let world = init_world(1).unwrap();
+
+        vec![
+            ServerMessage::Welcome {
+                player: 2,
+                snapshot: Snapshot::take(&world),
+                live_tick: 300,
+                sync: SyncMode::State,
+            },
+            ServerMessage::Lobby(LobbyState {
+                phase: LobbyPhase::Countdown { seconds: 3 },
+                host: Some(1),
+                players: vec![LobbyPlayer {
+                    id: 1,
+                    name: "host".to_owned(),
+                    ready: true,
+                }],
+            }),
+            ServerMessage::Refused(RefuseReason::VersionMismatch {
+                protocol: 3,
+                engine: "1.2.3".to_owned(),
+            }),
+            ServerMessage::Refused(RefuseReason::Kicked),
+            ServerMessage::Bundle(TickBundle {
+                tick: 5,
+                actions: vec![PlayerAction {
+                    player: 1,
+                    action: Action::Jump,
+                }],
+            }),
+            ServerMessage::State(WorldState::capture(&world).delta_from(None)),
+            ServerMessage::Rejected {
+                tick: 4,
+                reason: RejectReason::FireRateLimited,
+            },
+            ServerMessage::Desync {
+                tick: 60,
+                expected: 42,
+            },
+            ServerMessage::Pong {
+                sent: 1234.5,
+                time: 98765.25,
+                tick: 10,
+            },
+        ]
+    }
+
+    #[test]
+    fn client_messages_survive_the_trip() {
+        let (mut client, mut server) = MemorySocket::pair();
+
+        let messages = client_messages();
+        for message in &messages {
+            client.send(&message.encode().unwrap());
+        }
+
+        let received: Vec<_> = std::iter::from_fn(|| server.receive())
+            .map(|text| ClientMessage::decode(&text).unwrap())
+            .collect();
+        assert_eq!(received, messages);
+    }
+
+    #[test]
+    fn server_messages_survive_the_trip() {
+        let (mut client, mut server) = MemorySocket::pair();
+
+        let messages = server_messages();
+        for message in &messages {
+            server.send(&message.encode().unwrap());
+        }
+
+        let received: Vec<_> = std::iter::from_fn(|| client.receive())
+            .map(|text| ServerMessage::decode(&text).unwrap())
+            .collect();
+        assert_eq!(received, messages);
+    }
+
+    #[test]
+    fn garbage_is_a_protocol_error() {
+        assert!(ClientMessage::decode("{\"type\":\"teleport\"}").is_err());
+        assert!(ServerMessage::decode("not json").is_err());
+    }
+
+    #[test]
+    fn closing_one_end_closes_the_other() {
+        let (mut a, mut b) = MemorySocket::pair();
+        a.send("before");
+        a.close();
+
+        assert_eq!(a.state(), ConnectionState::Closed);
+        assert_eq!(b.receive().as_deref(), Some("before"));
+        assert_eq!(b.receive(), None);
+        assert_eq!(b.state(), ConnectionState::Closed);
+    }
+}
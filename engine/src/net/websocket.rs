@@ -0,0 +1,184 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    rc::{Rc, Weak},
+};
+
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{MessageEvent, WebSocket};
+
+use super::{ConnectionState, Transport};
+use crate::error::EngineError;
+
+/// Milliseconds to wait before the first attempt at reconnecting
+const INITIAL_BACKOFF: f64 = 250.;
+
+/// Backoff doubles with each failed attempt, up to this
+const MAX_BACKOFF: f64 = 10_000.;
+
+/// A browser WebSocket carrying one message per text frame,
+/// which reconnects with exponential backoff whenever it drops
+pub struct WebSocketTransport {
+    shared: Rc<RefCell<Shared>>,
+}
+
+/// State shared with the socket's event handlers
+struct Shared {
+    url: String,
+    socket: Option<WebSocket>,
+    state: ConnectionState,
+    incoming: VecDeque<String>,
+    backoff: f64,
+    handlers: Option<Handlers>,
+}
+
+/// Kept for as long as any socket may call them
+struct Handlers {
+    on_open: Closure<dyn FnMut()>,
+    on_message: Closure<dyn FnMut(MessageEvent)>,
+    on_close: Closure<dyn FnMut()>,
+}
+
+impl WebSocketTransport {
+    /// Starts connecting to `url`, e.g. `ws://localhost:7879`
+    pub fn open(url: &str) -> Result<Self, EngineError> {
+        let shared = Rc::new(RefCell::new(Shared {
+            url: url.to_owned(),
+            socket: None,
+            state: ConnectionState::Connecting,
+            incoming: VecDeque::new(),
+            backoff: INITIAL_BACKOFF,
+            handlers: None,
+        }));
+        shared.borrow_mut().handlers = Some(Handlers::new(Rc::downgrade(&shared)));
+
+        connect(&shared)?;
+
+        Ok(Self { shared })
+    }
+}
+
+impl Handlers {
+    fn new(shared: Weak<RefCell<Shared>>) -> Self {
+        let weak = shared.clone();
+        let on_open = Closure::new(move || {
+            if let Some(shared) = weak.upgrade() {
+                let mut shared = shared.borrow_mut();
+                shared.state = ConnectionState::Open;
+                shared.backoff = INITIAL_BACKOFF;
+            }
+        });
+
+        let weak = shared.clone();
+        let on_message = Closure::new(move |event: MessageEvent| {
+            if let (Some(shared), Some(text)) = (weak.upgrade(), event.data().as_string()) {
+                shared.borrow_mut().incoming.push_back(text);
+            }
+        });
+
+        let on_close = Closure::new(move || {
+            if let Some(shared) = shared.upgrade() {
+                reconnect_later(&shared);
+            }
+        });
+
+        Self {
+            on_open,
+            on_message,
+            on_close,
+        }
+    }
+}
+
+/// Opens a new socket in place of the last one
+fn connect(shared: &Rc<RefCell<Shared>>) -> Result<(), EngineError> {
+    let mut shared = shared.borrow_mut();
+    let socket = WebSocket::new(&shared.url)?;
+
+    let handlers = shared.handlers.as_ref().unwrap();
+    socket.set_onopen(Some(handlers.on_open.as_ref().unchecked_ref()));
+    socket.set_onmessage(Some(handlers.on_message.as_ref().unchecked_ref()));
+    socket.set_onclose(Some(handlers.on_close.as_ref().unchecked_ref()));
+
+    shared.socket = Some(socket);
+    Ok(())
+}
+
+/// Tries connecting again after the current backoff, doubling it for next time
+fn reconnect_later(shared: &Rc<RefCell<Shared>>) {
+    let delay = {
+        let mut shared = shared.borrow_mut();
+        if shared.state == ConnectionState::Closed {
+            return;
+        }
+
+        shared.state = ConnectionState::Reconnecting;
+        shared.incoming.clear();
+        let delay = shared.backoff;
+        shared.backoff = (delay * 2.).min(MAX_BACKOFF);
+        delay
+    };
+
+    // Holding on to nothing, so a transport dropped in the meantime stays dropped
+    let weak = Rc::downgrade(shared);
+    let retry = Closure::once_into_js(move || {
+        if let Some(shared) = weak.upgrade() {
+            if connect(&shared).is_err() {
+                reconnect_later(&shared);
+            }
+        }
+    });
+
+    let scheduled = web_sys::window().map(|window| {
+        window.set_timeout_with_callback_and_timeout_and_arguments_0(
+            retry.unchecked_ref(),
+            delay as i32,
+        )
+    });
+    if !matches!(scheduled, Some(Ok(_))) {
+        shared.borrow_mut().state = ConnectionState::Closed;
+    }
+}
+
+impl Transport for WebSocketTransport {
+    fn send(&mut self, message: &str) {
+        if let Some(socket) = &self.shared.borrow().socket {
+            // Failing here means the socket is closing, which its close handler deals with
+            let _ = socket.send_with_str(message);
+        }
+    }
+
+    fn receive(&mut self) -> Option<String> {
+        self.shared.borrow_mut().incoming.pop_front()
+    }
+
+    fn state(&self) -> ConnectionState {
+        self.shared.borrow().state
+    }
+
+    fn close(&mut self) {
+        let mut shared = self.shared.borrow_mut();
+        shared.state = ConnectionState::Closed;
+        shared.incoming.clear();
+
+        // Its close handler sees we're closed, so won't reconnect
+        if let Some(socket) = &shared.socket {
+            let _ = socket.close();
+        }
+    }
+}
+
+impl Drop for WebSocketTransport {
+    fn drop(&mut self) {
+        let mut shared = self.shared.borrow_mut();
+        shared.state = ConnectionState::Closed;
+
+        // The handlers are about to go, so the socket mustn't call them anymore
+        if let Some(socket) = shared.socket.take() {
+            socket.set_onopen(None);
+            socket.set_onmessage(None);
+            socket.set_onclose(None);
+            let _ = socket.close();
+        }
+    }
+}
@@ -1,31 +1,34 @@
 use wasm_bindgen::prelude::*;
+
+use crate::error::EngineError;
 use web_sys::{
     console, WebGl2RenderingContext, WebGlBuffer, WebGlProgram, WebGlShader, WebGlUniformLocation,
     WebGlVertexArrayObject,
 };
 
-pub fn init_renderer(canvas: &web_sys::HtmlCanvasElement) -> Option<Renderer> {
+pub fn init_renderer(canvas: &web_sys::HtmlCanvasElement) -> Result<Renderer, EngineError> {
     let context = canvas
-        .get_context("webgl2")
-        .unwrap()?
+        .get_context("webgl2")?
+        .ok_or(EngineError::WebGl2Unavailable)?
         .dyn_into::<WebGl2RenderingContext>()
-        .unwrap();
+        .map_err(|_| EngineError::WebGl2Unavailable)?;
 
     let vert_shader = compile_shader(
         &context,
         WebGl2RenderingContext::VERTEX_SHADER,
         include_str!("./shaders/circle_test.vert"),
     )
-    .expect("Vertex shader should compile");
+    .map_err(|e| EngineError::Renderer(format!("vertex shader: {e}")))?;
 
     let frag_shader = compile_shader(
         &context,
         WebGl2RenderingContext::FRAGMENT_SHADER,
         include_str!("./shaders/circle_test.frag"),
     )
-    .expect("Fragment shader should compile");
+    .map_err(|e| EngineError::Renderer(format!("fragment shader: {e}")))?;
 
-    let program = link_program(&context, &vert_shader, &frag_shader).unwrap();
+    let program =
+        link_program(&context, &vert_shader, &frag_shader).map_err(EngineError::Renderer)?;
 
     context.use_program(Some(&program));
 
@@ -38,10 +41,10 @@ pub fn init_renderer(canvas: &web_sys::HtmlCanvasElement) -> Option<Renderer> {
 
     // Setup attributes
     let position_attribute_location = context.get_attrib_location(&program, "position") as u32;
-    let color_uniform_location = get_uniform_location(&context, &program, "u_color");
-    let resolution_uniform_location = get_uniform_location(&context, &program, "u_resolution");
-    let center_uniform_location = get_uniform_location(&context, &program, "u_center");
-    let radius_uniform_location = get_uniform_location(&context, &program, "u_radius");
+    let color_uniform_location = get_uniform_location(&context, &program, "u_color")?;
+    let resolution_uniform_location = get_uniform_location(&context, &program, "u_resolution")?;
+    let center_uniform_location = get_uniform_location(&context, &program, "u_center")?;
+    let radius_uniform_location = get_uniform_location(&context, &program, "u_radius")?;
 
     context.uniform2fv_with_f32_array(
         Some(&resolution_uniform_location),
@@ -53,8 +56,7 @@ pub fn init_renderer(canvas: &web_sys::HtmlCanvasElement) -> Option<Renderer> {
     // Setup VAO
     let vao = context
         .create_vertex_array()
-        .ok_or("Could not create vertex array object")
-        .unwrap();
+        .ok_or_else(|| EngineError::Renderer("Could not create vertex array object".to_owned()))?;
     context.bind_vertex_array(Some(&vao));
 
     // Attach buffers to VAO
@@ -66,10 +68,10 @@ pub fn init_renderer(canvas: &web_sys::HtmlCanvasElement) -> Option<Renderer> {
         false,
         0,
         0,
-    );
+    )?;
     //let color_buffer = attach_buffer(&context, color_uniform_location, 4, WebGl2RenderingContext::FLOAT, true, 0, 0);
 
-    Some(Renderer {
+    Ok(Renderer {
         context,
         program,
 
@@ -93,25 +95,24 @@ fn attach_buffer(
     normalized: bool,
     stride: i32,
     offset: i32,
-) -> WebGlBuffer {
+) -> Result<WebGlBuffer, EngineError> {
     let buffer = context
         .create_buffer()
-        .ok_or("Failed to create buffer")
-        .unwrap();
+        .ok_or_else(|| EngineError::Renderer("Failed to create buffer".to_owned()))?;
     context.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&buffer));
     context.enable_vertex_attrib_array(location);
     context.vertex_attrib_pointer_with_i32(location, size, field_type, normalized, stride, offset);
-    buffer
+    Ok(buffer)
 }
 
 fn get_uniform_location(
     context: &WebGl2RenderingContext,
     program: &WebGlProgram,
     name: &str,
-) -> WebGlUniformLocation {
+) -> Result<WebGlUniformLocation, EngineError> {
     context
         .get_uniform_location(program, name)
-        .expect(&("Should have ".to_owned() + name))
+        .ok_or_else(|| EngineError::Renderer(format!("Missing uniform {name}")))
 }
 
 pub struct Renderer {
@@ -1,2 +1,29 @@
 mod res_tick_coordinator;
 pub use res_tick_coordinator::TickCoordinator;
+
+mod res_rng;
+pub use res_rng::Rng;
+
+mod res_prefabs;
+pub use res_prefabs::{Prefab, Prefabs};
+
+mod res_errors;
+pub use res_errors::Errors;
+
+mod res_event_bus;
+pub use res_event_bus::{EngineEvent, EventBus};
+
+mod res_action_delay;
+pub use res_action_delay::{ActionDelay, TICKS_PER_SECOND, TICK_MS};
+
+mod res_action_validator;
+pub use res_action_validator::{controls_entity, ActionValidator, RejectReason};
+
+mod res_local_player;
+pub use res_local_player::LocalPlayer;
+
+mod res_prediction;
+pub use res_prediction::Prediction;
+
+mod res_net_stats;
+pub use res_net_stats::NetStats;
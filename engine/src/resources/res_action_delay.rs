@@ -0,0 +1,168 @@
+use super::res_tick_coordinator::ACTION_QUEUE_SLOTS;
+
+/// Ticks per second at normal speed, one per animation frame
+pub const TICKS_PER_SECOND: usize = 60;
+
+/// How long a tick lasts at normal speed
+pub const TICK_MS: f64 = 1000. / TICKS_PER_SECOND as f64;
+
+/// Used until we have measured anything
+const DEFAULT_DELAY: usize = 5;
+
+/// Even with no latency at all, an action can't land on the tick it was made in
+const MIN_DELAY: usize = 1;
+
+/// The furthest ahead the action queue can hold, leaving one slot for the current tick
+const MAX_DELAY: usize = ACTION_QUEUE_SLOTS - 1;
+
+/// Decides how many ticks ahead our own actions are scheduled, so that they
+/// reach every peer before they are due. Calibrated from round trip times,
+/// smoothed the same way TCP estimates its retransmission timeout, or from
+/// the estimates clock sync makes while connected to a server.
+pub struct ActionDelay {
+    /// Smoothed round trip time, in milliseconds
+    pub round_trip: f64,
+
+    /// Smoothed deviation of the round trip time, in milliseconds
+    pub jitter: f64,
+
+    /// Ticks between an input and the tick its actions apply on
+    pub ticks: usize,
+
+    /// Smoothed time inputs waited for the frame that handled them, in milliseconds.
+    /// Only kept track of, as it's the same wherever the input came from.
+    pub input_age: f64,
+
+    samples: usize,
+}
+
+impl ActionDelay {
+    pub fn new() -> Self {
+        Self {
+            round_trip: 0.,
+            jitter: 0.,
+            ticks: DEFAULT_DELAY,
+            input_age: 0.,
+            samples: 0,
+        }
+    }
+
+    /// Folds in a measured round trip time, in milliseconds
+    pub fn record_round_trip(&mut self, rtt: f64) {
+        if !rtt.is_finite() || rtt < 0. {
+            return;
+        }
+
+        if self.samples == 0 {
+            self.round_trip = rtt;
+            self.jitter = rtt / 2.;
+        } else {
+            self.jitter = 0.75 * self.jitter + 0.25 * (self.round_trip - rtt).abs();
+            self.round_trip = 0.875 * self.round_trip + 0.125 * rtt;
+        }
+        self.samples += 1;
+
+        self.recalibrate();
+    }
+
+    /// Takes round trip estimates that were already smoothed, such as clock
+    /// sync's, as they are rather than smoothing them again
+    pub fn set_estimate(&mut self, round_trip: f64, jitter: f64) {
+        if !round_trip.is_finite() || !jitter.is_finite() || round_trip < 0. {
+            return;
+        }
+
+        self.round_trip = round_trip;
+        self.jitter = jitter.max(0.);
+        self.samples += 1;
+
+        self.recalibrate();
+    }
+
+    fn recalibrate(&mut self) {
+        // We run half a round trip behind the server, as that's how long its bundles
+        // take to reach us, and our actions take the other half to reach it
+        let lead = self.round_trip + 4. * self.jitter;
+        self.ticks = ((lead / TICK_MS).ceil() as usize + 1).clamp(MIN_DELAY, MAX_DELAY);
+    }
+
+    /// The tick for the actions of an input made on `current_tick`
+    pub fn target_tick(&self, current_tick: usize) -> usize {
+        current_tick + self.ticks
+    }
+
+    /// Folds in how long ago an input happened when it was handled, in milliseconds
+    pub fn record_input_age(&mut self, age: f64) {
+        if age.is_finite() && age >= 0. {
+            self.input_age += 0.125 * (age - self.input_age);
+        }
+    }
+}
+
+impl Default for ActionDelay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_follows_the_round_trip() {
+        let mut delay = ActionDelay::new();
+        assert_eq!(delay.ticks, DEFAULT_DELAY);
+
+        delay.record_round_trip(0.);
+        assert_eq!(delay.ticks, MIN_DELAY);
+
+        // 90ms is 5.4 ticks, rounded up, plus the tick we're on
+        delay.set_estimate(90., 0.);
+        assert_eq!(delay.ticks, 7);
+
+        // Jitter counts four times over
+        delay.set_estimate(90., 10.);
+        assert_eq!(delay.ticks, 9);
+
+        delay.set_estimate(1e9, 0.);
+        assert_eq!(delay.ticks, ACTION_QUEUE_SLOTS - 1);
+    }
+
+    #[test]
+    fn round_trips_are_smoothed() {
+        let mut delay = ActionDelay::new();
+
+        // The first sample is taken as it is, with half of it as jitter
+        delay.record_round_trip(100.);
+        assert_eq!((delay.round_trip, delay.jitter), (100., 50.));
+
+        delay.record_round_trip(200.);
+        assert_eq!((delay.round_trip, delay.jitter), (112.5, 62.5));
+
+        // Already smoothed estimates are taken as they are
+        delay.set_estimate(40., -5.);
+        assert_eq!((delay.round_trip, delay.jitter), (40., 0.));
+    }
+
+    #[test]
+    fn nonsense_is_ignored() {
+        let mut delay = ActionDelay::new();
+        for rtt in [f64::NAN, f64::INFINITY, -1.] {
+            delay.record_round_trip(rtt);
+            delay.set_estimate(rtt, 0.);
+        }
+        delay.set_estimate(50., f64::NAN);
+        assert_eq!((delay.round_trip, delay.ticks), (0., DEFAULT_DELAY));
+
+        // So the first real sample is still taken as the first
+        delay.record_round_trip(60.);
+        assert_eq!((delay.round_trip, delay.jitter), (60., 30.));
+
+        delay.record_input_age(f64::NAN);
+        delay.record_input_age(-3.);
+        assert_eq!(delay.input_age, 0.);
+        delay.record_input_age(80.);
+        assert_eq!(delay.input_age, 10.);
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+
+use super::res_tick_coordinator::ACTION_QUEUE_SLOTS;
+use super::TickCoordinator;
+use crate::{
+    action::{Action, FixedPoint, PlayerId},
+    components::{control::Owner, physics::MovementReceiver},
+    math::FixedVec2,
+};
+
+/// How many rejections are kept around for inspection
+const RECENT_REJECTIONS: usize = 64;
+
+/// Why an action was refused
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectReason {
+    /// Scheduled for a tick that has already been run, or too far ahead
+    TickOutOfWindow,
+
+    /// Fired again before the cooldown ran out
+    FireRateLimited,
+
+    /// Cursor placed outside the world
+    CursorOutOfBounds,
+
+    /// Values no real input could produce, such as a stick pushed past its edge
+    Malformed,
+
+    /// The player controls no entity the action could apply to
+    NotOwned,
+
+    /// The action is reserved for the authority
+    NotPermitted,
+}
+
+/// A refused action, as kept for the log
+#[derive(Clone, PartialEq, Debug, Serialize)]
+pub struct Rejection {
+    pub player: PlayerId,
+    pub tick: usize,
+    pub reason: RejectReason,
+}
+
+/// What counts as a plausible action
+pub struct ValidationRules {
+    /// Ticks a player must wait between shots
+    pub fire_cooldown: usize,
+
+    /// Cursor positions must lie within this distance of the origin on each axis
+    pub world_extent: FixedPoint,
+
+    /// Furthest ahead of the current tick an action may be scheduled
+    pub max_lead: usize,
+
+    /// Whether players may spawn prefabs, otherwise only the host can
+    pub player_spawns: bool,
+}
+
+impl Default for ValidationRules {
+    fn default() -> Self {
+        Self {
+            fire_cooldown: 10,
+            world_extent: FixedPoint::from_num(4096),
+            max_lead: ACTION_QUEUE_SLOTS - 1,
+            player_spawns: false,
+        }
+    }
+}
+
+/// Counts of what was let through and what was refused, by reason
+#[derive(Clone, Default, Debug, Serialize)]
+pub struct ValidationStats {
+    pub accepted: u64,
+    pub rejected: BTreeMap<RejectReason, u64>,
+}
+
+/// Checks actions received from players before they are queued,
+/// so the authority never applies anything malformed or impossible
+#[derive(Default)]
+pub struct ActionValidator {
+    pub rules: ValidationRules,
+    pub stats: ValidationStats,
+
+    /// The most recent rejections, oldest first
+    pub recent: VecDeque<Rejection>,
+
+    /// The tick of each player's last accepted shot
+    last_fire: BTreeMap<PlayerId, usize>,
+}
+
+impl ActionValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Validates an action and queues it if it passes, counting the outcome either way.
+    /// `controls_entity` is whether the player owns something their actions can move.
+    pub fn submit(
+        &mut self,
+        tc: &mut TickCoordinator,
+        player: PlayerId,
+        action: Action,
+        tick: usize,
+        controls_entity: bool,
+    ) -> Result<(), Rejection> {
+        let result = self
+            .check(player, &action, tick, tc.current_tick, controls_entity)
+            .and_then(|()| {
+                let is_fire = action == Action::Fire;
+                tc.enqueue_action(player, action, tick)
+                    .map_err(|_| RejectReason::TickOutOfWindow)?;
+
+                if is_fire {
+                    self.last_fire.insert(player, tick);
+                }
+                Ok(())
+            });
+
+        match result {
+            Ok(()) => {
+                self.stats.accepted += 1;
+                Ok(())
+            }
+            Err(reason) => {
+                *self.stats.rejected.entry(reason).or_default() += 1;
+
+                let rejection = Rejection {
+                    player,
+                    tick,
+                    reason,
+                };
+                if self.recent.len() == RECENT_REJECTIONS {
+                    self.recent.pop_front();
+                }
+                self.recent.push_back(rejection.clone());
+
+                Err(rejection)
+            }
+        }
+    }
+
+    /// Whether an action would be accepted, without queueing or counting it
+    pub fn check(
+        &self,
+        player: PlayerId,
+        action: &Action,
+        tick: usize,
+        current_tick: usize,
+        controls_entity: bool,
+    ) -> Result<(), RejectReason> {
+        if tick <= current_tick || tick > current_tick + self.rules.max_lead {
+            return Err(RejectReason::TickOutOfWindow);
+        }
+
+        match action {
+            Action::Spawn { .. } if !self.rules.player_spawns => Err(RejectReason::NotPermitted),
+            Action::Spawn { .. } => Ok(()),
+
+            Action::Leave => Err(RejectReason::NotPermitted),
+
+            _ if !controls_entity => Err(RejectReason::NotOwned),
+
+            Action::Fire => match self.last_fire.get(&player) {
+                Some(&last) if last.abs_diff(tick) < self.rules.fire_cooldown => {
+                    Err(RejectReason::FireRateLimited)
+                }
+                _ => Ok(()),
+            },
+
+            Action::Cursor { x, y } => {
+                let extent = self.rules.world_extent;
+                if x.abs() > extent || y.abs() > extent {
+                    Err(RejectReason::CursorOutOfBounds)
+                } else {
+                    Ok(())
+                }
+            }
+
+            Action::MoveAnalog { x, y } => {
+                // Quantized stick input may land a hair outside the unit circle
+                let slack = FixedPoint::from_num(1) + FixedPoint::DELTA * 64;
+                if FixedVec2::new(*x, *y).length() > slack {
+                    Err(RejectReason::Malformed)
+                } else {
+                    Ok(())
+                }
+            }
+
+            Action::StartMoving { .. } | Action::StopMoving { .. } | Action::Jump => Ok(()),
+        }
+    }
+}
+
+/// Whether a player owns an entity that movement and firing actions apply to
+pub fn controls_entity(
+    player: PlayerId,
+    owners: &ReadStorage<Owner>,
+    receivers: &ReadStorage<MovementReceiver>,
+) -> bool {
+    (owners, receivers)
+        .join()
+        .any(|(owner, _)| owner.player == player)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{action::Direction, resources::Prefab};
+
+    const PLAYER: PlayerId = 1;
+
+    fn fixed(value: f64) -> FixedPoint {
+        FixedPoint::from_num(value)
+    }
+
+    /// Checks an action for a tick a little ahead of the current one, from a player with an avatar
+    fn check(validator: &ActionValidator, action: Action) -> Result<(), RejectReason> {
+        validator.check(PLAYER, &action, 12, 10, true)
+    }
+
+    #[test]
+    fn ticks_must_lie_in_the_window() {
+        let validator = ActionValidator::new();
+        let max_lead = validator.rules.max_lead;
+
+        let at = |tick| validator.check(PLAYER, &Action::Jump, tick, 10, true);
+        assert_eq!(at(10), Err(RejectReason::TickOutOfWindow));
+        assert_eq!(at(9), Err(RejectReason::TickOutOfWindow));
+        assert_eq!(at(11), Ok(()));
+        assert_eq!(at(10 + max_lead), Ok(()));
+        assert_eq!(at(11 + max_lead), Err(RejectReason::TickOutOfWindow));
+    }
+
+    #[test]
+    fn fire_waits_for_the_cooldown() {
+        let mut validator = ActionValidator::new();
+        let mut tc = TickCoordinator::new();
+        let cooldown = validator.rules.fire_cooldown;
+
+        assert_eq!(
+            validator.submit(&mut tc, PLAYER, Action::Fire, 20, true),
+            Ok(())
+        );
+
+        let fire_at = |validator: &ActionValidator, tick| {
+            validator.check(PLAYER, &Action::Fire, tick, 0, true)
+        };
+        assert_eq!(
+            fire_at(&validator, 20 + cooldown - 1),
+            Err(RejectReason::FireRateLimited)
+        );
+        assert_eq!(fire_at(&validator, 20 + cooldown), Ok(()));
+
+        // Shots scheduled out of order still have to be a cooldown apart
+        assert_eq!(
+            fire_at(&validator, 20 - cooldown + 1),
+            Err(RejectReason::FireRateLimited)
+        );
+        assert_eq!(fire_at(&validator, 20 - cooldown), Ok(()));
+
+        // Other players keep their own cooldown
+        assert_eq!(validator.check(2, &Action::Fire, 21, 0, true), Ok(()));
+    }
+
+    #[test]
+    fn cursor_stays_in_the_world() {
+        let validator = ActionValidator::new();
+        let extent = validator.rules.world_extent;
+
+        let cursor = |x, y| check(&validator, Action::Cursor { x, y });
+        assert_eq!(cursor(extent, -extent), Ok(()));
+        assert_eq!(
+            cursor(extent + FixedPoint::DELTA, FixedPoint::ZERO),
+            Err(RejectReason::CursorOutOfBounds)
+        );
+        assert_eq!(
+            cursor(FixedPoint::ZERO, -extent - FixedPoint::DELTA),
+            Err(RejectReason::CursorOutOfBounds)
+        );
+    }
+
+    #[test]
+    fn analog_stays_in_the_unit_circle() {
+        let validator = ActionValidator::new();
+
+        let stick = |x, y| check(&validator, Action::MoveAnalog { x, y });
+        assert_eq!(stick(fixed(0.6), fixed(-0.8)), Ok(()));
+
+        // A hair outside is put down to quantization
+        assert_eq!(
+            stick(FixedPoint::ONE + FixedPoint::DELTA * 32, fixed(0.)),
+            Ok(())
+        );
+        assert_eq!(
+            stick(FixedPoint::ONE + FixedPoint::DELTA * 65, fixed(0.)),
+            Err(RejectReason::Malformed)
+        );
+        assert_eq!(stick(fixed(1.), fixed(1.)), Err(RejectReason::Malformed));
+    }
+
+    #[test]
+    fn controls_need_something_to_control() {
+        let validator = ActionValidator::new();
+
+        for action in [
+            Action::Jump,
+            Action::Fire,
+            Action::Cursor {
+                x: fixed(0.),
+                y: fixed(0.),
+            },
+            Action::StartMoving { dir: Direction::Up },
+        ] {
+            assert_eq!(check(&validator, action.clone()), Ok(()));
+            assert_eq!(
+                validator.check(PLAYER, &action, 12, 10, false),
+                Err(RejectReason::NotOwned)
+            );
+        }
+    }
+
+    #[test]
+    fn spawning_is_up_to_the_rules() {
+        let mut validator = ActionValidator::new();
+        let spawn = Action::Spawn {
+            prefab: "ball".to_owned(),
+            overrides: Prefab::new(),
+        };
+
+        assert_eq!(
+            check(&validator, spawn.clone()),
+            Err(RejectReason::NotPermitted)
+        );
+
+        validator.rules.player_spawns = true;
+        assert_eq!(check(&validator, spawn.clone()), Ok(()));
+        assert_eq!(validator.check(PLAYER, &spawn, 12, 10, false), Ok(()));
+
+        // Leaving is never up to the player
+        assert_eq!(
+            check(&validator, Action::Leave),
+            Err(RejectReason::NotPermitted)
+        );
+    }
+
+    #[test]
+    fn outcomes_are_counted() {
+        let mut validator = ActionValidator::new();
+        let mut tc = TickCoordinator::new();
+
+        assert_eq!(
+            validator.submit(&mut tc, PLAYER, Action::Fire, 5, true),
+            Ok(())
+        );
+        assert_eq!(
+            validator.submit(&mut tc, PLAYER, Action::Jump, 5, true),
+            Ok(())
+        );
+        assert_eq!(tc.action_queue[5].len(), 2);
+
+        let rejection = validator.submit(&mut tc, PLAYER, Action::Fire, 6, true);
+        assert_eq!(
+            rejection,
+            Err(Rejection {
+                player: PLAYER,
+                tick: 6,
+                reason: RejectReason::FireRateLimited,
+            })
+        );
+        assert_eq!(tc.action_queue[6].len(), 0);
+
+        for _ in 0..RECENT_REJECTIONS {
+            let _ = validator.submit(&mut tc, PLAYER, Action::Jump, 0, true);
+        }
+
+        assert_eq!(validator.stats.accepted, 2);
+        assert_eq!(validator.stats.rejected[&RejectReason::FireRateLimited], 1);
+        assert_eq!(
+            validator.stats.rejected[&RejectReason::TickOutOfWindow],
+            RECENT_REJECTIONS as u64
+        );
+
+        // Only the latest are kept, so the rate limited shot has dropped out
+        assert_eq!(validator.recent.len(), RECENT_REJECTIONS);
+        assert!(validator
+            .recent
+            .iter()
+            .all(|r| r.reason == RejectReason::TickOutOfWindow));
+    }
+}
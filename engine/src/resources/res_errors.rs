@@ -0,0 +1,15 @@
+use crate::error::EngineError;
+
+/// Errors raised by systems, passed on to the host once the frame is done
+#[derive(Default)]
+pub struct Errors(Vec<EngineError>);
+
+impl Errors {
+    pub fn push(&mut self, error: EngineError) {
+        self.0.push(error);
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = EngineError> + '_ {
+        self.0.drain(..)
+    }
+}
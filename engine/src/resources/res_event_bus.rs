@@ -0,0 +1,117 @@
+use serde::Serialize;
+
+use super::RejectReason;
+use crate::{
+    action::PlayerId,
+    net::{ConnectionState, LobbyState, RefuseReason},
+};
+
+/// Something that happened in the engine which the host may want to know about
+#[derive(Clone, PartialEq, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EngineEvent {
+    TickCompleted {
+        tick: usize,
+    },
+    EntitySpawned {
+        id: u32,
+    },
+    EntityDespawned {
+        id: u32,
+    },
+    /// Two drawn circles started overlapping, `a` has the lower id
+    Collision {
+        a: u32,
+        b: u32,
+    },
+    /// Our state hash disagreed with one reported for the same tick
+    Desync {
+        tick: usize,
+        local: u64,
+        remote: u64,
+    },
+    Error {
+        message: String,
+    },
+    /// A player's action failed validation and was dropped
+    ActionRejected {
+        player: PlayerId,
+        tick: usize,
+        reason: RejectReason,
+    },
+    /// The connection to the server opened, dropped or closed
+    ConnectionChanged {
+        state: ConnectionState,
+    },
+    /// Someone joined, left or readied up, or the countdown moved on
+    LobbyChanged {
+        lobby: LobbyState,
+    },
+    /// The server turned us away, or threw us out
+    Refused {
+        reason: RefuseReason,
+    },
+    /// Still fast-forwarding to where the session is at, sent every frame until caught up
+    CatchingUp {
+        tick: usize,
+        target: usize,
+        progress: f32,
+    },
+    /// Done fast-forwarding, and playing live from `tick`
+    CaughtUp {
+        tick: usize,
+    },
+}
+
+impl EngineEvent {
+    /// Every event type, as named in the `type` field
+    pub const TYPES: &'static [&'static str] = &[
+        "tick_completed",
+        "entity_spawned",
+        "entity_despawned",
+        "collision",
+        "desync",
+        "error",
+        "action_rejected",
+        "connection_changed",
+        "lobby_changed",
+        "refused",
+        "catching_up",
+        "caught_up",
+    ];
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::TickCompleted { .. } => "tick_completed",
+            Self::EntitySpawned { .. } => "entity_spawned",
+            Self::EntityDespawned { .. } => "entity_despawned",
+            Self::Collision { .. } => "collision",
+            Self::Desync { .. } => "desync",
+            Self::Error { .. } => "error",
+            Self::ActionRejected { .. } => "action_rejected",
+            Self::ConnectionChanged { .. } => "connection_changed",
+            Self::LobbyChanged { .. } => "lobby_changed",
+            Self::Refused { .. } => "refused",
+            Self::CatchingUp { .. } => "catching_up",
+            Self::CaughtUp { .. } => "caught_up",
+        }
+    }
+}
+
+/// Events raised during a frame, handed to the host in one batch once it's done
+#[derive(Default)]
+pub struct EventBus(Vec<EngineEvent>);
+
+impl EventBus {
+    pub fn push(&mut self, event: EngineEvent) {
+        self.0.push(event);
+    }
+
+    pub fn extend(&mut self, events: impl IntoIterator<Item = EngineEvent>) {
+        self.0.extend(events);
+    }
+
+    pub fn take(&mut self) -> Vec<EngineEvent> {
+        std::mem::take(&mut self.0)
+    }
+}
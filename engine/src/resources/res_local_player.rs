@@ -0,0 +1,5 @@
+use crate::action::PlayerId;
+
+/// The player whose input this peer reads
+#[derive(Default)]
+pub struct LocalPlayer(pub PlayerId);
@@ -0,0 +1,255 @@
+use std::collections::VecDeque;
+
+use serde::Serialize;
+
+/// How many of our latest pings loss is judged from
+const LOSS_WINDOW: usize = 20;
+
+/// Milliseconds after which a ping that wasn't answered counts as lost
+const PING_TIMEOUT: f64 = 2000.;
+
+/// Milliseconds between the samples kept for the graph
+const SAMPLE_INTERVAL: f64 = 100.;
+
+/// How many samples the graph shows
+const GRAPH_SAMPLES: usize = 120;
+
+/// Where the graph is drawn, from the bottom left corner of the canvas, and how big
+const GRAPH_INSET: f32 = 20.;
+const GRAPH_WIDTH: f32 = 240.;
+const GRAPH_HEIGHT: f32 = 100.;
+
+/// What the top of the graph stands for in each series
+const GRAPH_MAX_ROUND_TRIP: f64 = 500.;
+const GRAPH_MAX_TICKS_AHEAD: f64 = 30.;
+
+const DOT_RADIUS: f32 = 3.;
+const ROUND_TRIP_COLOR: [f32; 4] = [80., 200., 255., 220.];
+const JITTER_COLOR: [f32; 4] = [255., 220., 80., 220.];
+const TICKS_AHEAD_COLOR: [f32; 4] = [120., 255., 120., 220.];
+const BYTES_IN_COLOR: [f32; 4] = [255., 255., 255., 120.];
+const STALL_COLOR: [f32; 4] = [255., 60., 60., 255.];
+
+/// One point on the graph
+#[derive(Clone, Debug, Default)]
+struct Sample {
+    round_trip: f64,
+    jitter: f64,
+    ticks_ahead: usize,
+
+    /// Bytes received since the sample before
+    bytes_in: usize,
+
+    /// Whether the simulation waited on the horizon since the sample before
+    stalled: bool,
+}
+
+/// How healthy the connection to the server is, kept up to date by the
+/// `Connection` every frame. Times are in milliseconds.
+#[derive(Clone, Debug, Serialize)]
+pub struct NetStats {
+    /// Smoothed round trip time and its deviation, from pings
+    pub round_trip: f64,
+    pub jitter: f64,
+
+    /// Fraction of recent pings that never came back
+    pub loss: f64,
+
+    /// Everything sent and received, as encoded
+    pub bytes_in: usize,
+    pub bytes_out: usize,
+
+    /// How many ticks the horizon is ahead of the simulation, which can run that far
+    /// without waiting. Only counted in lockstep, as state sync doesn't simulate.
+    pub ticks_ahead: usize,
+
+    /// How often, and for how long in all, the simulation had to wait for `max_tick`
+    pub stalls: usize,
+    pub stalled_ms: f64,
+
+    /// Whether the graph is drawn over the game
+    #[serde(skip)]
+    pub overlay: bool,
+
+    /// When our latest pings were sent, and whether they were answered
+    #[serde(skip)]
+    pings: VecDeque<(f64, bool)>,
+
+    #[serde(skip)]
+    stalling: bool,
+    #[serde(skip)]
+    last_update: Option<f64>,
+
+    /// The graph so far, and the point being gathered for it
+    #[serde(skip)]
+    samples: VecDeque<Sample>,
+    #[serde(skip)]
+    sample: Sample,
+    #[serde(skip)]
+    last_sample: f64,
+}
+
+impl NetStats {
+    pub fn new() -> Self {
+        Self {
+            round_trip: 0.,
+            jitter: 0.,
+            loss: 0.,
+            bytes_in: 0,
+            bytes_out: 0,
+            ticks_ahead: 0,
+            stalls: 0,
+            stalled_ms: 0.,
+            overlay: false,
+            pings: VecDeque::new(),
+            stalling: false,
+            last_update: None,
+            samples: VecDeque::new(),
+            sample: Sample::default(),
+            last_sample: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Starts over for a new connection, still drawn if it was
+    pub fn reset(&mut self) {
+        *self = Self {
+            overlay: self.overlay,
+            ..Self::new()
+        };
+    }
+
+    pub fn received(&mut self, bytes: usize) {
+        self.bytes_in += bytes;
+        self.sample.bytes_in += bytes;
+    }
+
+    pub fn sent(&mut self, bytes: usize) {
+        self.bytes_out += bytes;
+    }
+
+    pub fn ping_sent(&mut self, sent: f64) {
+        if self.pings.len() == LOSS_WINDOW {
+            self.pings.pop_front();
+        }
+        self.pings.push_back((sent, false));
+    }
+
+    /// Notes the answer to the ping sent at `sent`, give or take a microsecond
+    /// as JSON doesn't always give back the very same float
+    pub fn pong_received(&mut self, sent: f64) {
+        if let Some(ping) = self
+            .pings
+            .iter_mut()
+            .find(|(time, _)| (time - sent).abs() < 0.001)
+        {
+            ping.1 = true;
+        }
+    }
+
+    /// Takes this frame's measurements, with `ticks_ahead` only in lockstep and
+    /// `stalled` whether the simulation ran out of ticks this frame
+    pub fn update(
+        &mut self,
+        now: f64,
+        round_trip: f64,
+        jitter: f64,
+        ticks_ahead: Option<usize>,
+        stalled: bool,
+    ) {
+        self.round_trip = round_trip;
+        self.jitter = jitter;
+        self.ticks_ahead = ticks_ahead.unwrap_or(0);
+
+        // Pings still on their way can't be judged yet
+        let (answered, lost) = self
+            .pings
+            .iter()
+            .filter(|(sent, answered)| *answered || now - sent >= PING_TIMEOUT)
+            .fold((0, 0), |(answered, lost), (_, ok)| {
+                if *ok {
+                    (answered + 1, lost)
+                } else {
+                    (answered, lost + 1)
+                }
+            });
+        if answered + lost > 0 {
+            self.loss = lost as f64 / (answered + lost) as f64;
+        }
+
+        let stalled = stalled && ticks_ahead.is_some();
+        if stalled {
+            if !self.stalling {
+                self.stalls += 1;
+            }
+            if let Some(last) = self.last_update {
+                self.stalled_ms += now - last;
+            }
+        }
+        self.stalling = stalled;
+        self.last_update = Some(now);
+
+        self.sample.round_trip = round_trip;
+        self.sample.jitter = jitter;
+        self.sample.ticks_ahead = self.ticks_ahead;
+        self.sample.stalled |= stalled;
+
+        if now - self.last_sample >= SAMPLE_INTERVAL {
+            self.last_sample = now;
+            if self.samples.len() == GRAPH_SAMPLES {
+                self.samples.pop_front();
+            }
+            self.samples.push_back(std::mem::take(&mut self.sample));
+        }
+    }
+
+    /// Circles to draw for the graph, as `(x, y, radius, color)` in canvas pixels
+    /// with y pointing up. Bytes received are scaled to the most in one sample.
+    pub fn shapes(&self) -> Vec<(f32, f32, f32, [f32; 4])> {
+        if !self.overlay {
+            return Vec::new();
+        }
+
+        let most_bytes = self.samples.iter().map(|s| s.bytes_in).max().unwrap_or(0);
+        let step = GRAPH_WIDTH / GRAPH_SAMPLES as f32;
+        let height = |fraction: f64| GRAPH_INSET + GRAPH_HEIGHT * fraction.clamp(0., 1.) as f32;
+
+        let mut shapes = Vec::new();
+        for (i, sample) in self.samples.iter().enumerate() {
+            let x = GRAPH_INSET + step * i as f32;
+
+            if most_bytes > 0 {
+                let fraction = sample.bytes_in as f64 / most_bytes as f64;
+                shapes.push((x, height(fraction), DOT_RADIUS, BYTES_IN_COLOR));
+            }
+            shapes.push((
+                x,
+                height(sample.ticks_ahead as f64 / GRAPH_MAX_TICKS_AHEAD),
+                DOT_RADIUS,
+                TICKS_AHEAD_COLOR,
+            ));
+            shapes.push((
+                x,
+                height(sample.jitter / GRAPH_MAX_ROUND_TRIP),
+                DOT_RADIUS,
+                JITTER_COLOR,
+            ));
+            shapes.push((
+                x,
+                height(sample.round_trip / GRAPH_MAX_ROUND_TRIP),
+                DOT_RADIUS,
+                ROUND_TRIP_COLOR,
+            ));
+            if sample.stalled {
+                shapes.push((x, GRAPH_INSET, 2. * DOT_RADIUS, STALL_COLOR));
+            }
+        }
+
+        shapes
+    }
+}
+
+impl Default for NetStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,339 @@
+use std::collections::BTreeMap;
+
+use specs::prelude::*;
+
+use crate::{
+    action::Action,
+    components::physics::{MovementReceiver, Position, Velocity},
+};
+
+/// Fraction of a correction still left to ease out after each frame
+const SMOOTHING: f32 = 0.85;
+
+/// Below this distance what's left of a correction is dropped
+const SNAP_DISTANCE: f32 = 0.01;
+
+/// Runs the local player's own movement ahead of the simulation, so that input
+/// shows straight away instead of once the tick it was scheduled for comes round.
+///
+/// Every frame starts over from the simulation's latest state and replays our
+/// actions still to come, so a wrong guess never lasts. When the simulation
+/// ends up somewhere other than predicted, the difference is eased out over a
+/// few frames rather than jumped. Only ever drawn: nothing here feeds back into
+/// the simulation, so state hashes are the same with or without it.
+pub struct Prediction {
+    pub enabled: bool,
+
+    /// How many ticks turned out differently than predicted
+    pub mispredictions: usize,
+
+    /// What we predicted for, and where it will be once our latest actions apply
+    entity: Option<Entity>,
+    predicted: Option<Position>,
+
+    /// Our own actions by the tick they are scheduled for, until that tick has run
+    inputs: BTreeMap<usize, Vec<Action>>,
+
+    /// Where we predicted the entity to be at the start of each tick still to run
+    history: BTreeMap<usize, Position>,
+
+    /// How far from the prediction it's drawn, left over from corrections
+    offset: (f32, f32),
+}
+
+impl Prediction {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            mispredictions: 0,
+            entity: None,
+            predicted: None,
+            inputs: BTreeMap::new(),
+            history: BTreeMap::new(),
+            offset: (0., 0.),
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.inputs.clear();
+            self.forget();
+        }
+    }
+
+    /// Notes actions of ours the simulation will apply on `tick`
+    pub fn record(&mut self, tick: usize, actions: &[Action]) {
+        if self.enabled {
+            self.inputs
+                .entry(tick)
+                .or_default()
+                .extend(actions.iter().cloned());
+        }
+    }
+
+    fn forget(&mut self) {
+        self.entity = None;
+        self.predicted = None;
+        self.history.clear();
+        self.offset = (0., 0.);
+    }
+
+    /// Checks the last prediction against the simulation, which is at the start of
+    /// `current_tick`, then predicts `ahead` ticks on from there for `controlled`,
+    /// the entity we move with its current state
+    pub fn update(
+        &mut self,
+        current_tick: usize,
+        ahead: usize,
+        controlled: Option<(Entity, &MovementReceiver, &Position, &Velocity)>,
+    ) {
+        // Those have been applied for real
+        self.inputs = self.inputs.split_off(&current_tick);
+
+        let Some((entity, receiver, position, velocity)) = controlled.filter(|_| self.enabled)
+        else {
+            self.forget();
+            return;
+        };
+        if self.entity != Some(entity) {
+            self.forget();
+            self.entity = Some(entity);
+        }
+
+        if let Some(expected) = self.history.get(&current_tick) {
+            if expected != position {
+                self.mispredictions += 1;
+                self.offset.0 += (expected.x - position.x).to_num::<f32>();
+                self.offset.1 += (expected.y - position.y).to_num::<f32>();
+            }
+        }
+        self.history.clear();
+
+        // The same steps as the simulation takes for this entity, minus anything
+        // outside our control, as far as the latest of our actions
+        let mut receiver = receiver.clone();
+        let mut position = position.clone();
+        let mut velocity = velocity.clone();
+        for tick in current_tick..=current_tick + ahead {
+            for action in self.inputs.get(&tick).into_iter().flatten() {
+                receiver.receive(action);
+            }
+            receiver.apply(&mut position, &mut velocity);
+            position.x += velocity.vx;
+            position.y += velocity.vy;
+
+            self.history.insert(tick + 1, position.clone());
+        }
+        self.predicted = Some(position);
+
+        self.offset.0 *= SMOOTHING;
+        self.offset.1 *= SMOOTHING;
+        if self.offset.0.hypot(self.offset.1) < SNAP_DISTANCE {
+            self.offset = (0., 0.);
+        }
+    }
+
+    /// Where to draw `entity` if it is the one predicted
+    pub fn drawn_position(&self, entity: Entity) -> Option<(f32, f32)> {
+        if self.entity != Some(entity) {
+            return None;
+        }
+
+        let predicted = self.predicted.as_ref()?;
+        Some((
+            predicted.x.to_num::<f32>() + self.offset.0,
+            predicted.y.to_num::<f32>() + self.offset.1,
+        ))
+    }
+}
+
+impl Default for Prediction {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        action::{Direction, FixedPoint},
+        resources::{LocalPlayer, TickCoordinator},
+        simulation,
+        state_hash::state_hash,
+        systems::SysPrediction,
+    };
+
+    struct Controlled {
+        entity: Entity,
+        receiver: MovementReceiver,
+        position: Position,
+        velocity: Velocity,
+    }
+
+    impl Controlled {
+        fn new(world: &mut World, x: f32) -> Self {
+            Self {
+                entity: world.create_entity().build(),
+                receiver: MovementReceiver::new(),
+                position: Position::new_f32(x, 0.),
+                velocity: Velocity::new_f32(0., 0.),
+            }
+        }
+
+        fn state(&self) -> Option<(Entity, &MovementReceiver, &Position, &Velocity)> {
+            Some((self.entity, &self.receiver, &self.position, &self.velocity))
+        }
+    }
+
+    fn enabled() -> Prediction {
+        let mut prediction = Prediction::new();
+        prediction.set_enabled(true);
+        prediction
+    }
+
+    #[test]
+    fn replays_our_inputs_ahead() {
+        let mut world = World::new();
+        let mut controlled = Controlled::new(&mut world, 0.);
+        let mut prediction = enabled();
+
+        prediction.update(0, 2, controlled.state());
+        assert_eq!(prediction.drawn_position(controlled.entity), Some((0., 0.)));
+
+        // Accelerating from tick 1 on, a tick at a time
+        prediction.record(
+            1,
+            &[Action::StartMoving {
+                dir: Direction::Right,
+            }],
+        );
+        prediction.update(0, 2, controlled.state());
+        assert_eq!(prediction.drawn_position(controlled.entity), Some((3., 0.)));
+        assert_eq!(prediction.mispredictions, 0);
+
+        // Once tick 1 ran, its inputs are the simulation's business, and if it
+        // went as predicted we keep speeding up from there
+        controlled.receiver.start_moving(Direction::Right);
+        controlled.position = Position::new_f32(1., 0.);
+        controlled.velocity = Velocity::new_f32(1., 0.);
+        prediction.update(2, 2, controlled.state());
+        assert!(prediction.inputs.is_empty());
+        assert_eq!(prediction.mispredictions, 0);
+        assert_eq!(
+            prediction.drawn_position(controlled.entity),
+            Some((10., 0.))
+        );
+    }
+
+    #[test]
+    fn mispredictions_are_eased_out() {
+        let mut world = World::new();
+        let mut controlled = Controlled::new(&mut world, 0.);
+        let mut prediction = enabled();
+
+        prediction.update(0, 0, controlled.state());
+        controlled.position = Position::new_f32(10., 0.);
+        prediction.update(1, 0, controlled.state());
+        assert_eq!(prediction.mispredictions, 1);
+
+        // Drawn where we thought, bar one frame of easing
+        let (x, _) = prediction.drawn_position(controlled.entity).unwrap();
+        assert!((x - 10. * (1. - SMOOTHING)).abs() < 1e-4, "drawn at {x}");
+
+        let mut last = x;
+        for tick in 2..100 {
+            prediction.update(tick, 0, controlled.state());
+            let (x, _) = prediction.drawn_position(controlled.entity).unwrap();
+            assert!(x >= last && x <= 10.);
+            last = x;
+        }
+        assert_eq!(last, 10., "should have snapped once within SNAP_DISTANCE");
+        assert_eq!(prediction.mispredictions, 1);
+    }
+
+    #[test]
+    fn forgets_on_a_new_entity_or_when_disabled() {
+        let mut world = World::new();
+        let mut first = Controlled::new(&mut world, 0.);
+        let second = Controlled::new(&mut world, 50.);
+        let mut prediction = enabled();
+
+        prediction.update(0, 0, first.state());
+        first.position = Position::new_f32(10., 0.);
+        prediction.update(1, 0, first.state());
+        assert_ne!(prediction.drawn_position(first.entity), Some((10., 0.)));
+
+        // The correction was for the old entity, it has nothing to do with this one
+        prediction.update(2, 0, second.state());
+        assert_eq!(prediction.drawn_position(first.entity), None);
+        assert_eq!(prediction.drawn_position(second.entity), Some((50., 0.)));
+
+        prediction.update(3, 0, None);
+        assert_eq!(prediction.drawn_position(second.entity), None);
+
+        prediction.set_enabled(false);
+        prediction.record(4, &[Action::Jump]);
+        prediction.update(3, 1, second.state());
+        assert_eq!(prediction.drawn_position(second.entity), None);
+        assert!(prediction.inputs.is_empty());
+    }
+
+    #[test]
+    fn never_changes_the_state_hash() {
+        let inputs = [
+            (
+                1,
+                Action::StartMoving {
+                    dir: Direction::Right,
+                },
+            ),
+            (4, Action::StartMoving { dir: Direction::Up }),
+            (
+                9,
+                Action::StopMoving {
+                    dir: Direction::Right,
+                },
+            ),
+        ];
+
+        let run = |predict: bool| {
+            let mut world = simulation::init_world(1).unwrap();
+            let mut dispatcher = simulation::init_dispatcher();
+            world.insert(LocalPlayer(0));
+            world.insert(enabled());
+
+            for (tick, action) in &inputs {
+                world
+                    .write_resource::<TickCoordinator>()
+                    .enqueue_action(0, action.clone(), *tick)
+                    .unwrap();
+                world
+                    .write_resource::<Prediction>()
+                    .record(*tick, &[action.clone()]);
+            }
+
+            let mut hashes = Vec::new();
+            for _ in 0..15 {
+                if predict {
+                    SysPrediction.run_now(&world);
+                }
+                simulation::tick(&mut dispatcher, &mut world);
+                hashes.push(state_hash(&world));
+            }
+
+            let predicted = world
+                .read_resource::<Prediction>()
+                .predicted
+                .as_ref()
+                .map(|p| p.x > FixedPoint::ZERO);
+            (hashes, predicted)
+        };
+
+        let (with, predicted) = run(true);
+        let (without, _) = run(false);
+        assert_eq!(predicted, Some(true), "prediction should have run");
+        assert_eq!(with, without);
+    }
+}
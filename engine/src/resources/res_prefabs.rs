@@ -0,0 +1,171 @@
+use std::{collections::BTreeMap, fmt};
+
+use serde::{
+    de::{Error, MapAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use serde_json::Value;
+use specs::prelude::*;
+
+use crate::{
+    components::{registry, Reflect},
+    error::EngineError,
+};
+
+/// A set of component values to build an entity from, keyed by component name.
+///
+/// Also used for the overrides applied when spawning, where only the
+/// components that differ from the prefab need to be given.
+#[derive(Clone, Default, PartialEq, Debug, Serialize)]
+#[serde(transparent)]
+pub struct Prefab {
+    components: BTreeMap<String, Value>,
+}
+
+impl Prefab {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a component, replacing any existing one of the same type
+    pub fn with<T: Reflect>(mut self, component: T) -> Result<Self, EngineError> {
+        let name = registry().name_of::<T>().ok_or_else(|| {
+            EngineError::Component(format!("`{}` isn't declared", std::any::type_name::<T>()))
+        })?;
+        let value = serde_json::to_value(component)
+            .map_err(|e| EngineError::Component(format!("{name}: {e}")))?;
+
+        self.components.insert(name.to_owned(), value);
+        Ok(self)
+    }
+
+    /// Parses and validates a component given by name
+    pub fn insert(&mut self, name: &str, value: Value) -> Result<(), String> {
+        let info = registry()
+            .get(name)
+            .ok_or_else(|| format!("unknown component `{name}`"))?;
+        info.check(&value).map_err(|e| format!("{name}: {e}"))?;
+
+        self.components.insert(name.to_owned(), value);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.components.get(name)
+    }
+
+    pub fn components(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.components.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// This prefab with the components given in `overrides` replaced
+    pub fn merged(&self, overrides: &Prefab) -> Prefab {
+        let mut components = self.components.clone();
+        components.extend(overrides.components.clone());
+
+        Prefab { components }
+    }
+
+    /// Spawns directly into the world
+    pub fn spawn(&self, world: &mut World) -> Entity {
+        let entity = world.create_entity().build();
+        self.add_to(world, entity);
+
+        entity
+    }
+
+    /// Adds our components to an existing entity, replacing any it already has
+    pub fn add_to(&self, world: &World, entity: Entity) {
+        for (name, value) in &self.components {
+            if let Some(info) = registry().get(name) {
+                // Values were checked when they were added
+                let _ = info.insert(world, entity, value);
+            }
+        }
+    }
+
+    /// Spawns from a system, the entity appears once the world is next maintained
+    pub fn spawn_lazy(&self, entities: &Entities, updater: &LazyUpdate) -> Entity {
+        let entity = entities.create();
+
+        for (name, value) in &self.components {
+            if let Some(info) = registry().get(name) {
+                let _ = info.insert_lazy(updater, entity, value);
+            }
+        }
+
+        entity
+    }
+}
+
+impl<'de> Deserialize<'de> for Prefab {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(PrefabVisitor)
+    }
+}
+
+struct PrefabVisitor;
+
+impl<'de> Visitor<'de> for PrefabVisitor {
+    type Value = Prefab;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map of component names to values")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut prefab = Prefab::new();
+
+        while let Some(name) = map.next_key::<String>()? {
+            if registry().get(&name).is_none() {
+                let known: Vec<_> = registry().names().collect();
+                return Err(A::Error::custom(format!(
+                    "unknown component `{name}`, expected one of {}",
+                    known.join(", ")
+                )));
+            }
+
+            let value: Value = map.next_value()?;
+            prefab.insert(&name, value).map_err(A::Error::custom)?;
+        }
+
+        Ok(prefab)
+    }
+}
+
+/// The library of named prefabs available for spawning
+pub struct Prefabs {
+    library: BTreeMap<String, Prefab>,
+}
+
+impl Prefabs {
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        Ok(Self {
+            library: serde_json::from_str(json)?,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Prefab> {
+        self.library.get(name)
+    }
+
+    /// Spawns from a system, the entity appears once the world is next maintained
+    pub fn spawn_lazy(
+        &self,
+        name: &str,
+        overrides: &Prefab,
+        entities: &Entities,
+        updater: &LazyUpdate,
+    ) -> Option<Entity> {
+        // Check first, so we don't create an empty entity for a bad name
+        let prefab = self.get(name)?;
+        Some(prefab.merged(overrides).spawn_lazy(entities, updater))
+    }
+}
+
+impl Default for Prefabs {
+    fn default() -> Self {
+        Self::from_json(include_str!("../data/prefabs.json"))
+            .expect("Built in prefabs should be valid")
+    }
+}
@@ -1,6 +1,19 @@
-use crate::action::Action;
+use crate::{
+    action::{Action, PlayerAction, PlayerId},
+    error::EngineError,
+};
 
-const ACTION_QUEUE_SLOTS: usize = 128;
+/// How many ticks ahead actions can be scheduled
+pub const ACTION_QUEUE_SLOTS: usize = 128;
+
+/// The horizon while playing offline, far enough away to never be reached
+const NO_HORIZON: usize = 1 << 30;
+
+/// Upper bound on ticks run in one frame when fast-forwarding, so a slow frame can't snowball
+const MAX_TICKS_PER_FRAME: usize = 16;
+
+/// Upper bound on ticks run in one frame when catching up with the server
+const MAX_CATCH_UP_TICKS_PER_FRAME: usize = 120;
 
 /// Manages when we are allowed to tick the simulation
 /// and what actions are to be applied in a particular tick
@@ -13,71 +26,303 @@ pub struct TickCoordinator {
     pub max_tick: usize,
 
     /// Circular queue of actions, use n%128 slot for tick n and clear
-    pub action_queue: [Vec<Action>; ACTION_QUEUE_SLOTS],
+    pub action_queue: [Vec<PlayerAction>; ACTION_QUEUE_SLOTS],
+
+    /// While paused the simulation only advances by explicit steps
+    pub paused: bool,
+
+    /// Ticks requested by single-stepping while paused
+    pub pending_steps: usize,
+
+    /// Ticks to run per frame, fractions carry over to later frames
+    pub time_scale: f32,
+
+    /// Slight adjustment to the time scale, to keep pace with the server
+    pub rate: f32,
+
+    /// Runs as far as the horizon allows each frame, regardless of time scale,
+    /// to catch up with a session that was already going
+    pub catching_up: bool,
+
+    /// Whether the last frame would have run further than the horizon allowed
+    pub stalled: bool,
+
+    /// The fraction of a tick carried over from previous frames
+    tick_budget: f32,
 }
 
 impl TickCoordinator {
     pub fn new() -> Self {
-        const EMPTY_VEC: Vec<Action> = Vec::new();
+        const EMPTY_VEC: Vec<PlayerAction> = Vec::new();
 
         Self {
             current_tick: 0,
-            // Testing
-            //max_tick: 0,
-            max_tick: 1 << 30,
+            max_tick: NO_HORIZON,
             action_queue: [EMPTY_VEC; ACTION_QUEUE_SLOTS],
+            paused: false,
+            pending_steps: 0,
+            time_scale: 1.,
+            rate: 1.,
+            catching_up: false,
+            stalled: false,
+            tick_budget: 0.,
         }
     }
 
-    /// Retrieves the queue slot for the specified tick
-    fn queue_slot_at(&mut self, tick: usize) -> &mut Vec<Action> {
-        assert!(
-            tick >= self.current_tick,
-            "Attempted to retrieve action queue from the past tick {tick}, currently at {}",
-            self.current_tick
-        );
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.tick_budget = 0.;
+    }
 
-        assert!(
-            tick < self.current_tick + ACTION_QUEUE_SLOTS,
-            "Attempted to retrieve action queue too far in the future at tick {tick}, currently at {}",
-            self.current_tick
-        );
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.pending_steps = 0;
+    }
 
-        &mut self.action_queue[tick % ACTION_QUEUE_SLOTS]
+    /// Pauses, and runs exactly one more tick on the next frame
+    pub fn step(&mut self) {
+        self.pause();
+        self.pending_steps += 1;
+    }
+
+    pub fn set_time_scale(&mut self, scale: f32) {
+        self.time_scale = if scale.is_finite() {
+            scale.clamp(0., MAX_TICKS_PER_FRAME as f32)
+        } else {
+            1.
+        };
+    }
+
+    /// How many ticks to run this frame, honouring pause, steps,
+    /// time scale and the action horizon
+    pub fn ticks_this_frame(&mut self) -> usize {
+        let available = self.max_tick.saturating_sub(self.current_tick);
+        let limit = MAX_TICKS_PER_FRAME.min(available);
+        self.stalled = false;
+
+        if self.paused {
+            let steps = self.pending_steps.min(limit);
+            self.pending_steps -= steps;
+            return steps;
+        }
+
+        if self.catching_up {
+            self.tick_budget = 0.;
+            return MAX_CATCH_UP_TICKS_PER_FRAME.min(available);
+        }
+
+        self.tick_budget += self.time_scale * self.rate;
+        let whole = self.tick_budget.floor();
+        self.tick_budget -= whole;
+
+        self.stalled = whole as usize > available;
+        (whole as usize).min(limit)
+    }
+
+    /// Retrieves the queue slot for the specified tick, which must be within the window
+    fn queue_slot_at(&mut self, tick: usize) -> Result<&mut Vec<PlayerAction>, EngineError> {
+        if tick < self.current_tick || tick >= self.current_tick + ACTION_QUEUE_SLOTS {
+            return Err(EngineError::TickOutOfWindow {
+                tick,
+                current_tick: self.current_tick,
+            });
+        }
+
+        Ok(&mut self.action_queue[tick % ACTION_QUEUE_SLOTS])
     }
 
     /// Retrieves the queue slot for the current tick, shared
-    pub fn current_tick_actions(&self) -> &Vec<Action> {
+    pub fn current_tick_actions(&self) -> &Vec<PlayerAction> {
         &self.action_queue[self.current_tick % ACTION_QUEUE_SLOTS]
     }
 
     /// Retrieves the queue slot for the current tick, exclusively
-    fn current_queue_slot(&mut self) -> &mut Vec<Action> {
-        self.queue_slot_at(self.current_tick)
+    fn current_queue_slot(&mut self) -> &mut Vec<PlayerAction> {
+        &mut self.action_queue[self.current_tick % ACTION_QUEUE_SLOTS]
+    }
+
+    /// Schedules a player's action, which must be for a future tick within the queue window
+    pub fn enqueue_action(
+        &mut self,
+        player: PlayerId,
+        action: Action,
+        tick: usize,
+    ) -> Result<(), EngineError> {
+        // The current tick's actions may already have been handled
+        if tick == self.current_tick {
+            return Err(EngineError::TickOutOfWindow {
+                tick,
+                current_tick: self.current_tick,
+            });
+        }
+
+        self.queue_slot_at(tick)?
+            .push(PlayerAction { player, action });
+        Ok(())
     }
 
-    pub fn enqueue_action(&mut self, action: Action, tick: usize) {
-        assert!(
-            tick > self.current_tick,
-            "Attempted to enqueue an action at past tick {tick}, currently at {}",
-            self.current_tick
-        );
+    /// Whether actions for `tick` fit in the queue yet
+    pub fn has_room_for(&self, tick: usize) -> bool {
+        tick < self.current_tick + ACTION_QUEUE_SLOTS
+    }
+
+    /// Stops at the current tick until bundles arrive for the ticks after it
+    pub fn hold(&mut self) {
+        self.max_tick = self.current_tick;
+    }
 
-        self.queue_slot_at(tick).push(action);
+    /// Runs freely again, as when playing offline
+    pub fn release(&mut self) {
+        self.max_tick = NO_HORIZON;
+        self.rate = 1.;
+        self.catching_up = false;
     }
 
-    pub fn advance(&mut self) {
+    /// Takes the server's bundle of every action for `tick`, which may then be simulated.
+    /// Bundles must arrive in order, and replace anything queued locally for that tick.
+    pub fn receive_bundle(
+        &mut self,
+        tick: usize,
+        actions: Vec<PlayerAction>,
+    ) -> Result<(), EngineError> {
+        if tick != self.max_tick {
+            return Err(EngineError::BundleOutOfOrder {
+                tick,
+                expected: self.max_tick,
+            });
+        }
+
+        *self.queue_slot_at(tick)? = actions;
+        self.max_tick = tick + 1;
+
+        Ok(())
+    }
+
+    /// Jumps to `tick`, forgetting every queued action
+    pub fn reset(&mut self, tick: usize) {
+        for slot in &mut self.action_queue {
+            slot.clear();
+        }
+
+        self.current_tick = tick;
+    }
+
+    pub fn advance(&mut self) -> Result<(), EngineError> {
         // Should not advance past the current action horizon
-        assert!(
-            self.current_tick < self.max_tick,
-            "Attempted to advance past the current action horizon at tick {}",
-            self.current_tick
-        );
+        if self.current_tick >= self.max_tick {
+            return Err(EngineError::PastHorizon {
+                tick: self.current_tick,
+            });
+        }
 
         // Remove events from current queue slot
         self.current_queue_slot().clear();
 
         // advance the tick counter
         self.current_tick += 1;
+
+        Ok(())
+    }
+}
+
+impl Default for TickCoordinator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(tc: &mut TickCoordinator, count: usize) -> Vec<usize> {
+        (0..count).map(|_| tc.ticks_this_frame()).collect()
+    }
+
+    #[test]
+    fn fractions_carry_over() {
+        let mut tc = TickCoordinator::new();
+        assert_eq!(frames(&mut tc, 3), vec![1, 1, 1]);
+
+        tc.set_time_scale(0.5);
+        assert_eq!(frames(&mut tc, 4), vec![0, 1, 0, 1]);
+
+        tc.set_time_scale(2.5);
+        assert_eq!(frames(&mut tc, 4), vec![2, 3, 2, 3]);
+    }
+
+    #[test]
+    fn stepping_runs_one_tick_at_a_time() {
+        let mut tc = TickCoordinator::new();
+        tc.pause();
+        assert_eq!(frames(&mut tc, 2), vec![0, 0]);
+
+        tc.step();
+        assert_eq!(frames(&mut tc, 2), vec![1, 0]);
+
+        tc.step();
+        tc.step();
+        assert_eq!(frames(&mut tc, 2), vec![2, 0]);
+
+        // Resuming forgets steps not taken yet
+        tc.step();
+        tc.resume();
+        assert_eq!(tc.pending_steps, 0);
+        assert_eq!(frames(&mut tc, 1), vec![1]);
+    }
+
+    #[test]
+    fn steps_wait_at_the_horizon() {
+        let mut tc = TickCoordinator::new();
+        tc.hold();
+        tc.step();
+        tc.step();
+
+        assert_eq!(frames(&mut tc, 2), vec![0, 0]);
+        assert_eq!(tc.pending_steps, 2);
+
+        tc.receive_bundle(0, Vec::new()).unwrap();
+        assert_eq!(frames(&mut tc, 1), vec![1]);
+        assert_eq!(tc.pending_steps, 1);
+    }
+
+    #[test]
+    fn stalls_are_flagged() {
+        let mut tc = TickCoordinator::new();
+        tc.hold();
+        assert_eq!(tc.ticks_this_frame(), 0);
+        assert!(tc.stalled);
+
+        tc.receive_bundle(0, Vec::new()).unwrap();
+        assert_eq!(tc.ticks_this_frame(), 1);
+        assert!(!tc.stalled);
+
+        // Paused, there's nothing to stall
+        tc.advance().unwrap();
+        tc.pause();
+        assert_eq!(tc.ticks_this_frame(), 0);
+        assert!(!tc.stalled);
+    }
+
+    #[test]
+    fn ticks_per_frame_are_bounded() {
+        let mut tc = TickCoordinator::new();
+        tc.set_time_scale(1000.);
+        assert_eq!(tc.time_scale, MAX_TICKS_PER_FRAME as f32);
+        assert_eq!(tc.ticks_this_frame(), MAX_TICKS_PER_FRAME);
+
+        // Even if the server asks us to speed up on top
+        tc.rate = 1.5;
+        assert_eq!(tc.ticks_this_frame(), MAX_TICKS_PER_FRAME);
+
+        tc.rate = 1.;
+        tc.set_time_scale(f32::NAN);
+        assert_eq!(tc.time_scale, 1.);
+        tc.set_time_scale(f32::INFINITY);
+        assert_eq!(tc.time_scale, 1.);
+
+        tc.set_time_scale(-2.);
+        assert_eq!(tc.time_scale, 0.);
+        assert_eq!(frames(&mut tc, 3), vec![0, 0, 0]);
     }
 }
@@ -0,0 +1,288 @@
+//! Scene files, JSON documents listing the entities to start a world with.
+//!
+//! ```json
+//! {
+//!     "entities": [
+//!         { "prefab": "orbiter", "components": { "Position": { "x": -32, "y": -8 } } },
+//!         { "components": { "Position": { "x": 0, "y": 0 }, "Gravity": {} } }
+//!     ]
+//! }
+//! ```
+//!
+//! Each entity may start from a prefab, with `components` overriding or adding to it.
+//! Everything is checked while parsing, so errors carry the line they were found on.
+
+use std::fmt;
+
+use serde::de::{DeserializeSeed, Error, MapAccess, SeqAccess, Visitor};
+use specs::{prelude::*, world::EntitiesRes};
+
+use crate::resources::{EngineEvent, EventBus, Prefab, Prefabs, Rng, TickCoordinator};
+
+/// A problem with a scene file, and where in the file it was found
+#[derive(Debug)]
+pub struct SceneError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl From<serde_json::Error> for SceneError {
+    fn from(e: serde_json::Error) -> Self {
+        // serde_json appends the location to its messages, we keep it separately
+        let message = e.to_string();
+        let message = match message.rfind(" at line ") {
+            Some(at) => message[..at].to_owned(),
+            None => message,
+        };
+
+        Self {
+            line: e.line(),
+            column: e.column(),
+            message,
+        }
+    }
+}
+
+/// A parsed and validated scene, with prefabs already resolved
+pub struct Scene {
+    entities: Vec<Prefab>,
+}
+
+impl Scene {
+    pub fn from_json(json: &str, prefabs: &Prefabs) -> Result<Self, SceneError> {
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        let entities = SceneSeed(prefabs).deserialize(&mut deserializer)?;
+        deserializer.end()?;
+
+        Ok(Self { entities })
+    }
+
+    /// Spawns every entity in the scene, returning them in order
+    pub fn spawn_into(&self, world: &mut World) -> Vec<Entity> {
+        self.entities
+            .iter()
+            .map(|entity| entity.spawn(world))
+            .collect()
+    }
+}
+
+/// Replaces everything in the world with the contents of a scene, which starts
+/// on tick 0 with the random generator as it was seeded, like a new world would.
+/// Every entity there was is reported despawned, and every one in the scene spawned.
+/// The world is left untouched if the scene is invalid.
+pub fn load_scene(world: &mut World, json: &str) -> Result<(), SceneError> {
+    let scene = Scene::from_json(json, &world.read_resource::<Prefabs>())?;
+
+    let despawned: Vec<_> = world.entities().join().map(|entity| entity.id()).collect();
+    world.delete_all();
+    world.maintain();
+
+    // Start from a fresh allocator, so what the world held before can't
+    // change which ids we get handed
+    world.insert(EntitiesRes::default());
+    let spawned = scene.spawn_into(world);
+
+    world.write_resource::<TickCoordinator>().reset(0);
+    world.write_resource::<Rng>().reset();
+    world.write_resource::<EventBus>().extend(
+        despawned
+            .into_iter()
+            .map(|id| EngineEvent::EntityDespawned { id })
+            .chain(
+                spawned
+                    .into_iter()
+                    .map(|entity| EngineEvent::EntitySpawned { id: entity.id() }),
+            ),
+    );
+
+    Ok(())
+}
+
+struct SceneSeed<'a>(&'a Prefabs);
+
+impl<'de, 'a> DeserializeSeed<'de> for SceneSeed<'a> {
+    type Value = Vec<Prefab>;
+
+    fn deserialize<D: serde::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for SceneSeed<'a> {
+    type Value = Vec<Prefab>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a scene object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut entities = None;
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "entities" if entities.is_some() => {
+                    return Err(A::Error::duplicate_field("entities"))
+                }
+                "entities" => entities = Some(map.next_value_seed(EntitiesSeed(self.0))?),
+                other => return Err(A::Error::unknown_field(other, &["entities"])),
+            }
+        }
+
+        entities.ok_or_else(|| A::Error::missing_field("entities"))
+    }
+}
+
+struct EntitiesSeed<'a>(&'a Prefabs);
+
+impl<'de, 'a> DeserializeSeed<'de> for EntitiesSeed<'a> {
+    type Value = Vec<Prefab>;
+
+    fn deserialize<D: serde::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for EntitiesSeed<'a> {
+    type Value = Vec<Prefab>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of entities")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut entities = Vec::new();
+        while let Some(entity) = seq.next_element_seed(EntitySeed(self.0))? {
+            entities.push(entity);
+        }
+
+        Ok(entities)
+    }
+}
+
+struct EntitySeed<'a>(&'a Prefabs);
+
+impl<'de, 'a> DeserializeSeed<'de> for EntitySeed<'a> {
+    type Value = Prefab;
+
+    fn deserialize<D: serde::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for EntitySeed<'a> {
+    type Value = Prefab;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an entity object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        const FIELDS: &[&str] = &["prefab", "components"];
+
+        let mut base: Option<&Prefab> = None;
+        let mut components: Option<Prefab> = None;
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "prefab" if base.is_some() => return Err(A::Error::duplicate_field("prefab")),
+                "prefab" => {
+                    let name: String = map.next_value()?;
+                    base = Some(
+                        self.0
+                            .get(&name)
+                            .ok_or_else(|| A::Error::custom(format!("unknown prefab `{name}`")))?,
+                    );
+                }
+                "components" if components.is_some() => {
+                    return Err(A::Error::duplicate_field("components"))
+                }
+                "components" => components = Some(map.next_value()?),
+                other => return Err(A::Error::unknown_field(other, FIELDS)),
+            }
+        }
+
+        // Components were checked as they were parsed
+        let components = components.unwrap_or_default();
+        Ok(match base {
+            Some(base) => base.merged(&components),
+            None => components,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{simulation, state_hash::state_hash};
+
+    const SCENE: &str = include_str!("data/scene.json");
+
+    #[test]
+    fn loading_a_scene_starts_over() {
+        let fresh = simulation::init_world(7).unwrap();
+
+        // Move on, and leave some ids free to be reused in whatever order
+        let mut world = simulation::init_world(7).unwrap();
+        let mut dispatcher = simulation::init_dispatcher();
+        for _ in 0..30 {
+            simulation::tick(&mut dispatcher, &mut world);
+        }
+        let extra: Vec<_> = (0..5).map(|_| world.create_entity().build()).collect();
+        world.delete_entities(&extra[1..4]).unwrap();
+        world.maintain();
+        world.write_resource::<Rng>().next_u64();
+
+        load_scene(&mut world, SCENE).unwrap();
+
+        assert_eq!(state_hash(&world), state_hash(&fresh));
+    }
+
+    #[test]
+    fn loading_a_scene_reports_what_came_and_went() {
+        let mut world = simulation::init_world(7).unwrap();
+        world.write_resource::<EventBus>().take();
+        let before = world.entities().join().count();
+
+        load_scene(&mut world, SCENE).unwrap();
+
+        let events = world.write_resource::<EventBus>().take();
+        let count = |f: fn(&EngineEvent) -> bool| events.iter().filter(|e| f(e)).count();
+        assert_eq!(
+            count(|e| matches!(e, EngineEvent::EntityDespawned { .. })),
+            before
+        );
+        assert_eq!(
+            count(|e| matches!(e, EngineEvent::EntitySpawned { .. })),
+            world.entities().join().count()
+        );
+    }
+
+    #[test]
+    fn an_invalid_scene_changes_nothing() {
+        let mut world = simulation::init_world(7).unwrap();
+        let before = state_hash(&world);
+
+        assert!(load_scene(&mut world, r#"{ "entities": [{ "prefab": "nope" }] }"#).is_err());
+        assert_eq!(state_hash(&world), before);
+    }
+}
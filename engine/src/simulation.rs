@@ -0,0 +1,54 @@
+//! The deterministic part of the engine, with nothing that needs a browser,
+//! so it can also run headless on a native server.
+
+use specs::prelude::*;
+
+use crate::{
+    components::registry,
+    error::EngineError,
+    resources::{
+        ActionDelay, ActionValidator, Errors, EventBus, LocalPlayer, Prefabs, Rng, TickCoordinator,
+    },
+    scene::load_scene,
+    systems,
+};
+
+/// A world with the starting scene and every resource the simulation needs
+pub fn init_world(seed: u64) -> Result<World, EngineError> {
+    let mut world = World::new();
+    registry().register_all(&mut world);
+
+    world.insert(Prefabs::default());
+    world.insert(TickCoordinator::new());
+    world.insert(ActionDelay::new());
+    world.insert(ActionValidator::new());
+    world.insert(LocalPlayer::default());
+    world.insert(Rng::new(seed));
+    world.insert(Errors::default());
+    world.insert(EventBus::default());
+
+    load_scene(&mut world, include_str!("data/scene.json"))?;
+
+    Ok(world)
+}
+
+/// Systems that run once per tick
+pub fn init_dispatcher() -> Dispatcher<'static, 'static> {
+    DispatcherBuilder::new()
+        // Register systems
+        .with(systems::SysMovementReceiver, "MovementReceiver", &[])
+        .with(systems::SysFireReceiver, "FireReceiver", &[])
+        .with(systems::SysSpawnReceiver, "SpawnReceiver", &[])
+        .with(systems::SysLeaveReceiver, "LeaveReceiver", &[])
+        .with(systems::SysMovement, "Movement", &["MovementReceiver"])
+        .with(systems::SysGravity, "Gravity", &[])
+        .with(systems::SysCollision::default(), "Collision", &["Movement"])
+        .with(systems::SysTickCoordinator, "TickCoordinator", &[])
+        .build()
+}
+
+/// Runs one tick
+pub fn tick(dispatcher: &mut Dispatcher, world: &mut World) {
+    dispatcher.dispatch_seq(world);
+    world.maintain();
+}
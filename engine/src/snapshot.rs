@@ -0,0 +1,107 @@
+//! Snapshots of the whole simulation state, built from the component registry,
+//! for saving, debugging and bringing new peers up to date.
+
+use serde::{Deserialize, Serialize};
+use specs::{prelude::*, world::EntitiesRes};
+
+use crate::{
+    components::registry,
+    resources::{EngineEvent, EventBus, Prefab, Rng, TickCoordinator},
+};
+
+/// Everything needed to recreate the simulation at a given tick
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub tick: usize,
+    pub rng: Rng,
+    pub entities: Vec<EntitySnapshot>,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct EntitySnapshot {
+    pub id: u32,
+    pub components: Prefab,
+}
+
+impl Snapshot {
+    pub fn take(world: &World) -> Self {
+        let entities = world
+            .entities()
+            .join()
+            .map(|entity| {
+                let mut components = Prefab::new();
+                for info in registry().iter() {
+                    if let Some(value) = info.read(world, entity) {
+                        // Read from a live component, so always valid
+                        let _ = components.insert(info.name, value);
+                    }
+                }
+
+                EntitySnapshot {
+                    id: entity.id(),
+                    components,
+                }
+            })
+            .collect();
+
+        Self {
+            tick: world.read_resource::<TickCoordinator>().current_tick,
+            rng: Rng::clone(&world.read_resource()),
+            entities,
+        }
+    }
+
+    /// Replaces the simulation state with this snapshot, dropping any queued actions.
+    /// Every entity there was is reported despawned, and every one restored spawned.
+    ///
+    /// Entity ids are preserved, since systems iterate in id order. Generations and
+    /// the order freed ids get reused in are not, which only matters for entities
+    /// spawned later on.
+    pub fn restore(&self, world: &mut World) {
+        let despawned: Vec<_> = world.entities().join().map(|entity| entity.id()).collect();
+        world.delete_all();
+        world.maintain();
+
+        // Start from a fresh allocator, so what the world held before can't
+        // change which ids we get handed
+        world.insert(EntitiesRes::default());
+
+        let len = self
+            .entities
+            .iter()
+            .map(|entity| entity.id as usize + 1)
+            .max()
+            .unwrap_or(0);
+
+        // A fresh allocator hands out ids in order, so create every id up to the
+        // highest one we need, then give back the ones we don't
+        let mut slots: Vec<Option<Entity>> = (0..len)
+            .map(|_| Some(world.create_entity().build()))
+            .collect();
+
+        for entity in &self.entities {
+            slots[entity.id as usize] = None;
+        }
+        let unused: Vec<_> = slots.into_iter().flatten().collect();
+        let _ = world.delete_entities(&unused);
+        world.maintain();
+
+        for snapshot in &self.entities {
+            let entity = world.entities().entity(snapshot.id);
+            snapshot.components.add_to(world, entity);
+        }
+
+        world.write_resource::<TickCoordinator>().reset(self.tick);
+        *world.write_resource::<Rng>() = self.rng.clone();
+        world.write_resource::<EventBus>().extend(
+            despawned
+                .into_iter()
+                .map(|id| EngineEvent::EntityDespawned { id })
+                .chain(
+                    self.entities
+                        .iter()
+                        .map(|entity| EngineEvent::EntitySpawned { id: entity.id }),
+                ),
+        );
+    }
+}
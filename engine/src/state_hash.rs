@@ -0,0 +1,146 @@
+//! Hashing of the deterministic simulation state, so that peers can cheaply
+//! check that they haven't diverged.
+
+use std::{
+    hash::{Hash, Hasher},
+    io,
+};
+
+use specs::prelude::*;
+
+use crate::{
+    components::registry,
+    resources::{Rng, TickCoordinator},
+};
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// FNV-1a, with integers always written as 64 bit little endian,
+/// so the result is the same on wasm32 and native targets
+pub struct StateHasher(u64);
+
+impl StateHasher {
+    pub fn new() -> Self {
+        Self(FNV_OFFSET)
+    }
+}
+
+impl Default for StateHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hasher for StateHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn write_u64(&mut self, v: u64) {
+        self.write(&v.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, v: u32) {
+        self.write_u64(v as u64);
+    }
+
+    fn write_usize(&mut self, v: usize) {
+        self.write_u64(v as u64);
+    }
+
+    fn write_i64(&mut self, v: i64) {
+        self.write_u64(v as u64);
+    }
+
+    fn write_i32(&mut self, v: i32) {
+        self.write_u64(v as i64 as u64);
+    }
+
+    fn write_isize(&mut self, v: isize) {
+        self.write_u64(v as i64 as u64);
+    }
+}
+
+/// Lets components be serialized straight into the hash
+impl io::Write for StateHasher {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        Hasher::write(self, bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hash of everything that must agree between peers at the current tick: every
+/// registered component, read the same way snapshots read them
+pub fn state_hash(world: &World) -> u64 {
+    let mut hasher = StateHasher::new();
+
+    world
+        .read_resource::<TickCoordinator>()
+        .current_tick
+        .hash(&mut hasher);
+    world.read_resource::<Rng>().hash(&mut hasher);
+
+    for entity in world.entities().join() {
+        // Generations aren't hashed, they don't survive a snapshot
+        entity.id().hash(&mut hasher);
+
+        for info in registry().iter() {
+            match info.read(world, entity) {
+                Some(value) => {
+                    info.name.hash(&mut hasher);
+                    // Writing into the hasher can't fail
+                    let _ = serde_json::to_writer(&mut hasher, &value);
+                }
+                None => 0u8.hash(&mut hasher),
+            }
+        }
+    }
+
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{components::control::Owner, simulation, snapshot::Snapshot};
+
+    #[test]
+    fn every_component_counts() {
+        let world = simulation::init_world(1).unwrap();
+        let before = state_hash(&world);
+
+        // Neither a position nor a velocity
+        let entity = world.entities().join().next().unwrap();
+        world
+            .write_storage::<Owner>()
+            .insert(entity, Owner { player: 9 })
+            .unwrap();
+        assert_ne!(state_hash(&world), before);
+    }
+
+    #[test]
+    fn survives_a_snapshot() {
+        let mut world = simulation::init_world(1).unwrap();
+        let mut dispatcher = simulation::init_dispatcher();
+        for _ in 0..10 {
+            simulation::tick(&mut dispatcher, &mut world);
+        }
+        let snapshot = Snapshot::take(&world);
+
+        let mut restored = simulation::init_world(2).unwrap();
+        snapshot.restore(&mut restored);
+        assert_eq!(state_hash(&restored), state_hash(&world));
+    }
+}
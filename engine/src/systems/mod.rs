@@ -7,6 +7,9 @@ pub use sys_renderer::SysRenderer;
 mod sys_gravity;
 pub use sys_gravity::SysGravity;
 
+mod sys_collision;
+pub use sys_collision::SysCollision;
+
 mod sys_input;
 pub use sys_input::SysInput;
 
@@ -18,3 +21,18 @@ pub use sys_movement_receive::SysMovementReceiver;
 
 mod sys_fire_receive;
 pub use sys_fire_receive::SysFireReceiver;
+
+mod sys_spawn_receive;
+pub use sys_spawn_receive::SysSpawnReceiver;
+
+mod sys_leave_receive;
+pub use sys_leave_receive::SysLeaveReceiver;
+
+mod sys_prediction;
+pub use sys_prediction::SysPrediction;
+
+mod sys_touch_overlay;
+pub use sys_touch_overlay::SysTouchOverlay;
+
+mod sys_net_overlay;
+pub use sys_net_overlay::SysNetOverlay;
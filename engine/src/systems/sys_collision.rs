@@ -0,0 +1,122 @@
+use std::collections::BTreeSet;
+
+use crate::{
+    action::FixedPoint,
+    components::{graphics::DrawCircle, physics::Position},
+    resources::{EngineEvent, EventBus},
+};
+use specs::prelude::*;
+
+/// Reports circles that started overlapping this tick, by their drawn radius.
+/// Overlaps don't change the simulation, they are only told to the host.
+#[derive(Default)]
+pub struct SysCollision {
+    /// Pairs of entities overlapping as of the last tick, lowest id first
+    touching: BTreeSet<(u32, u32)>,
+}
+
+impl<'a> System<'a> for SysCollision {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, DrawCircle>,
+        WriteExpect<'a, EventBus>,
+    );
+
+    fn run(&mut self, (entities, pos, circle, mut events): Self::SystemData) {
+        // Sorted by left edge, so each circle is only checked against those it could reach
+        let mut circles: Vec<_> = (&entities, &pos, &circle)
+            .join()
+            .map(|(entity, pos, circle)| {
+                let radius = FixedPoint::saturating_from_num(circle.radius);
+                (entity.id(), pos.x, pos.y, radius)
+            })
+            .collect();
+        circles.sort_by_key(|&(id, x, _, radius)| (x.saturating_sub(radius), id));
+
+        let mut touching = BTreeSet::new();
+        for (i, &(a, ax, ay, ar)) in circles.iter().enumerate() {
+            let right = ax.saturating_add(ar);
+
+            for &(b, bx, by, br) in &circles[i + 1..] {
+                if bx.saturating_sub(br) > right {
+                    break;
+                }
+
+                let (dx, dy) = (ax.saturating_sub(bx), ay.saturating_sub(by));
+                let dist_sq = dx.saturating_mul(dx).saturating_add(dy.saturating_mul(dy));
+                let reach = ar.saturating_add(br);
+
+                if dist_sq < reach.saturating_mul(reach) {
+                    touching.insert((a.min(b), a.max(b)));
+                }
+            }
+        }
+
+        events.extend(
+            touching
+                .difference(&self.touching)
+                .map(|&(a, b)| EngineEvent::Collision { a, b }),
+        );
+        self.touching = touching;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn circle(world: &mut World, x: f32, radius: f32) -> Entity {
+        world
+            .create_entity()
+            .with(Position::new_f32(x, 0.))
+            .with(DrawCircle::new(radius))
+            .build()
+    }
+
+    fn collisions(system: &mut SysCollision, world: &mut World) -> Vec<EngineEvent> {
+        system.run_now(world);
+        world.write_resource::<EventBus>().take()
+    }
+
+    #[test]
+    fn overlaps_are_reported_once() {
+        let mut world = World::new();
+        world.register::<Position>();
+        world.register::<DrawCircle>();
+        world.insert(EventBus::default());
+
+        let a = circle(&mut world, 0., 8.);
+        let b = circle(&mut world, 12., 8.);
+        let far = circle(&mut world, 100., 8.);
+        let mut system = SysCollision::default();
+
+        assert_eq!(
+            collisions(&mut system, &mut world),
+            vec![EngineEvent::Collision {
+                a: a.id(),
+                b: b.id()
+            }]
+        );
+        assert_eq!(collisions(&mut system, &mut world), vec![]);
+
+        // Moving apart and back together is a new collision, just touching is not
+        world.write_storage::<Position>().get_mut(b).unwrap().x = FixedPoint::from_num(16);
+        assert_eq!(collisions(&mut system, &mut world), vec![]);
+
+        world.write_storage::<Position>().get_mut(far).unwrap().x = FixedPoint::from_num(10);
+        assert_eq!(
+            collisions(&mut system, &mut world),
+            vec![
+                EngineEvent::Collision {
+                    a: a.id(),
+                    b: far.id()
+                },
+                EngineEvent::Collision {
+                    a: b.id(),
+                    b: far.id()
+                },
+            ]
+        );
+    }
+}
@@ -1,59 +1,76 @@
 use crate::{
-    action::Action,
+    action::{Action, FixedPoint, PlayerAction},
     components::{
-        graphics::{Color, DrawCircle},
-        physics::{Gravity, MovementReceiver, Position, Velocity},
+        control::Owner,
+        graphics::Color,
+        physics::{MovementReceiver, Position, Velocity},
     },
-    resources::TickCoordinator,
+    math::FixedVec2,
+    resources::{EngineEvent, Errors, EventBus, Prefab, Prefabs, Rng, TickCoordinator},
 };
 use specs::prelude::*;
-use web_sys::console;
 
 pub struct SysFireReceiver;
 
 impl<'a> System<'a> for SysFireReceiver {
     type SystemData = (
         ReadExpect<'a, TickCoordinator>,
-        WriteStorage<'a, MovementReceiver>,
+        ReadStorage<'a, MovementReceiver>,
+        ReadStorage<'a, Owner>,
         ReadStorage<'a, Position>,
         Entities<'a>,
         Read<'a, LazyUpdate>,
+        WriteExpect<'a, Rng>,
+        ReadExpect<'a, Prefabs>,
+        WriteExpect<'a, EventBus>,
+        WriteExpect<'a, Errors>,
     );
 
-    fn run(&mut self, (tc, mr, pos, entities, updater): Self::SystemData) {
-        for action in tc.current_tick_actions() {
+    fn run(
+        &mut self,
+        (tc, mr, owners, pos, entities, updater, mut rng, prefabs, mut events, mut errors): Self::SystemData,
+    ) {
+        for PlayerAction { player, action } in tc.current_tick_actions() {
             match action {
                 Action::Fire => {
-                    for (_, pos) in (&mr, &pos).join() {
+                    let shooters = (&mr, &owners, &pos)
+                        .join()
+                        .filter(|(_, owner, _)| owner.player == *player);
+
+                    for (_, _, pos) in shooters {
+                        // Each burst is a ring of particles at some random rotation
+                        let offset = rng.angle();
+
                         // Spawn some more particles
                         for i in 0..8 {
-                            let entity = entities.create();
-
-                            let theta = i as f32 * 2. * std::f32::consts::PI / 8.;
-
-                            updater.insert(
-                                entity,
-                                Position::new_f32(
-                                    pos.x.to_num::<f32>() + 300. * f32::cos(theta),
-                                    pos.y.to_num::<f32>() + 300. * f32::sin(theta),
-                                ),
-                            );
-
-                            updater.insert(
-                                entity,
-                                Velocity::new_f32(-1. * f32::sin(theta), 1. * f32::cos(theta)),
-                            );
-                            updater.insert(
-                                entity,
-                                Color::new(20 * i as u8, 255 - 16 * i as u8, 0, 255),
-                            );
-                            updater.insert(entity, DrawCircle::new(16.));
-                            updater.insert(entity, Gravity);
+                            let theta = offset + FixedPoint::TAU * i / 8;
+                            let dir = FixedVec2::from_angle(theta);
+
+                            let spawn =
+                                FixedVec2::new(pos.x, pos.y) + dir * FixedPoint::from_num(300);
+                            let vel = dir.perpendicular();
+
+                            let overrides = Prefab::new()
+                                .with(Position::new(spawn.x, spawn.y))
+                                .and_then(|p| p.with(Velocity::new(vel.x, vel.y)))
+                                .and_then(|p| {
+                                    p.with(Color::new(20 * i as u8, 255 - 16 * i as u8, 0, 255))
+                                });
+                            let overrides = match overrides {
+                                Ok(overrides) => overrides,
+                                Err(e) => {
+                                    errors.push(e);
+                                    continue;
+                                }
+                            };
+
+                            if let Some(entity) =
+                                prefabs.spawn_lazy("bullet", &overrides, &entities, &updater)
+                            {
+                                events.push(EngineEvent::EntitySpawned { id: entity.id() });
+                            }
                         }
                     }
-
-                    let ent_count = entities.join().count();
-                    console::log_1(&format!("Have {ent_count} entities now").into());
                 }
                 _ => continue,
             }
@@ -1,37 +1,51 @@
-use crate::{
-    action::FixedPoint,
-    components::physics::{Gravity, GravityEmitter, Position, Velocity},
-};
+use std::collections::BTreeMap;
+
+use crate::components::physics::{Gravity, GravityEmitter, Position, Velocity};
 use specs::prelude::*;
 extern crate web_sys;
 
+mod barnes_hut;
+use barnes_hut::{Body, GravityLaw, QuadTree};
+
+/// Applies the pull of every `GravityEmitter` to every `Gravity` receiver.
+/// Entities with both attract each other mutually.
 pub struct SysGravity;
 
 impl<'a> System<'a> for SysGravity {
     type SystemData = (
+        Entities<'a>,
         ReadStorage<'a, Position>,
         WriteStorage<'a, Velocity>,
         ReadStorage<'a, Gravity>,
         ReadStorage<'a, GravityEmitter>,
     );
 
-    fn run(&mut self, (pos, mut vel, receiver, emitter): Self::SystemData) {
-        for (gravity_position, gravity) in (&pos, &emitter).join() {
-            for (pos, vel, rec) in (&pos, &mut vel, &receiver).join() {
-                let dx = pos.x - gravity_position.x;
-                let dy = pos.y - gravity_position.y;
-
-                assert!(
-                    dx != 0 || dy != 0,
-                    "Two gravity entities in the same location"
-                );
+    fn run(&mut self, (entities, pos, mut vel, receiver, emitter): Self::SystemData) {
+        // Only emitters that share a force law can be aggregated together
+        let mut groups: BTreeMap<GravityLaw, Vec<Body>> = BTreeMap::new();
+        for (entity, pos, emitter) in (&entities, &pos, &emitter).join() {
+            groups
+                .entry(GravityLaw::of(emitter))
+                .or_default()
+                .push(Body {
+                    entity,
+                    x: pos.x,
+                    y: pos.y,
+                    strength: emitter.strength,
+                });
+        }
 
-                let d = dx * dx + dy * dy;
+        let trees: Vec<QuadTree> = groups
+            .into_iter()
+            .map(|(law, bodies)| QuadTree::build(law, bodies))
+            .collect();
 
-                let g = FixedPoint::from_num(2);
+        for (entity, pos, vel, _) in (&entities, &pos, &mut vel, &receiver).join() {
+            for tree in &trees {
+                let (ax, ay) = tree.acceleration_at(entity, pos.x, pos.y);
 
-                vel.vx -= g * dx / d;
-                vel.vy -= g * dy / d;
+                vel.vx = vel.vx.saturating_add(ax);
+                vel.vy = vel.vy.saturating_add(ay);
             }
         }
     }
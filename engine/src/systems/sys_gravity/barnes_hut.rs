@@ -0,0 +1,414 @@
+//! Barnes-Hut approximation of the pull of many gravity emitters.
+//!
+//! Emitters are bucketed into a quadtree, and cells that are small relative to
+//! their distance from a receiver are treated as a single body at their center
+//! of strength. Everything is done in `FixedPoint` (or exact integer math on its
+//! bits) so results are bit-identical across platforms.
+
+use specs::Entity;
+
+use crate::{
+    action::FixedPoint,
+    components::physics::{Falloff, GravityEmitter},
+    math::sqrt,
+};
+
+/// Squared opening angle, a cell is approximated when `size / distance < θ` (θ = 0.5)
+const THETA_SQ: FixedPoint = FixedPoint::from_bits(1 << 10);
+
+/// Cells with at most this many bodies are not subdivided further
+const LEAF_CAPACITY: usize = 4;
+
+/// Guards against unbounded subdivision when many bodies share a location
+const MAX_DEPTH: usize = 16;
+
+/// The parameters of an emitter that determine how its pull is computed,
+/// emitters sharing a law can be aggregated into the same tree
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GravityLaw {
+    pub falloff: Falloff,
+    pub max_range: Option<FixedPoint>,
+    pub softening: FixedPoint,
+}
+
+impl GravityLaw {
+    pub fn of(emitter: &GravityEmitter) -> Self {
+        Self {
+            falloff: emitter.falloff,
+            max_range: emitter.max_range,
+            softening: emitter.softening,
+        }
+    }
+
+    /// The acceleration on a receiver offset by `(dx, dy)` from a source of the given strength
+    pub fn pull(
+        &self,
+        dx: FixedPoint,
+        dy: FixedPoint,
+        strength: FixedPoint,
+    ) -> Option<(FixedPoint, FixedPoint)> {
+        let dist_sq = dx.saturating_mul(dx).saturating_add(dy.saturating_mul(dy));
+
+        if let Some(range) = self.max_range {
+            if dist_sq > range.saturating_mul(range) {
+                return None;
+            }
+        }
+
+        let soft_sq = dist_sq.saturating_add(self.softening.saturating_mul(self.softening));
+
+        // Coincident with no softening, there is no meaningful direction to pull in
+        if soft_sq == 0 {
+            return None;
+        }
+
+        let denominator = match self.falloff {
+            Falloff::Constant => sqrt(soft_sq),
+            Falloff::Inverse => soft_sq,
+            Falloff::InverseSquare => soft_sq.saturating_mul(sqrt(soft_sq)),
+        };
+
+        Some((
+            -strength.saturating_mul(dx).saturating_div(denominator),
+            -strength.saturating_mul(dy).saturating_div(denominator),
+        ))
+    }
+}
+
+pub struct Body {
+    pub entity: Entity,
+    pub x: FixedPoint,
+    pub y: FixedPoint,
+    pub strength: FixedPoint,
+}
+
+struct Cell {
+    /// Center and half the side length of this cell
+    cx: FixedPoint,
+    cy: FixedPoint,
+    half: FixedPoint,
+
+    /// Summed strength of everything in this cell
+    strength: FixedPoint,
+
+    /// Center of strength, weighted by the magnitude of each body's strength
+    mx: FixedPoint,
+    my: FixedPoint,
+
+    /// Indices of non-empty child cells, empty for leaves
+    children: Vec<usize>,
+
+    /// Indices of the bodies in this cell, only populated for leaves
+    bodies: Vec<usize>,
+}
+
+impl Cell {
+    /// Squared distance from a point to the nearest edge of this cell
+    fn distance_sq_to(&self, x: FixedPoint, y: FixedPoint) -> FixedPoint {
+        let dx = ((x - self.cx).saturating_abs() - self.half).max(FixedPoint::ZERO);
+        let dy = ((y - self.cy).saturating_abs() - self.half).max(FixedPoint::ZERO);
+        dx.saturating_mul(dx).saturating_add(dy.saturating_mul(dy))
+    }
+}
+
+pub struct QuadTree {
+    law: GravityLaw,
+    cells: Vec<Cell>,
+    bodies: Vec<Body>,
+}
+
+impl QuadTree {
+    pub fn build(law: GravityLaw, bodies: Vec<Body>) -> Self {
+        let mut tree = Self {
+            law,
+            cells: Vec::new(),
+            bodies,
+        };
+
+        if tree.bodies.is_empty() {
+            return tree;
+        }
+
+        // Find a square that encloses everything
+        let first = &tree.bodies[0];
+        let (mut min_x, mut max_x, mut min_y, mut max_y) = (first.x, first.x, first.y, first.y);
+        for body in &tree.bodies {
+            min_x = min_x.min(body.x);
+            max_x = max_x.max(body.x);
+            min_y = min_y.min(body.y);
+            max_y = max_y.max(body.y);
+        }
+
+        let cx = min_x + (max_x - min_x) / 2;
+        let cy = min_y + (max_y - min_y) / 2;
+        let half = (max_x - min_x).max(max_y - min_y) / 2 + FixedPoint::ONE;
+
+        let indices = (0..tree.bodies.len()).collect();
+        tree.build_cell(indices, cx, cy, half, 0);
+
+        tree
+    }
+
+    fn build_cell(
+        &mut self,
+        indices: Vec<usize>,
+        cx: FixedPoint,
+        cy: FixedPoint,
+        half: FixedPoint,
+        depth: usize,
+    ) -> usize {
+        // Exact aggregation on the raw bits, so summation order can't matter
+        let mut strength = FixedPoint::ZERO;
+        let (mut weight, mut wx, mut wy) = (0i128, 0i128, 0i128);
+        for &i in &indices {
+            let body = &self.bodies[i];
+            let w = body.strength.unsigned_abs().to_bits() as i128;
+
+            strength = strength.saturating_add(body.strength);
+            weight += w;
+            wx += w * body.x.to_bits() as i128;
+            wy += w * body.y.to_bits() as i128;
+        }
+
+        let (mx, my) = if weight == 0 {
+            (cx, cy)
+        } else {
+            (
+                FixedPoint::from_bits((wx / weight) as i64),
+                FixedPoint::from_bits((wy / weight) as i64),
+            )
+        };
+
+        let index = self.cells.len();
+        self.cells.push(Cell {
+            cx,
+            cy,
+            half,
+            strength,
+            mx,
+            my,
+            children: Vec::new(),
+            bodies: Vec::new(),
+        });
+
+        if indices.len() <= LEAF_CAPACITY || depth >= MAX_DEPTH {
+            self.cells[index].bodies = indices;
+            return index;
+        }
+
+        let mut quadrants: [Vec<usize>; 4] = Default::default();
+        for i in indices {
+            let body = &self.bodies[i];
+            let quadrant = (body.x >= cx) as usize | ((body.y >= cy) as usize) << 1;
+            quadrants[quadrant].push(i);
+        }
+
+        let quarter = half / 2;
+        let mut children = Vec::new();
+        for (quadrant, members) in quadrants.into_iter().enumerate() {
+            if members.is_empty() {
+                continue;
+            }
+
+            let qx = if quadrant & 1 == 0 {
+                cx - quarter
+            } else {
+                cx + quarter
+            };
+            let qy = if quadrant & 2 == 0 {
+                cy - quarter
+            } else {
+                cy + quarter
+            };
+            children.push(self.build_cell(members, qx, qy, quarter, depth + 1));
+        }
+
+        self.cells[index].children = children;
+        index
+    }
+
+    /// The summed acceleration on a receiver at `(x, y)`,
+    /// ignoring `receiver` itself if it is also one of the bodies
+    pub fn acceleration_at(
+        &self,
+        receiver: Entity,
+        x: FixedPoint,
+        y: FixedPoint,
+    ) -> (FixedPoint, FixedPoint) {
+        let (mut ax, mut ay) = (FixedPoint::ZERO, FixedPoint::ZERO);
+
+        let mut add = |pull: Option<(FixedPoint, FixedPoint)>| {
+            if let Some((px, py)) = pull {
+                ax = ax.saturating_add(px);
+                ay = ay.saturating_add(py);
+            }
+        };
+
+        if self.cells.is_empty() {
+            return (ax, ay);
+        }
+
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let cell = &self.cells[index];
+
+            // Nothing in this cell can reach us
+            if let Some(range) = self.law.max_range {
+                if cell.distance_sq_to(x, y) > range.saturating_mul(range) {
+                    continue;
+                }
+            }
+
+            if cell.children.is_empty() {
+                for &i in &cell.bodies {
+                    let body = &self.bodies[i];
+                    if body.entity != receiver {
+                        add(self.law.pull(x - body.x, y - body.y, body.strength));
+                    }
+                }
+                continue;
+            }
+
+            let dx = x - cell.mx;
+            let dy = y - cell.my;
+            let dist_sq = dx.saturating_mul(dx).saturating_add(dy.saturating_mul(dy));
+            let size = cell.half * 2;
+
+            if size.saturating_mul(size) < THETA_SQ.saturating_mul(dist_sq) {
+                // Far enough away to treat as a single body
+                add(self.law.pull(dx, dy, cell.strength));
+            } else {
+                stack.extend(cell.children.iter().rev());
+            }
+        }
+
+        (ax, ay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::{Builder, World, WorldExt};
+
+    use super::*;
+
+    const LAWS: [Falloff; 3] = [Falloff::Constant, Falloff::Inverse, Falloff::InverseSquare];
+
+    fn law(falloff: Falloff) -> GravityLaw {
+        GravityLaw {
+            falloff,
+            max_range: None,
+            softening: FixedPoint::ONE,
+        }
+    }
+
+    /// A scattered cluster of bodies, laid out by a fixed LCG so every run sees the same one
+    fn cluster(world: &mut World, count: usize) -> Vec<Body> {
+        let mut state = 12345u32;
+        let mut next = |range: i32| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            FixedPoint::from_num((state >> 16) as i32 % range)
+        };
+        let one = FixedPoint::ONE;
+        let hundred = FixedPoint::from_num(100);
+
+        (0..count)
+            .map(|_| Body {
+                entity: world.create_entity().build(),
+                x: next(200) - hundred,
+                y: next(200) - hundred,
+                strength: next(4) + one,
+            })
+            .collect()
+    }
+
+    fn copy(bodies: &[Body]) -> Vec<Body> {
+        bodies
+            .iter()
+            .map(|b| Body {
+                entity: b.entity,
+                x: b.x,
+                y: b.y,
+                strength: b.strength,
+            })
+            .collect()
+    }
+
+    fn direct(
+        law: GravityLaw,
+        bodies: &[Body],
+        x: FixedPoint,
+        y: FixedPoint,
+    ) -> (FixedPoint, FixedPoint) {
+        bodies
+            .iter()
+            .filter_map(|b| law.pull(x - b.x, y - b.y, b.strength))
+            .fold(
+                (FixedPoint::ZERO, FixedPoint::ZERO),
+                |(ax, ay), (px, py)| (ax + px, ay + py),
+            )
+    }
+
+    #[test]
+    fn approximates_the_direct_sum() {
+        let mut world = World::new();
+        let bodies = cluster(&mut world, 200);
+        let receiver = world.create_entity().build();
+
+        for falloff in LAWS {
+            let tree = QuadTree::build(law(falloff), copy(&bodies));
+
+            for (x, y) in [(0, 0), (50, -30), (400, 0), (-300, 250), (1000, 1000)] {
+                let (x, y) = (FixedPoint::from_num(x), FixedPoint::from_num(y));
+                let (ax, ay) = tree.acceleration_at(receiver, x, y);
+                let (dx, dy) = direct(law(falloff), &bodies, x, y);
+
+                // Within 5% of the exact pull, plus a little for fixed point rounding
+                let error = (ax - dx).abs() + (ay - dy).abs();
+                let allowed = (dx.abs() + dy.abs()) / 20 + FixedPoint::from_num(0.05);
+                assert!(
+                    error <= allowed,
+                    "{falloff:?} at ({x}, {y}): {ax}, {ay} vs {dx}, {dy}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn ignores_the_receiver_itself() {
+        let mut world = World::new();
+        let bodies = cluster(&mut world, 3);
+        let (entity, x, y) = (bodies[0].entity, bodies[0].x, bodies[0].y);
+        let others = copy(&bodies[1..]);
+
+        let tree = QuadTree::build(law(Falloff::Inverse), bodies);
+        assert_eq!(
+            tree.acceleration_at(entity, x, y),
+            direct(law(Falloff::Inverse), &others, x, y)
+        );
+    }
+
+    #[test]
+    fn same_bodies_same_pull() {
+        let mut world = World::new();
+        let bodies = cluster(&mut world, 100);
+        let receiver = world.create_entity().build();
+        let mut reversed = copy(&bodies);
+        reversed.reverse();
+
+        for falloff in LAWS {
+            let first = QuadTree::build(law(falloff), copy(&bodies));
+            let again = QuadTree::build(law(falloff), copy(&bodies));
+            let reordered = QuadTree::build(law(falloff), copy(&reversed));
+
+            for (x, y) in [(0, 0), (37, -81), (500, 20)] {
+                let (x, y) = (FixedPoint::from_num(x), FixedPoint::from_num(y));
+                let pull = first.acceleration_at(receiver, x, y);
+
+                assert_eq!(pull, first.acceleration_at(receiver, x, y));
+                assert_eq!(pull, again.acceleration_at(receiver, x, y));
+                assert_eq!(pull, reordered.acceleration_at(receiver, x, y));
+            }
+        }
+    }
+}
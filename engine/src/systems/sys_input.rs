@@ -1,7 +1,13 @@
 use crate::{
-    action::{Action, Direction},
-    input::{EventQueue, InputEvent},
-    resources::TickCoordinator,
+    action::{Action, FixedPoint},
+    components::{control::Owner, physics::MovementReceiver},
+    input::{Binding, Bindings, EventQueue, InputEvent, TimedInput, TouchControls},
+    net::{NetClient, SyncMode},
+    resources::{
+        controls_entity, ActionDelay, ActionValidator, EngineEvent, EventBus, LocalPlayer,
+        Prediction, TickCoordinator,
+    },
+    utils,
 };
 use specs::prelude::*;
 extern crate web_sys;
@@ -9,47 +15,106 @@ extern crate web_sys;
 pub struct SysInput;
 
 impl<'a> System<'a> for SysInput {
-    type SystemData = (WriteExpect<'a, TickCoordinator>, ReadExpect<'a, EventQueue>);
+    type SystemData = (
+        WriteExpect<'a, TickCoordinator>,
+        ReadExpect<'a, EventQueue>,
+        WriteExpect<'a, ActionDelay>,
+        ReadExpect<'a, Bindings>,
+        WriteExpect<'a, TouchControls>,
+        ReadExpect<'a, LocalPlayer>,
+        WriteExpect<'a, ActionValidator>,
+        ReadStorage<'a, Owner>,
+        ReadStorage<'a, MovementReceiver>,
+        WriteExpect<'a, EventBus>,
+        WriteExpect<'a, Prediction>,
+        Option<WriteExpect<'a, NetClient>>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            mut tc,
+            eq,
+            mut delay,
+            bindings,
+            mut touch,
+            player,
+            mut validator,
+            owners,
+            receivers,
+            mut events,
+            mut prediction,
+            mut client,
+        ): Self::SystemData,
+    ) {
+        let now = utils::now();
+        let player = player.0;
+        let controls_entity = controls_entity(player, &owners, &receivers);
 
-    fn run(&mut self, (mut tc, eq): Self::SystemData) {
         // TODO does this keep the lock for the entire loop?
-        for event in eq.items().lock().unwrap().drain(..) {
-            let action: Action = match event {
-                InputEvent::KeyDown { key } => match key.as_str() {
-                    "w" => Action::StartMoving { dir: Direction::Up },
-                    "a" => Action::StartMoving {
-                        dir: Direction::Left,
-                    },
-                    "s" => Action::StartMoving {
-                        dir: Direction::Down,
-                    },
-                    "d" => Action::StartMoving {
-                        dir: Direction::Right,
-                    },
-                    " " => Action::Fire,
-                    _ => continue,
-                },
-
-                InputEvent::KeyUp { key } => match key.as_str() {
-                    "w" => Action::StopMoving { dir: Direction::Up },
-                    "a" => Action::StopMoving {
-                        dir: Direction::Left,
-                    },
-                    "s" => Action::StopMoving {
-                        dir: Direction::Down,
-                    },
-                    "d" => Action::StopMoving {
-                        dir: Direction::Right,
-                    },
-                    _ => continue,
-                },
+        for TimedInput { time, event } in eq.items().lock().unwrap().drain(..) {
+            let actions: Vec<Action> = match event {
+                InputEvent::KeyDown { key } => bindings
+                    .key(&key)
+                    .map(Binding::pressed)
+                    .into_iter()
+                    .collect(),
+
+                InputEvent::KeyUp { key } => bindings
+                    .key(&key)
+                    .and_then(Binding::released)
+                    .into_iter()
+                    .collect(),
+
+                InputEvent::GamepadButtonDown { button, .. } => bindings
+                    .button(button)
+                    .map(Binding::pressed)
+                    .into_iter()
+                    .collect(),
+
+                InputEvent::GamepadButtonUp { button, .. } => bindings
+                    .button(button)
+                    .and_then(Binding::released)
+                    .into_iter()
+                    .collect(),
+
+                // Gamepads count y downwards, the simulation upwards
+                InputEvent::GamepadStick { x, y, .. } => vec![Action::MoveAnalog {
+                    x: FixedPoint::from_num(x),
+                    y: FixedPoint::from_num(-y),
+                }],
+
+                InputEvent::TouchStart { id, x, y } => touch.touch_start(id, x, y),
+                InputEvent::TouchMove { id, x, y } => touch.touch_move(id, x, y),
+                InputEvent::TouchEnd { id } => touch.touch_end(id),
+
                 // Ignore unhandled events
                 _ => continue,
             };
 
-            // For testing purposes, we'll just schedule everything 5 ticks in the future
-            let tick = tc.current_tick + 5;
-            tc.enqueue_action(action, tick);
+            delay.record_input_age(now - time);
+            let tick = delay.target_tick(tc.current_tick);
+
+            // Online, the server decides what happens and tells everyone
+            if let Some(client) = &mut client {
+                if client.can_act() && client.sync == SyncMode::Lockstep {
+                    prediction.record(tick, &actions);
+                }
+                client.send_actions(tick, actions);
+                continue;
+            }
+
+            // Our own input is held to the same rules as everyone else's
+            for action in actions {
+                match validator.submit(&mut tc, player, action.clone(), tick, controls_entity) {
+                    Ok(()) => prediction.record(tick, &[action]),
+                    Err(rejection) => events.push(EngineEvent::ActionRejected {
+                        player: rejection.player,
+                        tick: rejection.tick,
+                        reason: rejection.reason,
+                    }),
+                }
+            }
         }
     }
 }
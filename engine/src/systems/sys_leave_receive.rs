@@ -0,0 +1,31 @@
+use crate::{
+    action::{Action, PlayerAction},
+    components::control::Owner,
+    resources::{EngineEvent, EventBus, TickCoordinator},
+};
+use specs::prelude::*;
+
+pub struct SysLeaveReceiver;
+
+impl<'a> System<'a> for SysLeaveReceiver {
+    type SystemData = (
+        ReadExpect<'a, TickCoordinator>,
+        Entities<'a>,
+        ReadStorage<'a, Owner>,
+        WriteExpect<'a, EventBus>,
+    );
+
+    fn run(&mut self, (tc, entities, owners, mut events): Self::SystemData) {
+        for PlayerAction { player, action } in tc.current_tick_actions() {
+            if !matches!(action, Action::Leave) {
+                continue;
+            }
+
+            for (entity, owner) in (&entities, &owners).join() {
+                if owner.player == *player && entities.delete(entity).is_ok() {
+                    events.push(EngineEvent::EntityDespawned { id: entity.id() });
+                }
+            }
+        }
+    }
+}
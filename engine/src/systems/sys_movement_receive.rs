@@ -1,10 +1,12 @@
 use crate::{
-    action::Action,
-    components::physics::{MovementReceiver, Velocity},
+    action::{Action, PlayerAction},
+    components::{
+        control::Owner,
+        physics::{MovementReceiver, Position, Velocity},
+    },
     resources::TickCoordinator,
 };
 use specs::prelude::*;
-use web_sys::console;
 
 pub struct SysMovementReceiver;
 
@@ -12,26 +14,34 @@ impl<'a> System<'a> for SysMovementReceiver {
     type SystemData = (
         ReadExpect<'a, TickCoordinator>,
         WriteStorage<'a, MovementReceiver>,
+        ReadStorage<'a, Owner>,
+        WriteStorage<'a, Position>,
         WriteStorage<'a, Velocity>,
     );
 
-    fn run(&mut self, (tc, mut mr, mut vel): Self::SystemData) {
-        for action in tc.current_tick_actions() {
-            match action {
-                Action::StartMoving { dir } => {
-                    for (mr, vel) in (&mut mr, &mut vel).join() {
-                        mr.direction = mr.direction.or(*dir);
-                        mr.apply(vel);
-                    }
-                }
-                Action::StopMoving { dir } => {
-                    for (mr, vel) in (&mut mr, &mut vel).join() {
-                        mr.direction = mr.direction.and(dir.not());
-                        mr.apply(vel);
-                    }
+    fn run(&mut self, (tc, mut mr, owners, mut pos, mut vel): Self::SystemData) {
+        for PlayerAction { player, action } in tc.current_tick_actions() {
+            if !matches!(
+                action,
+                Action::StartMoving { .. }
+                    | Action::StopMoving { .. }
+                    | Action::MoveAnalog { .. }
+                    | Action::Jump
+            ) {
+                continue;
+            }
+
+            // Players only move what they own
+            for (mr, owner) in (&mut mr, &owners).join() {
+                if owner.player == *player {
+                    mr.receive(action);
                 }
-                _ => continue,
             }
         }
+
+        // Movement accelerates over time, so this applies every tick and not just on input
+        for (mr, pos, vel) in (&mut mr, &mut pos, &mut vel).join() {
+            mr.apply(pos, vel);
+        }
     }
 }
@@ -0,0 +1,15 @@
+use crate::{renderer::Renderer, resources::NetStats};
+use specs::prelude::*;
+
+/// Draws the network graph over everything else, when it's turned on
+pub struct SysNetOverlay;
+
+impl<'a> System<'a> for SysNetOverlay {
+    type SystemData = (WriteExpect<'a, Renderer>, ReadExpect<'a, NetStats>);
+
+    fn run(&mut self, (mut renderer, stats): Self::SystemData) {
+        for (x, y, radius, color) in stats.shapes() {
+            renderer.draw_test(x, y, radius, color);
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use crate::{
+    components::{
+        control::Owner,
+        physics::{MovementReceiver, Position, Velocity},
+    },
+    net::{NetClient, SyncMode},
+    resources::{ActionDelay, LocalPlayer, Prediction, TickCoordinator},
+};
+use specs::prelude::*;
+
+/// Predicts where the entity we move will be once our latest input applies
+pub struct SysPrediction;
+
+impl<'a> System<'a> for SysPrediction {
+    type SystemData = (
+        WriteExpect<'a, Prediction>,
+        ReadExpect<'a, TickCoordinator>,
+        ReadExpect<'a, ActionDelay>,
+        ReadExpect<'a, LocalPlayer>,
+        Entities<'a>,
+        ReadStorage<'a, Owner>,
+        ReadStorage<'a, MovementReceiver>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Velocity>,
+        Option<ReadExpect<'a, NetClient>>,
+    );
+
+    fn run(
+        &mut self,
+        (mut prediction, tc, delay, player, entities, owners, receivers, pos, vel, client): Self::SystemData,
+    ) {
+        // In state sync nothing is simulated locally, so there's nothing to run ahead of
+        let lockstep =
+            client.map_or(SyncMode::Lockstep, |client| client.sync) == SyncMode::Lockstep;
+
+        let controlled = (&entities, &owners, &receivers, &pos, &vel)
+            .join()
+            .filter(|_| lockstep)
+            .find(|(_, owner, ..)| owner.player == player.0)
+            .map(|(entity, _, receiver, pos, vel)| (entity, receiver, pos, vel));
+
+        prediction.update(tc.current_tick, delay.ticks, controlled);
+    }
+}
@@ -4,6 +4,7 @@ use crate::{
         physics::Position,
     },
     renderer::Renderer,
+    resources::Prediction,
 };
 use specs::prelude::*;
 
@@ -12,25 +13,28 @@ pub struct SysRenderer;
 impl<'a> System<'a> for SysRenderer {
     type SystemData = (
         WriteExpect<'a, Renderer>,
+        ReadExpect<'a, Prediction>,
+        Entities<'a>,
         ReadStorage<'a, Position>,
         ReadStorage<'a, Color>,
         ReadStorage<'a, DrawCircle>,
     );
 
-    fn run(&mut self, (mut renderer, pos, col, circle): Self::SystemData) {
+    fn run(&mut self, (mut renderer, prediction, entities, pos, col, circle): Self::SystemData) {
         // Arbitrarily chosen "center" for the moment
         let cx = 400.;
         let cy = 400.;
 
         //renderer.draw_test(cx, cy, 6., [0., 0., 0., 255.]);
 
-        for (pos, col, circle) in (&pos, &col, &circle).join() {
-            let xx = pos.x;
-            let yy = pos.y;
+        for (entity, pos, col, circle) in (&entities, &pos, &col, &circle).join() {
+            let (xx, yy) = prediction
+                .drawn_position(entity)
+                .unwrap_or((pos.x.to_num(), pos.y.to_num()));
 
             renderer.draw_test(
-                xx.to_num::<f32>() + cx,
-                yy.to_num::<f32>() + cy,
+                xx + cx,
+                yy + cy,
                 circle.radius,
                 [
                     col.red as f32,
@@ -0,0 +1,29 @@
+use crate::{
+    action::{Action, PlayerAction},
+    resources::{EngineEvent, EventBus, Prefabs, TickCoordinator},
+    utils,
+};
+use specs::prelude::*;
+
+pub struct SysSpawnReceiver;
+
+impl<'a> System<'a> for SysSpawnReceiver {
+    type SystemData = (
+        ReadExpect<'a, TickCoordinator>,
+        ReadExpect<'a, Prefabs>,
+        Entities<'a>,
+        Read<'a, LazyUpdate>,
+        WriteExpect<'a, EventBus>,
+    );
+
+    fn run(&mut self, (tc, prefabs, entities, updater, mut events): Self::SystemData) {
+        for PlayerAction { action, .. } in tc.current_tick_actions() {
+            if let Action::Spawn { prefab, overrides } = action {
+                match prefabs.spawn_lazy(prefab, overrides, &entities, &updater) {
+                    Some(entity) => events.push(EngineEvent::EntitySpawned { id: entity.id() }),
+                    None => utils::log(&format!("No prefab named {prefab} to spawn")),
+                }
+            }
+        }
+    }
+}
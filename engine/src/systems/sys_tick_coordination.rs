@@ -1,13 +1,21 @@
-use crate::resources::TickCoordinator;
+use crate::resources::{EngineEvent, Errors, EventBus, TickCoordinator};
 use specs::prelude::*;
 extern crate web_sys;
 
 pub struct SysTickCoordinator;
 
 impl<'a> System<'a> for SysTickCoordinator {
-    type SystemData = (WriteExpect<'a, TickCoordinator>,);
+    type SystemData = (
+        WriteExpect<'a, TickCoordinator>,
+        WriteExpect<'a, Errors>,
+        WriteExpect<'a, EventBus>,
+    );
 
-    fn run(&mut self, mut tc: Self::SystemData) {
-        tc.0.advance();
+    fn run(&mut self, (mut tc, mut errors, mut events): Self::SystemData) {
+        let tick = tc.current_tick;
+        match tc.advance() {
+            Ok(()) => events.push(EngineEvent::TickCompleted { tick }),
+            Err(e) => errors.push(e),
+        }
     }
 }
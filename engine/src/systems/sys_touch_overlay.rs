@@ -0,0 +1,15 @@
+use crate::{input::TouchControls, renderer::Renderer};
+use specs::prelude::*;
+
+/// Draws the on-screen touch controls over everything else
+pub struct SysTouchOverlay;
+
+impl<'a> System<'a> for SysTouchOverlay {
+    type SystemData = (WriteExpect<'a, Renderer>, ReadExpect<'a, TouchControls>);
+
+    fn run(&mut self, (mut renderer, touch): Self::SystemData) {
+        for (x, y, radius, color) in touch.shapes() {
+            renderer.draw_test(x, y, radius, color);
+        }
+    }
+}
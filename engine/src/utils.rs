@@ -8,3 +8,33 @@ pub fn set_panic_hook() {
     #[cfg(feature = "console_error_panic_hook")]
     console_error_panic_hook::set_once();
 }
+
+/// Logs to the browser console, or to stderr when running natively
+pub fn log(message: &str) {
+    #[cfg(target_arch = "wasm32")]
+    web_sys::console::log_1(&message.into());
+
+    #[cfg(not(target_arch = "wasm32"))]
+    eprintln!("{message}");
+}
+
+/// Milliseconds on a steady clock, the same one DOM event timestamps use in the
+/// browser, or since it was first read when running natively
+pub fn now() -> f64 {
+    #[cfg(target_arch = "wasm32")]
+    let now = web_sys::window()
+        .and_then(|window| window.performance())
+        .map_or(0., |performance| performance.now());
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let now = {
+        static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+        START
+            .get_or_init(std::time::Instant::now)
+            .elapsed()
+            .as_secs_f64()
+            * 1000.
+    };
+
+    now
+}
@@ -0,0 +1,287 @@
+//! Loopback clients, which join like anyone else and play at random,
+//! so a whole session can be checked end to end on one machine.
+
+use std::{
+    error::Error,
+    io::{BufRead, BufReader, Write},
+    net::{Shutdown, SocketAddr, TcpStream},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use canvas_test::{
+    action::{Action, Direction},
+    math::FixedVec2,
+    net::{
+        Connection, ConnectionState, LinkConditions, NetClient, SimulatedTransport, SyncMode,
+        Transport,
+    },
+    resources::{ActionDelay, EngineEvent, EventBus, NetStats, Rng, TickCoordinator},
+    simulation,
+};
+use specs::prelude::*;
+
+/// How long to wait for the server when there's nothing to run
+const IDLE_WAIT: Duration = Duration::from_millis(1);
+
+/// How a loopback client's session went
+pub struct BotReport {
+    pub name: String,
+    pub ticks: usize,
+    pub desyncs: usize,
+    pub rejections: usize,
+
+    /// Our final estimate of the round trip time to the server, in milliseconds
+    pub round_trip: f64,
+
+    /// The longest we went without a tick to run once in, in milliseconds
+    pub longest_stall: f64,
+
+    /// The tick we had caught up with the session on, having joined it late
+    pub caught_up: Option<usize>,
+
+    /// How the connection went as we saw it
+    pub net: NetStats,
+}
+
+/// Starts a client on its own thread, which plays until the server hangs up,
+/// over a simulated link with the given conditions if there are any
+pub fn spawn<T: Transport + Send + 'static>(
+    transport: T,
+    link: Option<LinkConditions>,
+    name: String,
+    seed: u64,
+) -> JoinHandle<Result<BotReport, Box<dyn Error + Send + Sync>>> {
+    thread::spawn(move || match link {
+        // Misbehaves differently from how the client plays
+        Some(conditions) => run(
+            SimulatedTransport::new(transport, conditions, !seed),
+            name,
+            seed,
+        ),
+        None => run(transport, name, seed),
+    })
+}
+
+fn run<T: Transport>(
+    transport: T,
+    name: String,
+    seed: u64,
+) -> Result<BotReport, Box<dyn Error + Send + Sync>> {
+    // Replaced by the server's snapshot once we're in
+    let mut world = simulation::init_world(seed)?;
+    let mut dispatcher = simulation::init_dispatcher();
+    world.insert(NetClient::new(&name));
+    world.insert(NetStats::new());
+    world.write_resource::<TickCoordinator>().hold();
+    let mut connection = Connection::new(transport);
+
+    // Decides what to do, kept apart from the simulation's own randomness
+    let mut rng = Rng::new(seed);
+
+    let mut report = BotReport {
+        name,
+        ticks: 0,
+        desyncs: 0,
+        rejections: 0,
+        round_trip: 0.,
+        longest_stall: 0.,
+        caught_up: None,
+        net: NetStats::new(),
+    };
+    let mut last_tick: Option<Instant> = None;
+    let mut ready = false;
+
+    // In state sync, the tick of the latest state we were shown
+    let mut followed: Option<usize> = None;
+
+    loop {
+        connection.flush(&world);
+        connection.receive(&mut world)?;
+        {
+            let mut client = world.write_resource::<NetClient>();
+            if let Some(reason) = &client.refused {
+                return Err(format!("Refused by the server: {reason:?}").into());
+            }
+
+            // Always ready to play, once we're in
+            if client.player.is_some() && !ready {
+                client.set_ready(true);
+                ready = true;
+            }
+        }
+        if connection.state() == ConnectionState::Closed {
+            // The server hung up
+            report.round_trip = world.read_resource::<NetClient>().clock.round_trip;
+            report.net = (*world.read_resource::<NetStats>()).clone();
+            return Ok(report);
+        }
+
+        // Run everything the server has let us
+        let mut idle = true;
+        loop {
+            let tick = {
+                let tc = world.read_resource::<TickCoordinator>();
+                if tc.current_tick >= tc.max_tick {
+                    break;
+                }
+                tc.current_tick
+            };
+
+            simulation::tick(&mut dispatcher, &mut world);
+            world.write_resource::<NetClient>().tick_completed(&world);
+            report.ticks += 1;
+            idle = false;
+
+            act(&world, &mut rng, tick);
+        }
+
+        // Or follow the server's states instead, acting as often as we would have
+        if world.read_resource::<NetClient>().sync == SyncMode::State {
+            let tick = world.read_resource::<TickCoordinator>().current_tick;
+            let from = *followed.get_or_insert(tick);
+            for tick in from..tick {
+                report.ticks += 1;
+                idle = false;
+                act(&world, &mut rng, tick);
+            }
+            // The state shown can step back when the clock is corrected
+            followed = Some(tick.max(from));
+        }
+
+        for event in world.write_resource::<EventBus>().take() {
+            match event {
+                EngineEvent::Desync { .. } => report.desyncs += 1,
+                EngineEvent::ActionRejected { .. } => report.rejections += 1,
+                EngineEvent::CaughtUp { tick } => report.caught_up = Some(tick),
+                _ => {}
+            }
+        }
+
+        if idle {
+            thread::sleep(IDLE_WAIT);
+        } else {
+            if let Some(last_tick) = last_tick {
+                let stall = last_tick.elapsed().as_secs_f64() * 1000.;
+                report.longest_stall = report.longest_stall.max(stall);
+            }
+            last_tick = Some(Instant::now());
+        }
+    }
+}
+
+/// The client end of a connection sending one message per line
+pub struct TcpTransport {
+    stream: TcpStream,
+    incoming: Receiver<String>,
+    closed: bool,
+}
+
+impl TcpTransport {
+    pub fn connect(addr: SocketAddr) -> std::io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+
+        let reader = BufReader::new(stream.try_clone()?);
+        let (lines, incoming) = mpsc::channel();
+        thread::spawn(move || {
+            for line in reader.lines().map_while(Result::ok) {
+                if lines.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            stream,
+            incoming,
+            closed: false,
+        })
+    }
+}
+
+impl Transport for TcpTransport {
+    fn send(&mut self, message: &str) {
+        if writeln!(self.stream, "{message}").is_err() {
+            self.closed = true;
+        }
+    }
+
+    fn receive(&mut self) -> Option<String> {
+        match self.incoming.try_recv() {
+            Ok(line) => Some(line),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.closed = true;
+                None
+            }
+        }
+    }
+
+    fn state(&self) -> ConnectionState {
+        if self.closed {
+            ConnectionState::Closed
+        } else {
+            ConnectionState::Open
+        }
+    }
+
+    fn close(&mut self) {
+        // Also ends the reader thread, as its half of the stream closes too
+        let _ = self.stream.shutdown(Shutdown::Both);
+        self.closed = true;
+    }
+}
+
+/// Maybe does something on `tick`
+fn act(world: &World, rng: &mut Rng, tick: usize) {
+    if let Some(action) = random_action(rng) {
+        let target = world.read_resource::<ActionDelay>().target_tick(tick);
+        world
+            .write_resource::<NetClient>()
+            .send_actions(target, vec![action]);
+    }
+}
+
+/// Acts about twice a second, mostly moving about
+fn random_action(rng: &mut Rng) -> Option<Action> {
+    if rng.below(30) != 0 {
+        return None;
+    }
+
+    let dir = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ][rng.below(4) as usize];
+
+    Some(match rng.below(6) {
+        0 | 1 => Action::StartMoving { dir },
+        2 => Action::StopMoving { dir },
+        3 => Action::Jump,
+        4 => Action::Fire,
+        _ => {
+            let stick = FixedVec2::from_angle(rng.angle()) * rng.unit();
+            Action::MoveAnalog {
+                x: stick.x,
+                y: stick.y,
+            }
+        }
+    })
+}
+
+/// Where loopback clients should connect to reach a server listening on `addr`
+pub fn loopback_addr(addr: SocketAddr) -> SocketAddr {
+    let mut addr = addr;
+    if addr.ip().is_unspecified() {
+        addr.set_ip(if addr.is_ipv4() {
+            [127, 0, 0, 1].into()
+        } else {
+            std::net::Ipv6Addr::LOCALHOST.into()
+        });
+    }
+
+    addr
+}
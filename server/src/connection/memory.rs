@@ -0,0 +1,33 @@
+use std::thread;
+
+use canvas_test::net::MemorySocket;
+
+use super::{Hub, NetEvent};
+
+/// Connects a client in the same process, returning its end of the connection
+pub fn attach_memory(hub: &Hub, name: &str) -> MemorySocket {
+    let (client, server) = MemorySocket::pair();
+    let (to_client, from_client) = server.into_channels();
+    let (id, outgoing, events) = hub.add(format!("{name} (in memory)"));
+
+    thread::spawn(move || {
+        for message in outgoing {
+            if to_client.send(message).is_err() {
+                break;
+            }
+        }
+        // Dropping our end hangs up on the client
+    });
+
+    thread::spawn(move || {
+        for message in from_client {
+            if events.send(NetEvent::Message(id, message)).is_err() {
+                return;
+            }
+        }
+
+        let _ = events.send(NetEvent::Closed(id));
+    });
+
+    client
+}
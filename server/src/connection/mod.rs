@@ -0,0 +1,117 @@
+//! Client connections, each carrying one JSON message at a time: one per line
+//! over TCP, one per text frame over WebSocket, or as they are in memory.
+//!
+//! Each connection gets threads of its own to read and write, and everything
+//! read ends up on one channel for the session loop.
+
+mod memory;
+mod tcp;
+mod websocket;
+
+pub use memory::attach_memory;
+pub use tcp::listen_tcp;
+pub use websocket::listen_websocket;
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    time::Duration,
+};
+
+pub type ConnectionId = u32;
+
+/// A slow client gets dropped rather than holding up everyone else
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The longest message a client may send, well over anything it has reason to
+const MAX_MESSAGE_BYTES: usize = 64 * 1024;
+
+/// Something that happened on one of the connections
+pub enum NetEvent {
+    /// A new connection, and who it's from
+    Connected(ConnectionId, String),
+    Message(ConnectionId, String),
+    Closed(ConnectionId),
+}
+
+/// Admits connections from any transport, handed to each listener
+#[derive(Clone)]
+pub struct Hub {
+    next_id: Arc<AtomicU32>,
+    events: Sender<NetEvent>,
+    new_connections: Sender<(ConnectionId, Sender<String>)>,
+}
+
+/// Where to send messages for every open connection.
+/// Connections are handed over before their `Connected` event, so calling
+/// `update` before handling events means every one they mention is here.
+pub struct Connections {
+    outgoing: HashMap<ConnectionId, Sender<String>>,
+    new_connections: Receiver<(ConnectionId, Sender<String>)>,
+}
+
+/// A hub to attach connections to, the session loop's side of them,
+/// and where to hear what happens on them
+pub fn hub() -> (Hub, Connections, Receiver<NetEvent>) {
+    let (events, event_receiver) = mpsc::channel();
+    let (new_connections, new_connection_receiver) = mpsc::channel();
+
+    (
+        Hub {
+            next_id: Arc::new(AtomicU32::new(0)),
+            events,
+            new_connections,
+        },
+        Connections {
+            outgoing: HashMap::new(),
+            new_connections: new_connection_receiver,
+        },
+        event_receiver,
+    )
+}
+
+impl Hub {
+    /// Adds a connection from `peer`. Returns its id, the messages to write to it,
+    /// which hang up once it should be closed, and where to report what's read.
+    /// Whatever reads it must report it `Closed` when it stops.
+    fn add(&self, peer: String) -> (ConnectionId, Receiver<String>, Sender<NetEvent>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (outgoing, outgoing_receiver) = mpsc::channel();
+
+        let _ = self.new_connections.send((id, outgoing));
+        let _ = self.events.send(NetEvent::Connected(id, peer));
+
+        (id, outgoing_receiver, self.events.clone())
+    }
+}
+
+impl Connections {
+    /// Picks up connections made since the last call
+    pub fn update(&mut self) {
+        self.outgoing.extend(self.new_connections.try_iter());
+    }
+
+    /// Queues a message to be written, closing the connection if it already stopped writing
+    pub fn send(&mut self, id: ConnectionId, message: &str) {
+        let Some(outgoing) = self.outgoing.get(&id) else {
+            return;
+        };
+
+        if outgoing.send(message.to_owned()).is_err() {
+            self.close(id);
+        }
+    }
+
+    pub fn close_all(&mut self) {
+        self.outgoing.clear();
+    }
+
+    /// Hangs up once everything sent so far is written, the connection is then reported closed
+    pub fn close(&mut self, id: ConnectionId) {
+        self.outgoing.remove(&id);
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    thread,
+};
+
+use super::{Hub, NetEvent, MAX_MESSAGE_BYTES, WRITE_TIMEOUT};
+
+/// Accepts connections sending one message per line on `addr`, in the background
+pub fn listen_tcp(addr: &str, hub: Hub) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+
+            if let Err(e) = accept(stream, &hub) {
+                eprintln!("Could not accept a connection: {e}");
+            }
+        }
+    });
+
+    Ok(local_addr)
+}
+
+fn accept(stream: TcpStream, hub: &Hub) -> io::Result<()> {
+    let peer = stream.peer_addr()?;
+    stream.set_nodelay(true)?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let (id, outgoing, events) = hub.add(peer.to_string());
+
+    thread::spawn(move || {
+        for message in outgoing {
+            if writeln!(&stream, "{message}").is_err() {
+                break;
+            }
+        }
+
+        // Wakes the reader, which reports the connection closed
+        let _ = stream.shutdown(Shutdown::Both);
+    });
+
+    thread::spawn(move || {
+        while let Ok(Some(line)) = read_line(&mut reader) {
+            if events.send(NetEvent::Message(id, line)).is_err() {
+                return;
+            }
+        }
+
+        let _ = events.send(NetEvent::Closed(id));
+    });
+
+    Ok(())
+}
+
+/// The next line without its line ending, or none at the end. A line longer
+/// than a message can be is an error, rather than read on until memory runs out.
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    reader
+        .take(MAX_MESSAGE_BYTES as u64 + 1)
+        .read_until(b'\n', &mut line)?;
+
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+    }
+    if line.len() > MAX_MESSAGE_BYTES {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Line too long"));
+    }
+
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn reads_lines_up_to_the_limit() {
+        let long = "x".repeat(MAX_MESSAGE_BYTES);
+        let mut reader = Cursor::new(format!("one\r\ntwo\n{long}\nlast"));
+
+        assert_eq!(read_line(&mut reader).unwrap().as_deref(), Some("one"));
+        assert_eq!(read_line(&mut reader).unwrap().as_deref(), Some("two"));
+        assert_eq!(read_line(&mut reader).unwrap(), Some(long));
+        assert_eq!(read_line(&mut reader).unwrap().as_deref(), Some("last"));
+        assert_eq!(read_line(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn too_long_a_line_is_an_error() {
+        let mut reader = Cursor::new("x".repeat(MAX_MESSAGE_BYTES + 1) + "\nnext\n");
+        assert!(read_line(&mut reader).is_err());
+
+        let mut endless = io::repeat(b'x');
+        let mut reader = BufReader::new(&mut endless);
+        assert!(read_line(&mut reader).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    error::Error,
+    io::{self, ErrorKind},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::mpsc::{Receiver, Sender, TryRecvError},
+    thread,
+    time::Duration,
+};
+
+use tungstenite::{protocol::WebSocketConfig, Message, WebSocket};
+
+use super::{ConnectionId, Hub, NetEvent, MAX_MESSAGE_BYTES, WRITE_TIMEOUT};
+
+/// A socket can't be read and written at once, so reads give up this often to write
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+/// Accepts browser clients on `addr`, each sending one message per text frame,
+/// in the background
+pub fn listen_websocket(addr: &str, hub: Hub) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+
+            // The handshake waits on the client, so it happens on the connection's own thread
+            let hub = hub.clone();
+            thread::spawn(move || {
+                if let Err(e) = accept(stream, &hub) {
+                    eprintln!("Could not accept a WebSocket connection: {e}");
+                }
+            });
+        }
+    });
+
+    Ok(local_addr)
+}
+
+fn accept(stream: TcpStream, hub: &Hub) -> Result<(), Box<dyn Error>> {
+    let peer = stream.peer_addr()?;
+    stream.set_nodelay(true)?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+
+    let config = WebSocketConfig {
+        max_message_size: Some(MAX_MESSAGE_BYTES),
+        max_frame_size: Some(MAX_MESSAGE_BYTES),
+        ..WebSocketConfig::default()
+    };
+    let mut socket = tungstenite::accept_with_config(stream, Some(config))?;
+    socket.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+
+    let (id, outgoing, events) = hub.add(format!("{peer} (WebSocket)"));
+    serve(&mut socket, id, &outgoing, &events);
+    let _ = events.send(NetEvent::Closed(id));
+
+    Ok(())
+}
+
+/// Passes messages both ways until either side hangs up
+fn serve(
+    socket: &mut WebSocket<TcpStream>,
+    id: ConnectionId,
+    outgoing: &Receiver<String>,
+    events: &Sender<NetEvent>,
+) {
+    loop {
+        loop {
+            match outgoing.try_recv() {
+                Ok(message) => {
+                    if socket.send(Message::Text(message)).is_err() {
+                        return;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    let _ = socket.close(None);
+                    let _ = socket.flush();
+                    return;
+                }
+            }
+        }
+
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                if events.send(NetEvent::Message(id, text)).is_err() {
+                    return;
+                }
+            }
+            // Pings are answered as part of reading, and closing is reported as an error next time
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(_) => return,
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    time::Instant,
+};
+
+/// Timestamped record of what happened in a session, on stderr and optionally in a file
+pub struct SessionLog {
+    start: Instant,
+    file: Option<File>,
+}
+
+impl SessionLog {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            file: None,
+        }
+    }
+
+    /// Also appends everything to the file at `path`
+    pub fn with_file(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            file: Some(File::options().create(true).append(true).open(path)?),
+            ..Self::new()
+        })
+    }
+
+    pub fn log(&mut self, tick: usize, message: &str) {
+        let line = format!(
+            "[{:>9.3}s tick {tick:>6}] {message}",
+            self.start.elapsed().as_secs_f64()
+        );
+        eprintln!("{line}");
+
+        if let Some(file) = &mut self.file {
+            if writeln!(file, "{line}").is_err() {
+                eprintln!("Could not write to the log file, only logging to stderr from now on");
+                self.file = None;
+            }
+        }
+    }
+}
+
+impl Default for SessionLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,536 @@
+//! Dedicated server hosting an authoritative lockstep session.
+//!
+//! Clients connect over TCP, sending and receiving one JSON message per line,
+//! or with `--ws` from a browser over WebSocket, one message per text frame.
+//! With `--loopback` the server also starts that many clients of its own, which
+//! play at random, and with `--ticks` it stops after that many ticks, failing
+//! if anyone desynced. Together they make an end to end test:
+//!
+//! ```sh
+//! cargo run -p canvas-test-server -- --loopback 4 --ticks 1200
+//! ```
+//!
+//! Loopback clients can also be given a bad network to cope with:
+//!
+//! ```sh
+//! cargo run -p canvas-test-server -- --loopback 4 --ticks 1200 --latency 60 --jitter 20 --loss 0.05
+//! ```
+//!
+//! With `--late` one more joins partway through, and has to catch up, and with
+//! `--sync state` clients follow states sent by the server instead of simulating.
+
+mod bot;
+mod connection;
+mod log;
+mod session;
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    path::PathBuf,
+    process::ExitCode,
+    str::FromStr,
+    sync::mpsc::RecvTimeoutError,
+    time::{Duration, Instant},
+};
+
+use canvas_test::{
+    action::PlayerId,
+    net::{ClientMessage, Delay, LinkConditions, LobbySettings, ServerMessage, SyncMode},
+    resources::{TICKS_PER_SECOND, TICK_MS},
+};
+
+use bot::{BotReport, TcpTransport};
+use connection::{ConnectionId, Connections, NetEvent};
+use log::SessionLog;
+use session::{Recipient, Session};
+
+const USAGE: &str = "\
+Usage: canvas-test-server [options]
+
+Options:
+    --bind ADDR      Address to listen on, 0.0.0.0:7878 to accept LAN clients [127.0.0.1:7878]
+    --ws ADDR        Also accept WebSocket clients on ADDR, e.g. 127.0.0.1:7879
+    --seed N         Seed for the simulation [24301]
+    --log FILE       Also append the session log to FILE
+    --loopback N     Start N clients that play at random [0]
+    --transport T    How loopback clients connect, tcp or memory [tcp]
+    --late N         Start one more loopback client N ticks into the game
+    --ticks N        Stop after N ticks of play, failing if anyone desynced
+    --players N      Players needed in the lobby before the game can start [1]
+    --countdown S    Seconds from everyone being ready to the game starting [3]
+    --sync MODE      How clients are kept up to date, lockstep or state [lockstep]
+
+Network conditions for loopback clients, the same both ways:
+    --latency MS     Mean delay [0]
+    --jitter MS      Standard deviation of the delay [0]
+    --loss P         Chance of a message being lost, from 0 to 1 [0]
+    --duplicate P    Chance of a message arriving twice [0]
+    --reorder P      Chance of a message being overtaken by later ones [0]";
+
+struct Options {
+    bind: String,
+    ws: Option<String>,
+    seed: u64,
+    log: Option<PathBuf>,
+    loopback: usize,
+    transport: LoopbackTransport,
+    late: Option<usize>,
+    ticks: Option<usize>,
+    lobby: LobbySettings,
+    sync: SyncMode,
+    link: LinkConditions,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1:7878".to_owned(),
+            ws: None,
+            // The same default the browser uses
+            seed: 0x5eed,
+            log: None,
+            loopback: 0,
+            transport: LoopbackTransport::Tcp,
+            late: None,
+            ticks: None,
+            lobby: LobbySettings::default(),
+            sync: SyncMode::Lockstep,
+            link: LinkConditions::perfect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum LoopbackTransport {
+    Tcp,
+    Memory,
+}
+
+/// How a session went
+struct Outcome {
+    /// How many mismatched state hashes clients reported to the server
+    desyncs: usize,
+
+    /// The loopback clients that played until the end, and how many didn't
+    reports: Vec<BotReport>,
+    failures: usize,
+}
+
+impl Outcome {
+    /// Whether nobody desynced, and every loopback client played until the end
+    fn in_sync(&self) -> bool {
+        self.desyncs == 0
+            && self.failures == 0
+            && self.reports.iter().all(|report| report.desyncs == 0)
+    }
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options::default();
+    let (mut latency, mut jitter) = (0., 0.);
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+
+        match arg.as_str() {
+            "--bind" => options.bind = value()?,
+            "--ws" => options.ws = Some(value()?),
+            "--seed" => options.seed = number(&arg, value()?)?,
+            "--log" => options.log = Some(value()?.into()),
+            "--loopback" => options.loopback = number(&arg, value()?)?,
+            "--transport" => {
+                options.transport = match value()?.as_str() {
+                    "tcp" => LoopbackTransport::Tcp,
+                    "memory" => LoopbackTransport::Memory,
+                    other => return Err(format!("Unknown transport {other}")),
+                }
+            }
+            "--late" => options.late = Some(number(&arg, value()?)?),
+            "--ticks" => options.ticks = Some(number(&arg, value()?)?),
+            "--players" => options.lobby.min_players = number(&arg, value()?)?,
+            "--countdown" => {
+                let seconds: f64 = number(&arg, value()?)?;
+                options.lobby.countdown_ticks =
+                    (seconds * TICKS_PER_SECOND as f64).round() as usize;
+            }
+            "--sync" => {
+                options.sync = match value()?.as_str() {
+                    "lockstep" => SyncMode::Lockstep,
+                    "state" => SyncMode::State,
+                    other => return Err(format!("Unknown sync mode {other}")),
+                }
+            }
+            "--latency" => latency = number(&arg, value()?)?,
+            "--jitter" => jitter = number(&arg, value()?)?,
+            "--loss" => options.link.loss = number(&arg, value()?)?,
+            "--duplicate" => options.link.duplication = number(&arg, value()?)?,
+            "--reorder" => options.link.reordering = number(&arg, value()?)?,
+            "--help" | "-h" => return Err(String::new()),
+            other => return Err(format!("Unknown option {other}")),
+        }
+    }
+
+    options.link.delay = if jitter > 0. {
+        Delay::Normal {
+            mean: latency,
+            deviation: jitter,
+        }
+    } else {
+        Delay::Fixed(latency)
+    };
+
+    Ok(options)
+}
+
+fn number<T: FromStr>(arg: &str, value: String) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{arg} expects a number, got {value}"))
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(options) {
+        Ok(outcome) if outcome.in_sync() => ExitCode::SUCCESS,
+        Ok(_) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Hosts the session until it is told to stop
+fn run(options: Options) -> Result<Outcome, Box<dyn Error>> {
+    let log = match &options.log {
+        Some(path) => SessionLog::with_file(path)?,
+        None => SessionLog::new(),
+    };
+    let mut session = Session::new(options.seed, options.sync, options.lobby.clone(), log)?;
+
+    let (hub, mut connections, events) = connection::hub();
+    let addr = connection::listen_tcp(&options.bind, hub.clone())?;
+    session.log(&format!("Listening on {addr}"));
+    if let Some(ws) = &options.ws {
+        let ws = connection::listen_websocket(ws, hub.clone())?;
+        session.log(&format!("Listening for WebSocket clients on {ws}"));
+    }
+
+    let link = (options.link != LinkConditions::perfect()).then_some(options.link);
+    let spawn_bot = |i: usize| -> std::io::Result<_> {
+        let name = format!("bot {i}");
+        let seed = options.seed.wrapping_add(i as u64 + 1);
+        Ok(match options.transport {
+            LoopbackTransport::Tcp => {
+                let transport = TcpTransport::connect(bot::loopback_addr(addr))?;
+                bot::spawn(transport, link, name, seed)
+            }
+            LoopbackTransport::Memory => {
+                let transport = connection::attach_memory(&hub, &name);
+                bot::spawn(transport, link, name, seed)
+            }
+        })
+    };
+    let mut bots = Vec::new();
+    for i in 0..options.loopback {
+        bots.push(spawn_bot(i)?);
+    }
+    let mut late = options.late;
+
+    let tick_length = Duration::from_secs_f64(TICK_MS / 1000.);
+    let mut next_tick = Instant::now() + tick_length;
+    let mut players = HashMap::new();
+
+    loop {
+        // Handle whatever arrives until the next tick is due
+        loop {
+            let timeout = next_tick.saturating_duration_since(Instant::now());
+            match events.recv_timeout(timeout) {
+                Ok(event) => {
+                    connections.update();
+                    handle(&mut session, &mut connections, &mut players, event);
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return Err("Stopped listening".into()),
+            }
+
+            if Instant::now() >= next_tick {
+                break;
+            }
+        }
+
+        session.tick();
+        flush(&mut session, &mut connections, &players);
+
+        // After a stall, carry on from now rather than rushing to catch up
+        next_tick = (next_tick + tick_length).max(Instant::now());
+
+        if late.is_some_and(|tick| session.current_tick() >= tick) {
+            late = None;
+            session.log("Starting a loopback client late");
+            bots.push(spawn_bot(options.loopback)?);
+        }
+
+        if options
+            .ticks
+            .is_some_and(|ticks| session.current_tick() >= ticks)
+        {
+            break;
+        }
+    }
+
+    let mut reports = Vec::new();
+    let mut failures = 0;
+    session.log(&format!(
+        "Stopping with {} players connected and {} desyncs reported",
+        session.player_count(),
+        session.desyncs
+    ));
+
+    // Loopback clients stop once they are hung up on
+    connections.close_all();
+    for bot in bots {
+        match bot.join() {
+            Ok(Ok(report)) => {
+                let caught_up = report
+                    .caught_up
+                    .map(|tick| format!(", having caught up on tick {tick}"))
+                    .unwrap_or_default();
+                session.log(&format!(
+                    "{} ran {} ticks, with {} desyncs and {} rejected actions, \
+                     {:.1}ms from us and stalling for {:.1}ms at most{caught_up}, \
+                     losing {:.0}% of pings with {:.1}KB in and {:.1}KB out",
+                    report.name,
+                    report.ticks,
+                    report.desyncs,
+                    report.rejections,
+                    report.round_trip,
+                    report.longest_stall,
+                    report.net.loss * 100.,
+                    report.net.bytes_in as f64 / 1024.,
+                    report.net.bytes_out as f64 / 1024.
+                ));
+                reports.push(report);
+            }
+            Ok(Err(e)) => {
+                failures += 1;
+                session.log(&format!("A loopback client failed: {e}"));
+            }
+            Err(_) => {
+                failures += 1;
+                session.log("A loopback client panicked");
+            }
+        }
+    }
+
+    Ok(Outcome {
+        desyncs: session.desyncs,
+        reports,
+        failures,
+    })
+}
+
+fn handle(
+    session: &mut Session,
+    connections: &mut Connections,
+    players: &mut HashMap<ConnectionId, PlayerId>,
+    event: NetEvent,
+) {
+    match event {
+        NetEvent::Connected(id, peer) => session.log(&format!("Connection {id} from {peer}")),
+
+        NetEvent::Message(id, text) => {
+            let message = match ClientMessage::decode(&text) {
+                Ok(message) => message,
+                Err(e) => {
+                    session.log(&format!("Dropping connection {id}: {e}"));
+                    connections.close(id);
+                    return;
+                }
+            };
+
+            match (players.get(&id), message) {
+                (Some(&player), message) => session.receive(player, message),
+                (
+                    None,
+                    ClientMessage::Join {
+                        protocol,
+                        engine,
+                        name,
+                    },
+                ) => match session.join(protocol, &engine, &name) {
+                    Ok(player) => {
+                        players.insert(id, player);
+                    }
+                    Err(reason) => {
+                        if let Ok(text) = ServerMessage::Refused(reason).encode() {
+                            connections.send(id, &text);
+                        }
+                        connections.close(id);
+                    }
+                },
+                (None, _) => {
+                    session.log(&format!("Dropping connection {id}: it never joined"));
+                    connections.close(id);
+                }
+            }
+
+            flush(session, connections, players);
+        }
+
+        NetEvent::Closed(id) => {
+            connections.close(id);
+            if let Some(player) = players.remove(&id) {
+                session.leave(player);
+            }
+            session.log(&format!("Connection {id} closed"));
+        }
+    }
+}
+
+/// Sends everything the session has queued up
+fn flush(
+    session: &mut Session,
+    connections: &mut Connections,
+    players: &HashMap<ConnectionId, PlayerId>,
+) {
+    for (recipient, message) in session.take_outbox() {
+        let text = match message.encode() {
+            Ok(text) => text,
+            Err(e) => {
+                session.log(&format!("Could not send a message: {e}"));
+                continue;
+            }
+        };
+        for (&id, &player) in players {
+            if recipient == Recipient::All || recipient == Recipient::Player(player) {
+                connections.send(id, &text);
+
+                // Hangs up once it's been told why
+                if let ServerMessage::Refused(_) = message {
+                    connections.close(id);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICKS: usize = 300;
+
+    /// Three loopback clients in memory, starting straight away
+    fn loopback() -> Options {
+        Options {
+            bind: "127.0.0.1:0".to_owned(),
+            loopback: 3,
+            transport: LoopbackTransport::Memory,
+            ticks: Some(TICKS),
+            lobby: LobbySettings {
+                countdown_ticks: 0,
+                ..LobbySettings::default()
+            },
+            ..Options::default()
+        }
+    }
+
+    /// Plays a session through, checking nobody desynced
+    fn play(options: Options) -> Vec<BotReport> {
+        let outcome = run(options).expect("The session should run");
+
+        assert_eq!(outcome.desyncs, 0);
+        assert_eq!(outcome.failures, 0);
+        for report in &outcome.reports {
+            assert_eq!(report.desyncs, 0, "{} desynced", report.name);
+        }
+
+        outcome.reports
+    }
+
+    #[test]
+    fn lockstep_clients_stay_in_sync() {
+        let reports = play(loopback());
+
+        assert_eq!(reports.len(), 3);
+        for report in reports {
+            assert!(
+                report.ticks >= TICKS * 2 / 3,
+                "{} ran {} ticks",
+                report.name,
+                report.ticks
+            );
+        }
+    }
+
+    #[test]
+    fn late_client_catches_up() {
+        let reports = play(Options {
+            late: Some(TICKS / 2),
+            ticks: Some(TICKS + TICKS / 2),
+            ..loopback()
+        });
+
+        assert_eq!(reports.len(), 4);
+        let late = reports.last().unwrap();
+        assert!(late.caught_up.is_some(), "{} never caught up", late.name);
+        assert!(late.ticks > 0);
+    }
+
+    #[test]
+    fn lossy_link_stays_in_sync_and_recovers_from_stalls() {
+        let reports = play(Options {
+            link: LinkConditions {
+                delay: Delay::Normal {
+                    mean: 40.,
+                    deviation: 10.,
+                },
+                loss: 0.05,
+                duplication: 0.05,
+                reordering: 0.05,
+                ..LinkConditions::perfect()
+            },
+            ..loopback()
+        });
+
+        // Lost bundles hold clients up at their horizon until they are sent
+        // again, after which they carry on and make up for it
+        assert_eq!(reports.len(), 3);
+        for report in reports {
+            assert!(
+                report.ticks >= TICKS * 2 / 3,
+                "{} ran {} ticks",
+                report.name,
+                report.ticks
+            );
+        }
+    }
+
+    #[test]
+    fn state_sync_clients_follow_the_server() {
+        let reports = play(Options {
+            sync: SyncMode::State,
+            ..loopback()
+        });
+
+        assert_eq!(reports.len(), 3);
+        for report in reports {
+            assert!(
+                report.ticks >= TICKS * 2 / 3,
+                "{} followed {} ticks",
+                report.name,
+                report.ticks
+            );
+        }
+    }
+}
@@ -0,0 +1,618 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::Instant,
+};
+
+use canvas_test::{
+    action::{Action, PlayerAction, PlayerId},
+    components::{
+        control::Owner,
+        physics::{MovementReceiver, Position},
+    },
+    error::EngineError,
+    net::{
+        ClientMessage, Interest, Lobby, LobbySettings, RefuseReason, ServerMessage, SpatialIndex,
+        SyncMode, TickBundle, WorldState, STATE_INTERVAL,
+    },
+    resources::{
+        controls_entity, ActionValidator, Errors, EventBus, Prefab, RejectReason, TickCoordinator,
+    },
+    simulation,
+    snapshot::Snapshot,
+    state_hash::state_hash,
+};
+use specs::prelude::*;
+
+use crate::log::SessionLog;
+
+/// How many ticks of state hashes and bundles are kept, to check clients against and resend
+const HISTORY: usize = 600;
+
+/// Ticks between snapshots for players joining late to start from, well within the history
+/// so every bundle since the latest is still there to catch up with
+const CHECKPOINT_INTERVAL: usize = 300;
+
+/// The most bundles resent for one request, the client asks again if it needs more
+const MAX_RESEND: usize = 120;
+
+/// How many of each player's latest action sequence numbers are remembered to spot copies
+const SEEN_ACTIONS: usize = 64;
+
+/// The prefab every player gets to control
+const AVATAR_PREFAB: &str = "emitter";
+
+/// Who a message is for
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Recipient {
+    All,
+    Player(PlayerId),
+}
+
+/// The authoritative side of a lockstep session: validates what players send,
+/// hands out one bundle of actions per tick, and runs the same simulation
+/// headless to check everyone's state hashes against.
+///
+/// Players wait in the lobby until everyone is ready, and the simulation
+/// only starts running once the countdown after that is over.
+///
+/// In state sync, players are sent the state every few ticks instead of bundles,
+/// each relative to the latest state they acknowledged.
+pub struct Session {
+    world: World,
+    dispatcher: Dispatcher<'static, 'static>,
+    sync: SyncMode,
+
+    /// Who is playing, and whether the game started
+    lobby: Lobby,
+
+    /// Sequence numbers of each player's latest actions messages, oldest first
+    seen_actions: BTreeMap<PlayerId, VecDeque<u32>>,
+
+    /// Our state hash at the start of each recent tick
+    hashes: BTreeMap<usize, u64>,
+
+    /// The actions of each recent tick, as sent out
+    bundles: BTreeMap<usize, Vec<PlayerAction>>,
+
+    /// The latest snapshot new players start from, rather than taking one for each
+    checkpoint: Snapshot,
+
+    /// In state sync, what each player gets sent, and the latest state they acknowledged
+    interests: BTreeMap<PlayerId, Interest>,
+    acked: BTreeMap<PlayerId, usize>,
+
+    outbox: Vec<(Recipient, ServerMessage)>,
+    log: SessionLog,
+
+    /// What the clock clients sync to counts from
+    started: Instant,
+
+    /// How many mismatched state hashes clients have reported
+    pub desyncs: usize,
+}
+
+impl Session {
+    pub fn new(
+        seed: u64,
+        sync: SyncMode,
+        lobby: LobbySettings,
+        log: SessionLog,
+    ) -> Result<Self, EngineError> {
+        let world = simulation::init_world(seed)?;
+
+        let mut hashes = BTreeMap::new();
+        hashes.insert(0, state_hash(&world));
+        let checkpoint = Snapshot::take(&world);
+
+        Ok(Self {
+            world,
+            dispatcher: simulation::init_dispatcher(),
+            sync,
+            lobby: Lobby::new(lobby),
+            seen_actions: BTreeMap::new(),
+            hashes,
+            bundles: BTreeMap::new(),
+            checkpoint,
+            interests: BTreeMap::new(),
+            acked: BTreeMap::new(),
+            outbox: Vec::new(),
+            log,
+            started: Instant::now(),
+            desyncs: 0,
+        })
+    }
+
+    pub fn current_tick(&self) -> usize {
+        self.world.read_resource::<TickCoordinator>().current_tick
+    }
+
+    pub fn player_count(&self) -> usize {
+        self.lobby.player_count()
+    }
+
+    /// Admits a new player if their handshake checks out, who starts from the
+    /// latest checkpoint. Once the game started they join straight in.
+    pub fn join(
+        &mut self,
+        protocol: u32,
+        engine: &str,
+        name: &str,
+    ) -> Result<PlayerId, RefuseReason> {
+        let player = match self.lobby.admit(protocol, engine, name) {
+            Ok(player) => player,
+            Err(reason) => {
+                self.log(&format!("Refused {name:?} running {engine}: {reason:?}"));
+                return Err(reason);
+            }
+        };
+        self.seen_actions.insert(player, VecDeque::new());
+
+        if self.lobby.is_playing() {
+            self.spawn_avatar(player);
+        }
+
+        self.welcome(player);
+        self.log(&format!("Player {player} ({name}) joined"));
+
+        Ok(player)
+    }
+
+    /// Avatars are spawned through the action queue, so every peer sees them appear
+    fn spawn_avatar(&mut self, player: PlayerId) {
+        let tick = self.current_tick() + 1;
+        let queued = Prefab::new().with(Owner { player }).and_then(|overrides| {
+            let avatar = Action::Spawn {
+                prefab: AVATAR_PREFAB.to_owned(),
+                overrides,
+            };
+            self.world
+                .write_resource::<TickCoordinator>()
+                .enqueue_action(player, avatar, tick)
+        });
+        if let Err(e) = queued {
+            self.log(&format!(
+                "Could not spawn an avatar for player {player}: {e}"
+            ));
+        }
+    }
+
+    /// Like spawning, through the action queue so every peer sees them go
+    fn despawn_avatar(&mut self, player: PlayerId) {
+        let tick = self.current_tick() + 1;
+        let queued = self
+            .world
+            .write_resource::<TickCoordinator>()
+            .enqueue_action(player, Action::Leave, tick);
+        if let Err(e) = queued {
+            self.log(&format!(
+                "Could not despawn the avatar of player {player}: {e}"
+            ));
+        }
+    }
+
+    /// Sends the latest checkpoint, followed by every bundle since to catch up with.
+    /// In state sync there's nothing to catch up with, and the state follows instead.
+    fn welcome(&mut self, player: PlayerId) {
+        if self.sync == SyncMode::State {
+            let welcome = ServerMessage::Welcome {
+                player,
+                snapshot: Snapshot::take(&self.world),
+                live_tick: self.current_tick(),
+                sync: self.sync,
+            };
+            self.outbox.push((Recipient::Player(player), welcome));
+
+            // Whatever they had before, the next state they get has everything near them
+            self.acked.remove(&player);
+            self.interests.insert(player, Interest::new());
+            return;
+        }
+
+        let welcome = ServerMessage::Welcome {
+            player,
+            snapshot: self.checkpoint.clone(),
+            live_tick: self.current_tick(),
+            sync: self.sync,
+        };
+        let bundles: Vec<_> = self.bundles_from(self.checkpoint.tick).collect();
+
+        self.outbox.push((Recipient::Player(player), welcome));
+        self.outbox.extend(
+            bundles
+                .into_iter()
+                .map(|bundle| (Recipient::Player(player), bundle)),
+        );
+    }
+
+    /// The bundles sent out from `tick` on, as far as we still have them
+    fn bundles_from(&self, tick: usize) -> impl Iterator<Item = ServerMessage> + '_ {
+        self.bundles.range(tick..).map(|(&tick, actions)| {
+            ServerMessage::Bundle(TickBundle {
+                tick,
+                actions: actions.clone(),
+            })
+        })
+    }
+
+    /// Removes a player, whose avatar goes with them. Reconnecting makes them a new
+    /// player, so it would otherwise be left standing there for good.
+    pub fn leave(&mut self, player: PlayerId) {
+        if let Some(name) = self.lobby.name(player).map(str::to_owned) {
+            if self.lobby.is_playing() {
+                self.despawn_avatar(player);
+            }
+
+            self.lobby.remove(player);
+            self.seen_actions.remove(&player);
+            self.acked.remove(&player);
+            self.interests.remove(&player);
+            self.log(&format!("Player {player} ({name}) left"));
+        }
+    }
+
+    /// Handles a message from a player who has joined
+    pub fn receive(&mut self, player: PlayerId, message: ClientMessage) {
+        // Kicked, and not hung up on yet
+        if self.lobby.name(player).is_none() {
+            return;
+        }
+
+        match message {
+            // Our welcome went missing, or some of the bundles after it
+            ClientMessage::Join { .. } => {
+                self.log(&format!("Player {player} asked to join again"));
+                self.welcome(player);
+            }
+
+            ClientMessage::Ready { ready } => self.lobby.set_ready(player, ready),
+
+            ClientMessage::Kick { player: kicked } => {
+                if !self.lobby.may_kick(player, kicked) {
+                    self.log(&format!(
+                        "Player {player} tried to kick player {kicked}, but isn't the host"
+                    ));
+                    return;
+                }
+
+                self.log(&format!("Player {player} kicked player {kicked}"));
+                self.outbox.push((
+                    Recipient::Player(kicked),
+                    ServerMessage::Refused(RefuseReason::Kicked),
+                ));
+                self.leave(kicked);
+            }
+
+            ClientMessage::Actions { seq, tick, actions } => {
+                let Some(seen_actions) = self.seen_actions.get_mut(&player) else {
+                    return;
+                };
+                if seen_actions.contains(&seq) {
+                    return;
+                }
+                if seen_actions.len() == SEEN_ACTIONS {
+                    seen_actions.pop_front();
+                }
+                seen_actions.push_back(seq);
+
+                // Nothing can happen before the game starts
+                if !self.lobby.is_playing() {
+                    self.outbox.push((
+                        Recipient::Player(player),
+                        ServerMessage::Rejected {
+                            tick,
+                            reason: RejectReason::NotPermitted,
+                        },
+                    ));
+                    return;
+                }
+
+                // Clients don't get to choose when their actions happen in state sync,
+                // as they don't simulate and their tick means nothing
+                let tick = match self.sync {
+                    SyncMode::Lockstep => tick,
+                    SyncMode::State => self.current_tick() + 1,
+                };
+                self.receive_actions(player, tick, actions);
+            }
+
+            ClientMessage::Ack { tick } => {
+                let acked = self.acked.entry(player).or_default();
+                *acked = (*acked).max(tick);
+            }
+
+            ClientMessage::StateHash { tick, hash } => match self.hashes.get(&tick) {
+                Some(&expected) if expected != hash => {
+                    self.desyncs += 1;
+                    self.log(&format!(
+                        "Player {player} desynced at tick {tick}: {hash:016x}, expected {expected:016x}"
+                    ));
+                    self.outbox.push((
+                        Recipient::Player(player),
+                        ServerMessage::Desync { tick, expected },
+                    ));
+                }
+                Some(_) => {}
+                None => self.log(&format!(
+                    "Player {player} sent a hash for tick {tick}, which we no longer have"
+                )),
+            },
+
+            ClientMessage::Ping { sent } => {
+                let pong = ServerMessage::Pong {
+                    sent,
+                    time: self.started.elapsed().as_secs_f64() * 1000.,
+                    tick: self.current_tick(),
+                };
+                self.outbox.push((Recipient::Player(player), pong));
+            }
+
+            ClientMessage::Resend { tick } => {
+                let bundles: Vec<_> = self.bundles_from(tick).take(MAX_RESEND).collect();
+
+                if bundles.is_empty() {
+                    self.log(&format!(
+                        "Player {player} asked for bundles from tick {tick}, which we no longer have"
+                    ));
+                }
+                self.outbox.extend(
+                    bundles
+                        .into_iter()
+                        .map(|bundle| (Recipient::Player(player), bundle)),
+                );
+            }
+        }
+    }
+
+    fn receive_actions(&mut self, player: PlayerId, tick: usize, actions: Vec<Action>) {
+        let rejections: Vec<_> = {
+            let (mut validator, mut tc, owners, receivers) = self.world.system_data::<(
+                WriteExpect<ActionValidator>,
+                WriteExpect<TickCoordinator>,
+                ReadStorage<Owner>,
+                ReadStorage<MovementReceiver>,
+            )>();
+            let controls_entity = controls_entity(player, &owners, &receivers);
+
+            actions
+                .into_iter()
+                .filter_map(|action| {
+                    validator
+                        .submit(&mut tc, player, action, tick, controls_entity)
+                        .err()
+                })
+                .collect()
+        };
+
+        for rejection in rejections {
+            self.log(&format!(
+                "Rejected an action from player {player} for tick {tick}: {:?}",
+                rejection.reason
+            ));
+            self.outbox.push((
+                Recipient::Player(player),
+                ServerMessage::Rejected {
+                    tick,
+                    reason: rejection.reason,
+                },
+            ));
+        }
+    }
+
+    /// Runs the lobby's countdown, and once the game started, sends out
+    /// the current tick's actions and simulates it, or in state sync
+    /// simulates it and sends out the state every few ticks
+    pub fn tick(&mut self) {
+        let tick = self.current_tick();
+        if !self.lobby.is_playing() {
+            if !self.lobby.tick(tick) {
+                return;
+            }
+
+            let players: Vec<_> = self.lobby.players().collect();
+            self.log(&format!("Starting on tick {tick} with players {players:?}"));
+            for player in players {
+                self.spawn_avatar(player);
+            }
+        }
+
+        let actions = self
+            .world
+            .read_resource::<TickCoordinator>()
+            .current_tick_actions()
+            .clone();
+
+        if self.sync == SyncMode::Lockstep {
+            self.outbox.push((
+                Recipient::All,
+                ServerMessage::Bundle(TickBundle {
+                    tick,
+                    actions: actions.clone(),
+                }),
+            ));
+            self.bundles.insert(tick, actions);
+            while self.bundles.len() > HISTORY {
+                self.bundles.pop_first();
+            }
+        }
+
+        simulation::tick(&mut self.dispatcher, &mut self.world);
+
+        let errors: Vec<_> = self.world.write_resource::<Errors>().drain().collect();
+        for error in errors {
+            self.log(&format!("Simulation error: {error}"));
+        }
+        // There is no host here to hear about events
+        self.world.write_resource::<EventBus>().take();
+
+        let tick = self.current_tick();
+        if self.sync == SyncMode::State {
+            if tick.is_multiple_of(STATE_INTERVAL) {
+                self.send_states();
+            }
+            return;
+        }
+
+        self.hashes.insert(tick, state_hash(&self.world));
+        while self.hashes.len() > HISTORY {
+            self.hashes.pop_first();
+        }
+
+        if tick.is_multiple_of(CHECKPOINT_INTERVAL) {
+            self.checkpoint = Snapshot::take(&self.world);
+        }
+    }
+
+    /// Sends every player what changed since the latest state they acknowledged,
+    /// or everything if we no longer have that
+    fn send_states(&mut self) {
+        let state = WorldState::capture(&self.world);
+        let index = SpatialIndex::build(&self.world);
+
+        // The entity each player moves, which decides what's near them
+        let focus: BTreeMap<_, _> = {
+            let owners = self.world.read_storage::<Owner>();
+            let receivers = self.world.read_storage::<MovementReceiver>();
+            let positions = self.world.read_storage::<Position>();
+            (&self.world.entities(), &owners, &receivers, &positions)
+                .join()
+                .map(|(entity, owner, _, _)| (owner.player, entity.id()))
+                .collect()
+        };
+
+        for player in self.lobby.players() {
+            let delta = self.interests.entry(player).or_default().next(
+                &state,
+                &index,
+                focus.get(&player).copied(),
+                self.acked.get(&player).copied(),
+            );
+            self.outbox
+                .push((Recipient::Player(player), ServerMessage::State(delta)));
+        }
+    }
+
+    /// Messages to send, oldest first
+    pub fn take_outbox(&mut self) -> Vec<(Recipient, ServerMessage)> {
+        if self.lobby.take_changed() {
+            self.outbox
+                .push((Recipient::All, ServerMessage::Lobby(self.lobby.state())));
+        }
+
+        std::mem::take(&mut self.outbox)
+    }
+
+    pub fn log(&mut self, message: &str) {
+        let tick = self.current_tick();
+        self.log.log(tick, message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use canvas_test::net::{ENGINE_VERSION, PROTOCOL_VERSION};
+
+    use super::*;
+
+    fn avatars(session: &Session, player: PlayerId) -> usize {
+        session
+            .world
+            .read_storage::<Owner>()
+            .join()
+            .filter(|owner| owner.player == player)
+            .count()
+    }
+
+    #[test]
+    fn leaving_takes_the_avatar_along() {
+        let settings = LobbySettings {
+            countdown_ticks: 0,
+            ..LobbySettings::default()
+        };
+        let mut session = Session::new(1, SyncMode::Lockstep, settings, SessionLog::new()).unwrap();
+
+        let players: Vec<_> = ["stays", "goes"]
+            .into_iter()
+            .map(|name| {
+                session
+                    .join(PROTOCOL_VERSION, ENGINE_VERSION, name)
+                    .unwrap()
+            })
+            .collect();
+        for &player in &players {
+            session.receive(player, ClientMessage::Ready { ready: true });
+        }
+        for _ in 0..10 {
+            session.tick();
+        }
+        assert_eq!(avatars(&session, players[0]), 1);
+        assert_eq!(avatars(&session, players[1]), 1);
+
+        session.leave(players[1]);
+        for _ in 0..2 {
+            session.tick();
+        }
+        assert_eq!(avatars(&session, players[0]), 1);
+        assert_eq!(avatars(&session, players[1]), 0);
+
+        // Coming back makes a new player, with an avatar of their own
+        let back = session
+            .join(PROTOCOL_VERSION, ENGINE_VERSION, "goes")
+            .unwrap();
+        for _ in 0..2 {
+            session.tick();
+        }
+        assert_eq!(avatars(&session, back), 1);
+        assert_eq!(avatars(&session, players[1]), 0);
+    }
+
+    #[test]
+    fn clients_cant_make_anyone_leave() {
+        let settings = LobbySettings {
+            countdown_ticks: 0,
+            ..LobbySettings::default()
+        };
+        let mut session = Session::new(1, SyncMode::Lockstep, settings, SessionLog::new()).unwrap();
+        let player = session
+            .join(PROTOCOL_VERSION, ENGINE_VERSION, "player")
+            .unwrap();
+        session.receive(player, ClientMessage::Ready { ready: true });
+        while !session.lobby.is_playing() {
+            session.tick();
+        }
+        session.tick();
+        assert_eq!(avatars(&session, player), 1);
+        session.take_outbox();
+
+        let tick = session.current_tick() + 1;
+        session.receive(
+            player,
+            ClientMessage::Actions {
+                seq: 0,
+                tick,
+                actions: vec![Action::Leave],
+            },
+        );
+
+        let rejected = session.take_outbox().into_iter().any(|(_, message)| {
+            matches!(
+                message,
+                ServerMessage::Rejected {
+                    reason: RejectReason::NotPermitted,
+                    ..
+                }
+            )
+        });
+        assert!(rejected);
+        assert!(session
+            .world
+            .read_resource::<TickCoordinator>()
+            .action_queue
+            .iter()
+            .all(|slot| slot.is_empty()));
+
+        while session.current_tick() <= tick {
+            session.tick();
+        }
+        assert_eq!(avatars(&session, player), 1);
+    }
+}